@@ -0,0 +1,58 @@
+use defmt::*;
+use embassy_rp::Peri;
+use embassy_rp::adc::{Adc, Channel as AdcChannel, Config};
+use embassy_rp::dma::Channel;
+use embassy_rp::gpio::Pull;
+use embassy_rp::peripherals::{ADC, PIN_26};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use visualisation::audio::FFT_LEN;
+use visualisation::{AudioAnalyser, AudioFrame};
+
+use crate::Irqs;
+
+/// Samples per second taken from the microphone
+const SAMPLE_RATE: u32 = 16_000;
+/// Divides the ADC's 48MHz clock down to the sample rate
+const CLOCK_DIVIDER: u16 = (48_000_000 / SAMPLE_RATE - 1) as u16;
+
+/// The latest analysis of the microphone, picked up by the display core each frame
+pub static AUDIO_FRAME: Signal<CriticalSectionRawMutex, AudioFrame> = Signal::new();
+/// A new beat sensitivity for the analyser, sent from the phone to the display core
+pub static BEAT_SENSITIVITY: Signal<CriticalSectionRawMutex, f32> = Signal::new();
+
+/// Sample an analog microphone on GPIO 26 with the ADC and DMA, and analyse each block
+/// of samples into [AUDIO_FRAME]
+pub async fn run_audio(
+    adc: Peri<'static, ADC>,
+    mic: Peri<'static, PIN_26>,
+    mut dma: Peri<'static, impl Channel>,
+) -> ! {
+    let mut adc = Adc::new(adc, Irqs, Config::default());
+    let mut mic = AdcChannel::new_pin(mic, Pull::None);
+    let mut analyser = AudioAnalyser::new(SAMPLE_RATE);
+    let mut samples = [0u16; FFT_LEN];
+
+    loop {
+        if let Err(e) = adc
+            .read_many(&mut mic, &mut samples, CLOCK_DIVIDER, dma.reborrow())
+            .await
+        {
+            warn!("ADC read failed: {}", e);
+            continue;
+        }
+        if let Some(sensitivity) = BEAT_SENSITIVITY.try_take() {
+            analyser.set_sensitivity(sensitivity);
+        }
+        let mut frame = analyser.process(&samples);
+        // the display core might not have picked up the last frame yet, so don't
+        // lose a beat that was in it
+        if let Some(missed) = AUDIO_FRAME.try_take()
+            && missed.beat
+        {
+            frame.beat = true;
+            frame.beat_strength = frame.beat_strength.max(missed.beat_strength);
+        }
+        AUDIO_FRAME.signal(frame);
+    }
+}
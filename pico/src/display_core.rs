@@ -5,7 +5,7 @@ use embassy_rp::dma::Channel;
 use embassy_rp::pio::{Instance, Pio, PioPin};
 use embassy_time::Duration;
 
-use crate::{Display, FB_BYTES, Irqs, Lut};
+use crate::{AUDIO_FRAME, BEAT_SENSITIVITY, Display, FB_BYTES, Irqs, Lut};
 use visualisation::{CurrentVisualisationState, GameOfLife, Ising, SandPile, Turmite};
 
 struct Trng<'d> {
@@ -86,8 +86,17 @@ pub async fn run_display_core<L: Lut + Copy>(
     let mut start_time = embassy_time::Instant::now();
 
     loop {
-        let elapsed = start_time.elapsed();
-        start_time = embassy_time::Instant::now();
+        // take the elapsed time from a single reading, so no time is lost between frames
+        // and the clock visualisation doesn't drift
+        let now = embassy_time::Instant::now();
+        let elapsed = now - start_time;
+        start_time = now;
+        if let Some(frame) = AUDIO_FRAME.try_take() {
+            state.audio(&frame);
+        }
+        if let Some(sensitivity) = state.take_beat_sensitivity() {
+            BEAT_SENSITIVITY.signal(sensitivity);
+        }
         state.update(elapsed.as_micros() as u32);
         let mut current_framebuffer = display.get_framebuffer();
         current_framebuffer.fill(0);
@@ -3,6 +3,7 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
 
+mod audio;
 mod comms;
 mod display;
 mod display_core;
@@ -12,6 +13,7 @@ mod lut;
 
 pub const FB_BYTES: usize = fb_bytes(64, 32, 8);
 
+pub use audio::{AUDIO_FRAME, BEAT_SENSITIVITY, run_audio};
 pub use comms::Comms;
 pub use display::{Display, fb_bytes};
 pub use display_core::run_display_core;
@@ -23,6 +25,7 @@ pub use framebuffer::FrameBuffer;
 pub use lut::{GammaLut, Init, Lut, LutState};
 
 bind_interrupts!(pub struct Irqs {
+    ADC_IRQ_FIFO => embassy_rp::adc::InterruptHandler;
     PIO0_IRQ_0 => embassy_rp::pio::InterruptHandler<PIO0>;
     PIO1_IRQ_0 => embassy_rp::pio::InterruptHandler<PIO1>;
     TRNG_IRQ => embassy_rp::trng::InterruptHandler<TRNG>;
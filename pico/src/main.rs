@@ -18,8 +18,8 @@ use embassy_rp::pac::dma::Dma;
 use embassy_rp::pac::dma::regs::CtrlTrig;
 use embassy_rp::pac::dma::vals::{DataSize, TreqSel};
 use embassy_rp::peripherals::{
-    DMA_CH0, DMA_CH1, DMA_CH2, DMA_CH3, PIN_0, PIN_1, PIN_2, PIN_3, PIN_4, PIN_5, PIN_6, PIN_7,
-    PIN_8, PIN_9, PIN_10, PIN_11, PIN_12, TRNG,
+    ADC, DMA_CH0, DMA_CH1, DMA_CH2, DMA_CH3, DMA_CH4, PIN_0, PIN_1, PIN_2, PIN_3, PIN_4, PIN_5,
+    PIN_6, PIN_7, PIN_8, PIN_9, PIN_10, PIN_11, PIN_12, PIN_26, TRNG,
 };
 use embassy_rp::peripherals::{PIO0, PIO1};
 use embassy_rp::pio::{
@@ -29,7 +29,8 @@ use embassy_rp::pio::{
 use fixed::FixedU32;
 use fixed::types::extra::U8;
 use hub75_pico::{
-    Comms, Display, FB_BYTES, FrameBuffer, GammaLut, Init, Irqs, Lut, fb_bytes, run_display_core,
+    Comms, Display, FB_BYTES, FrameBuffer, GammaLut, Init, Irqs, Lut, fb_bytes, run_audio,
+    run_display_core,
 };
 use pio::{ProgramWithDefines, pio_asm};
 use static_cell::{ConstStaticCell, StaticCell};
@@ -58,6 +59,15 @@ async fn comms_and_display_runner(spawner: Spawner, p: embassy_rp::Peripherals)
     .await;
 }
 
+#[embassy_executor::task]
+async fn audio_task(
+    adc: Peri<'static, ADC>,
+    mic: Peri<'static, PIN_26>,
+    dma: Peri<'static, DMA_CH4>,
+) {
+    run_audio(adc, mic, dma).await;
+}
+
 struct DisplayCoreTaskArgs {
     pio: Pio<'static, PIO0>,
     r1: Peri<'static, PIN_0>,
@@ -130,6 +140,8 @@ fn main() -> ! {
             let _ = spawner.spawn(comms_and_display_runner(spawner, unsafe {
                 embassy_rp::Peripherals::steal()
             }));
+            let p = unsafe { embassy_rp::Peripherals::steal() };
+            let _ = spawner.spawn(audio_task(p.ADC, p.PIN_26, p.DMA_CH4));
         });
     });
 
@@ -0,0 +1,290 @@
+use embedded_graphics::{Pixel, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, palette::Palette};
+
+/// How far the parameters of the 2d maps wander while morphing
+const MORPH_AMOUNT: f32 = 0.15;
+/// The time step used to integrate the Lorenz system
+const LORENZ_DT: f32 = 0.004;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AttractorKind {
+    /// The Lorenz system, with a, b and c as sigma, rho and beta. Rotated about its vertical axis.
+    Lorenz,
+    /// `x' = sin(a y) + c cos(a x), y' = sin(b x) + d cos(b y)`
+    Clifford,
+    /// `x' = sin(a y) - cos(b x), y' = sin(c x) - cos(d y)`
+    DeJong,
+}
+
+impl AttractorKind {
+    fn default_parameters(self) -> [f32; 4] {
+        match self {
+            AttractorKind::Lorenz => [10.0, 28.0, 8.0 / 3.0, 0.0],
+            AttractorKind::Clifford => [-1.4, 1.6, 1.0, 0.7],
+            AttractorKind::DeJong => [1.4, -2.3, 2.4, -2.1],
+        }
+    }
+}
+
+/// Plots a strange attractor by following a point around it and counting how often
+/// it lands on each pixel. The counts slowly decay and are drawn on a log scale,
+/// while the attractor rotates (Lorenz) or morphs through its parameters (the maps).
+pub struct Attractor<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    hits: Grid<u16, W, H>,
+    kind: AttractorKind,
+    parameters: [f32; 4],
+    point: (f32, f32, f32),
+    /// the angle of rotation or the position in the morph cycle, in radians
+    phase: f32,
+    /// radians per second
+    morph_speed: f32,
+    /// the fraction of the hits lost each second
+    decay: f32,
+    points_per_update: u16,
+    palette: Palette,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Attractor<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(kind: AttractorKind, rng: Rng) -> Self {
+        let mut this = Attractor {
+            hits: Grid::new(0),
+            kind,
+            parameters: kind.default_parameters(),
+            point: (0.0, 0.0, 0.0),
+            phase: 0.0,
+            morph_speed: 0.3,
+            decay: 0.5,
+            points_per_update: 1500,
+            palette: Palette::Electric,
+            rng,
+        };
+        this.restart();
+        this
+    }
+
+    /// Clear the plot and start from a new random point
+    fn restart(&mut self) {
+        self.hits.buffer_mut().fill(0);
+        self.point = (
+            self.rng.unit_f32() - 0.5,
+            self.rng.unit_f32() - 0.5,
+            self.rng.unit_f32() * 10.0 + 10.0,
+        );
+    }
+
+    /// The parameters moved along the morph cycle
+    fn morphed_parameters(&self) -> [f32; 4] {
+        let mut parameters = self.parameters;
+        if self.kind != AttractorKind::Lorenz {
+            for (i, p) in parameters.iter_mut().enumerate() {
+                // each parameter moves at a different rate so the shape never quite repeats
+                *p += MORPH_AMOUNT * libm::sinf(self.phase * (1.0 + 0.37 * i as f32) + i as f32);
+            }
+        }
+        parameters
+    }
+
+    /// Move the point on by one step
+    fn advance(&mut self, [a, b, c, d]: [f32; 4]) {
+        let (x, y, z) = self.point;
+        self.point = match self.kind {
+            AttractorKind::Lorenz => {
+                let derivative =
+                    |(x, y, z): (f32, f32, f32)| (a * (y - x), x * (b - z) - y, x * y - c * z);
+                // midpoint method
+                let (dx, dy, dz) = derivative((x, y, z));
+                let half = LORENZ_DT / 2.0;
+                let (dx, dy, dz) = derivative((x + dx * half, y + dy * half, z + dz * half));
+                (x + dx * LORENZ_DT, y + dy * LORENZ_DT, z + dz * LORENZ_DT)
+            }
+            AttractorKind::Clifford => (
+                libm::sinf(a * y) + c * libm::cosf(a * x),
+                libm::sinf(b * x) + d * libm::cosf(b * y),
+                0.0,
+            ),
+            AttractorKind::DeJong => (
+                libm::sinf(a * y) - libm::cosf(b * x),
+                libm::sinf(c * x) - libm::cosf(d * y),
+                0.0,
+            ),
+        };
+        let (x, y, z) = self.point;
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            self.restart();
+        }
+    }
+
+    /// Where the point lands on the display
+    fn project(&self, [_, _, c, d]: [f32; 4]) -> (i32, i32) {
+        let (x, y, z) = self.point;
+        // the position in a square of side 2 centred on the origin, and how far it's
+        // stretched to fill the display height
+        let (px, py) = match self.kind {
+            AttractorKind::Lorenz => {
+                let (sin, cos) = (libm::sinf(self.phase), libm::cosf(self.phase));
+                ((x * cos - y * sin) / 27.0, (25.0 - z) / 27.0)
+            }
+            AttractorKind::Clifford => (x / (1.0 + libm::fabsf(c)), y / (1.0 + libm::fabsf(d))),
+            AttractorKind::DeJong => (x / 2.0, y / 2.0),
+        };
+        let scale = (H as f32 - 1.0) / 2.0;
+        (
+            libm::floorf(W as f32 / 2.0 + px * scale) as i32,
+            libm::floorf(H as f32 / 2.0 + py * scale) as i32,
+        )
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum AttractorUpdate {
+    Reset,
+    /// Switch attractor, going back to its default parameters
+    SetKind(AttractorKind),
+    /// The parameters of the attractor, see [AttractorKind] for what they mean
+    SetParameters {
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+    },
+    /// The fraction of the plot that fades away each second
+    SetDecay(f32),
+    /// How fast the attractor rotates or morphs, in radians per second
+    SetMorphSpeed(f32),
+    SetPointsPerUpdate(u16),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for AttractorUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Attractor<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = AttractorUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        self.phase += self.morph_speed * dt;
+
+        // fade the old hits by a 16 bit fixed point multiplier. The result is rounded
+        // randomly, otherwise small counts would lose a whole hit every frame. The
+        // rounding only needs to look random, so a xorshift seeded once a frame is
+        // plenty, and much cheaper than the rng for every pixel.
+        let keep = (libm::powf(1.0 - self.decay.min(0.999), dt) * 65536.0) as u32;
+        let mut dither = self.rng.next_u32() | 1;
+        for h in self.hits.buffer_mut().iter_mut() {
+            dither ^= dither << 13;
+            dither ^= dither >> 17;
+            dither ^= dither << 5;
+            *h = ((*h as u32 * keep + (dither & 0xffff)) >> 16) as u16;
+        }
+
+        let parameters = self.morphed_parameters();
+        for _ in 0..self.points_per_update {
+            self.advance(parameters);
+            let (x, y) = self.project(parameters);
+            if let Some(h) = self.hits.get_mut(x, y) {
+                *h = h.saturating_add(1);
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let max = self.hits.buffer().iter().copied().max().unwrap_or(0);
+        if max == 0 {
+            return;
+        }
+        // log scaling, so the faint parts of the attractor still show up
+        let scale = 1.0 / libm::logf(1.0 + max as f32);
+        let palette = self.palette;
+        let _ = target.draw_iter(self.hits.iter_with_index().filter(|(_, h)| **h > 0).map(
+            |((x, y), h)| {
+                let t = libm::logf(1.0 + *h as f32) * scale;
+                Pixel(Point::new(x, y), palette.sample(t))
+            },
+        ));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            AttractorUpdate::Reset => self.reset(),
+            AttractorUpdate::SetKind(kind) => {
+                self.kind = kind;
+                self.parameters = kind.default_parameters();
+                self.restart();
+            }
+            AttractorUpdate::SetParameters { a, b, c, d } => {
+                self.parameters = [a, b, c, d];
+                self.restart();
+            }
+            AttractorUpdate::SetDecay(decay) => self.decay = decay.clamp(0.0, 1.0),
+            AttractorUpdate::SetMorphSpeed(speed) => self.morph_speed = speed,
+            AttractorUpdate::SetPointsPerUpdate(points) => self.points_per_update = points,
+            AttractorUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Attractor::new(AttractorKind::Clifford, rng)
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+        self.restart();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::CountingRng;
+
+    type TestAttractor = Attractor<CountingRng, 64, 32>;
+
+    fn attractor() -> TestAttractor {
+        let mut attractor = TestAttractor::new(AttractorKind::Clifford, CountingRng::new(11));
+        attractor.run_state_update(AttractorUpdate::SetPointsPerUpdate(0));
+        attractor
+    }
+
+    #[test]
+    fn fading_takes_one_number_from_the_rng() {
+        let mut attractor = attractor();
+        attractor.rng.count = 0;
+        attractor.update(20_000);
+        assert_eq!(attractor.rng.count, 1);
+    }
+
+    #[test]
+    fn fading_rounds_small_counts_fairly() {
+        let mut attractor = attractor();
+        // keeps 0.001 ^ 0.1, very nearly half, over a tenth of a second
+        attractor.run_state_update(AttractorUpdate::SetDecay(0.999));
+        attractor.hits.buffer_mut().fill(1);
+        attractor.update(100_000);
+        let total: u32 = attractor.hits.buffer().iter().map(|h| *h as u32).sum();
+        let pixels = (64 * 32) as u32;
+        assert!(
+            total > pixels * 45 / 100 && total < pixels * 55 / 100,
+            "{total} of {pixels} hits left"
+        );
+    }
+}
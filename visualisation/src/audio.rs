@@ -0,0 +1,390 @@
+//! Sound analysis for the sound reactive visualisations: a fixed point FFT, and an
+//! analyser that turns blocks of microphone samples into band levels, a volume level
+//! and beats.
+//!
+//! Nothing here touches the hardware, so it runs the same on the host as on the pico.
+
+/// Samples in each block, and the size of the FFT
+pub const FFT_LEN: usize = 256;
+/// The number of bars the spectrum is split into
+pub const BAND_COUNT: usize = 16;
+/// The lowest frequency shown in the bands, in Hz
+const MIN_BAND_FREQUENCY: f32 = 60.0;
+/// The FFT magnitude of a full scale sine wave, after the scaling and windowing
+const FULL_SCALE: f32 = 4096.0;
+/// The range of the band levels, below the loudest recent band, in dB
+const BAND_RANGE_DB: f32 = 48.0;
+/// The quietest the automatic gain lets the loudest band be, so that silence isn't
+/// turned up until it fills the bars
+const MIN_REFERENCE_DB: f32 = -40.0;
+/// How quickly the automatic gain turns back up after something loud, in dB per second
+const GAIN_RECOVERY_DB: f32 = 4.0;
+/// The range of the volume level, below full scale, in dB
+const LEVEL_RANGE_DB: f32 = 50.0;
+/// Bass below this frequency, in Hz, is used for finding beats
+const BEAT_MAX_FREQUENCY: f32 = 200.0;
+/// The number of blocks the bass energy is averaged over, about 0.7 seconds at 16kHz
+const BEAT_HISTORY: usize = 43;
+/// The shortest time between two beats, in seconds
+const MIN_BEAT_INTERVAL: f32 = 0.25;
+/// Bass quieter than this, relative to full scale, is never a beat
+const MIN_BEAT_ENERGY: f32 = 1e-4;
+
+/// Multiply two Q15 numbers, rounding to the nearest
+fn mul_q15(a: i32, b: i32) -> i32 {
+    (a * b + (1 << 14)) >> 15
+}
+
+fn bit_reverse(i: usize) -> usize {
+    i.reverse_bits() >> (usize::BITS - FFT_LEN.trailing_zeros())
+}
+
+/// The twiddle factors for an [FFT_LEN] point FFT, as Q15 fixed point
+pub struct Fft {
+    cos: [i16; FFT_LEN / 2],
+    sin: [i16; FFT_LEN / 2],
+}
+
+impl Fft {
+    pub fn new() -> Self {
+        let mut cos = [0; FFT_LEN / 2];
+        let mut sin = [0; FFT_LEN / 2];
+        for k in 0..FFT_LEN / 2 {
+            let angle = core::f32::consts::TAU * k as f32 / FFT_LEN as f32;
+            cos[k] = (libm::cosf(angle) * i16::MAX as f32) as i16;
+            sin[k] = (libm::sinf(angle) * i16::MAX as f32) as i16;
+        }
+        Fft { cos, sin }
+    }
+
+    /// An in place, radix-2 FFT of Q15 numbers. Each stage halves the values so they
+    /// can't overflow, so the result is the transform divided by [FFT_LEN]. The inputs
+    /// need magnitudes under 0.5, or 16384.
+    pub fn transform(&self, re: &mut [i16; FFT_LEN], im: &mut [i16; FFT_LEN]) {
+        for i in 0..FFT_LEN {
+            let j = bit_reverse(i);
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= FFT_LEN {
+            let half = len / 2;
+            let stride = FFT_LEN / len;
+            for start in (0..FFT_LEN).step_by(len) {
+                for k in 0..half {
+                    // e^(-2πik/len)
+                    let (wr, wi) = (self.cos[k * stride] as i32, -(self.sin[k * stride] as i32));
+                    let (a, b) = (start + k, start + k + half);
+                    let (br, bi) = (re[b] as i32, im[b] as i32);
+                    let tr = mul_q15(br, wr) - mul_q15(bi, wi);
+                    let ti = mul_q15(br, wi) + mul_q15(bi, wr);
+                    let (ar, ai) = (re[a] as i32, im[a] as i32);
+                    re[a] = ((ar + tr) >> 1) as i16;
+                    im[a] = ((ai + ti) >> 1) as i16;
+                    re[b] = ((ar - tr) >> 1) as i16;
+                    im[b] = ((ai - ti) >> 1) as i16;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+impl Default for Fft {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the analyser heard in one block of samples
+#[derive(Copy, Clone, Default, Debug)]
+pub struct AudioFrame {
+    /// The level of each band from 0 to 1, from the lowest frequencies to the highest,
+    /// relative to the loudest band recently
+    pub bands: [f32; BAND_COUNT],
+    /// The volume from 0 to 1, on a log scale
+    pub level: f32,
+    /// The biggest sample from 0 to 1, where 1 is clipping
+    pub peak: f32,
+    /// Whether a beat started in this block
+    pub beat: bool,
+    /// How much louder the bass of the beat was than usual, from 0 to 1
+    pub beat_strength: f32,
+}
+
+/// Turns blocks of microphone samples into [AudioFrame]s
+pub struct AudioAnalyser {
+    fft: Fft,
+    /// a Hann window in Q15
+    window: [i16; FFT_LEN],
+    /// the first FFT bin in each band, with an extra entry for the end of the last band
+    band_starts: [u8; BAND_COUNT + 1],
+    /// the last FFT bin with bass in it
+    bass_end: usize,
+    /// seconds of sound in each block
+    block_time: f32,
+    /// the loudest band recently in dB, which sets the top of the band levels
+    reference_db: f32,
+    bass_history: [f32; BEAT_HISTORY],
+    history_index: usize,
+    /// how far above the average bass energy counts as a beat
+    sensitivity: f32,
+    /// seconds since the last beat
+    since_beat: f32,
+}
+
+impl AudioAnalyser {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut window = [0; FFT_LEN];
+        for (n, w) in window.iter_mut().enumerate() {
+            let angle = core::f32::consts::TAU * n as f32 / FFT_LEN as f32;
+            *w = ((0.5 - 0.5 * libm::cosf(angle)) * i16::MAX as f32) as i16;
+        }
+
+        // spread the bands evenly on a log scale, with at least one bin in each
+        let bin_width = sample_rate as f32 / FFT_LEN as f32;
+        let nyquist = sample_rate as f32 / 2.0;
+        let ratio = nyquist / MIN_BAND_FREQUENCY;
+        let mut band_starts = [0; BAND_COUNT + 1];
+        for (i, start) in band_starts.iter_mut().enumerate() {
+            let frequency = MIN_BAND_FREQUENCY * libm::powf(ratio, i as f32 / BAND_COUNT as f32);
+            *start = libm::roundf(frequency / bin_width).clamp(1.0, (FFT_LEN / 2) as f32) as u8;
+        }
+        for i in 1..=BAND_COUNT {
+            band_starts[i] = band_starts[i].max(band_starts[i - 1] + 1);
+        }
+        // squeezing the low bands up may push the last ones past the end
+        for i in (0..=BAND_COUNT).rev() {
+            let most = (FFT_LEN / 2 - (BAND_COUNT - i)) as u8;
+            band_starts[i] = band_starts[i].min(most);
+            if i < BAND_COUNT {
+                band_starts[i] = band_starts[i].min(band_starts[i + 1] - 1);
+            }
+        }
+
+        AudioAnalyser {
+            fft: Fft::new(),
+            window,
+            band_starts,
+            bass_end: ((BEAT_MAX_FREQUENCY / bin_width) as usize).max(1),
+            block_time: FFT_LEN as f32 / sample_rate as f32,
+            reference_db: MIN_REFERENCE_DB,
+            bass_history: [0.0; BEAT_HISTORY],
+            history_index: 0,
+            sensitivity: 1.4,
+            since_beat: 0.0,
+        }
+    }
+
+    /// How many times louder than usual the bass has to get to count as a beat
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity.clamp(1.05, 4.0);
+    }
+
+    /// Analyse a block of 12 bit unsigned samples, as read from the ADC
+    pub fn process(&mut self, samples: &[u16; FFT_LEN]) -> AudioFrame {
+        let mean = samples.iter().map(|s| *s as u32).sum::<u32>() / FFT_LEN as u32;
+
+        // centre the samples and scale them up to just under the top of the FFT's range
+        let mut re = [0i16; FFT_LEN];
+        let mut im = [0i16; FFT_LEN];
+        let mut square_sum = 0.0;
+        let mut peak = 0;
+        for i in 0..FFT_LEN {
+            let sample = ((samples[i] as i32 - mean as i32) * 8).clamp(-16384, 16383);
+            square_sum += (sample * sample) as f32;
+            peak = peak.max(sample.abs());
+            re[i] = mul_q15(sample, self.window[i] as i32) as i16;
+        }
+        self.fft.transform(&mut re, &mut im);
+        let power = |bin: usize| {
+            let (r, i) = (re[bin] as f32, im[bin] as f32);
+            (r * r + i * i) / (FULL_SCALE * FULL_SCALE)
+        };
+
+        let mut frame = AudioFrame::default();
+        let mut band_db = [0.0; BAND_COUNT];
+        for (band, db) in band_db.iter_mut().enumerate() {
+            let bins = self.band_starts[band] as usize..self.band_starts[band + 1] as usize;
+            let energy: f32 = bins.map(power).sum();
+            *db = 10.0 * libm::log10f(energy.max(1e-12));
+        }
+
+        // turn the gain down straight away for something loud, and back up slowly
+        let loudest = band_db.iter().copied().fold(MIN_REFERENCE_DB, f32::max);
+        self.reference_db = loudest.max(self.reference_db - GAIN_RECOVERY_DB * self.block_time);
+        for (level, db) in frame.bands.iter_mut().zip(band_db) {
+            *level = ((db - self.reference_db) / BAND_RANGE_DB + 1.0).clamp(0.0, 1.0);
+        }
+
+        // a full scale sine wave has an rms of 1 / sqrt(2)
+        let mean_square = square_sum / FFT_LEN as f32 / (16384.0 * 16384.0 / 2.0);
+        let level_db = 10.0 * libm::log10f(mean_square.max(1e-12));
+        frame.level = (level_db / LEVEL_RANGE_DB + 1.0).clamp(0.0, 1.0);
+        frame.peak = peak as f32 / 16384.0;
+
+        // a beat is bass that's much louder than it has been over the last moment
+        let bass: f32 = (1..=self.bass_end).map(power).sum();
+        let average = self.bass_history.iter().sum::<f32>() / BEAT_HISTORY as f32;
+        self.bass_history[self.history_index] = bass;
+        self.history_index = (self.history_index + 1) % BEAT_HISTORY;
+        self.since_beat += self.block_time;
+        if bass > MIN_BEAT_ENERGY
+            && bass > self.sensitivity * average
+            && self.since_beat >= MIN_BEAT_INTERVAL
+        {
+            self.since_beat = 0.0;
+            frame.beat = true;
+            let ratio = bass / average.max(MIN_BEAT_ENERGY);
+            frame.beat_strength = ((ratio - self.sensitivity) / self.sensitivity).clamp(0.0, 1.0);
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    /// A block of 12 bit samples of a sine wave at the centre of an FFT bin, around
+    /// the middle of the ADC's range
+    fn sine_block(bin: usize, amplitude: f32) -> [u16; FFT_LEN] {
+        core::array::from_fn(|n| {
+            let angle = core::f32::consts::TAU * (bin * n) as f32 / FFT_LEN as f32;
+            (2048.0 + amplitude * libm::sinf(angle)) as u16
+        })
+    }
+
+    /// A quiet block, with a couple of counts of noise like an idle microphone
+    fn quiet_block(seed: &mut u32) -> [u16; FFT_LEN] {
+        core::array::from_fn(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 17;
+            *seed ^= *seed << 5;
+            2047 + (*seed % 3) as u16
+        })
+    }
+
+    #[test]
+    fn full_scale_sine_peaks_at_its_bin() {
+        let analyser = AudioAnalyser::new(SAMPLE_RATE);
+        for bin in [4, 17, 60, 100] {
+            // the same scaling as `process`, a full scale sine is just under 16384
+            let mut re: [i16; FFT_LEN] = core::array::from_fn(|n| {
+                let angle = core::f32::consts::TAU * (bin * n) as f32 / FFT_LEN as f32;
+                let sample = (16383.0 * libm::sinf(angle)) as i32;
+                mul_q15(sample, analyser.window[n] as i32) as i16
+            });
+            let mut im = [0; FFT_LEN];
+            analyser.fft.transform(&mut re, &mut im);
+
+            let magnitude = |k: usize| libm::hypotf(re[k] as f32, im[k] as f32);
+            let peak = (0..FFT_LEN / 2)
+                .max_by(|&a, &b| magnitude(a).total_cmp(&magnitude(b)))
+                .unwrap();
+            assert_eq!(peak, bin);
+            let error = (magnitude(bin) - FULL_SCALE).abs() / FULL_SCALE;
+            assert!(error < 0.02, "bin {bin} has magnitude {}", magnitude(bin));
+        }
+    }
+
+    #[test]
+    fn constant_input_has_no_band_energy() {
+        let mut analyser = AudioAnalyser::new(SAMPLE_RATE);
+        for level in [0, 1000, 2048, 4095] {
+            let frame = analyser.process(&[level; FFT_LEN]);
+            assert!(
+                frame.bands.iter().all(|band| *band == 0.0),
+                "{:?}",
+                frame.bands
+            );
+            assert_eq!(frame.level, 0.0);
+            assert!(!frame.beat);
+        }
+    }
+
+    #[test]
+    fn bands_are_increasing_and_in_range() {
+        for sample_rate in [8_000, 16_000, 44_100] {
+            let analyser = AudioAnalyser::new(sample_rate);
+            let starts = analyser.band_starts;
+            assert!(
+                starts.windows(2).all(|pair| pair[0] < pair[1]),
+                "{sample_rate}: {starts:?}"
+            );
+            assert!(
+                starts
+                    .iter()
+                    .all(|start| (1..=FFT_LEN / 2).contains(&(*start as usize))),
+                "{sample_rate}: {starts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sensitivity_is_kept_in_range() {
+        let mut analyser = AudioAnalyser::new(16_000);
+        analyser.set_sensitivity(2.0);
+        assert_eq!(analyser.sensitivity, 2.0);
+        analyser.set_sensitivity(100.0);
+        assert_eq!(analyser.sensitivity, 4.0);
+        analyser.set_sensitivity(0.0);
+        assert_eq!(analyser.sensitivity, 1.05);
+    }
+
+    #[test]
+    fn silence_never_beats() {
+        let mut analyser = AudioAnalyser::new(SAMPLE_RATE);
+        let mut seed = 1;
+        for _ in 0..2000 {
+            assert!(!analyser.process(&quiet_block(&mut seed)).beat);
+            assert!(!analyser.process(&[2048; FFT_LEN]).beat);
+        }
+    }
+
+    /// Play bass bursts every `period` blocks between quiet blocks, returning the
+    /// blocks that had a beat
+    fn beats_from_bursts(period: usize, blocks: usize) -> heapless::Vec<usize, 128> {
+        let mut analyser = AudioAnalyser::new(SAMPLE_RATE);
+        let mut seed = 1;
+        // 125Hz, well inside the bass used for beats
+        let burst = sine_block(2, 1800.0);
+        (0..blocks)
+            .filter(|block| {
+                let samples = if block % period == 0 {
+                    burst
+                } else {
+                    quiet_block(&mut seed)
+                };
+                analyser.process(&samples).beat
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bass_bursts_beat() {
+        // a burst about every half second, like 120bpm
+        let period = 31;
+        let beats = beats_from_bursts(period, period * 20);
+        // the first burst comes before the shortest gap between beats has passed
+        assert_eq!(beats.len(), 19, "{beats:?}");
+        assert!(beats.iter().all(|block| block % period == 0), "{beats:?}");
+    }
+
+    #[test]
+    fn beats_are_spaced_out() {
+        let block_time = FFT_LEN as f32 / SAMPLE_RATE as f32;
+        // bursts faster than the shortest gap between beats
+        let beats = beats_from_bursts(8, 800);
+        assert!(beats.len() > 10, "{beats:?}");
+        for pair in beats.windows(2) {
+            let gap = (pair[1] - pair[0]) as f32 * block_time;
+            assert!(gap >= MIN_BEAT_INTERVAL, "{beats:?}");
+        }
+    }
+}
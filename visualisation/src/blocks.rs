@@ -0,0 +1,773 @@
+use core::fmt::Write;
+
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{
+    Input, RngU32, StateUpdate, Visualisation,
+    text::{TextBuffer, draw_text, draw_text_centred},
+};
+
+const BOARD_W: usize = 10;
+const BOARD_H: usize = 16;
+/// The size of a board cell in pixels
+const CELL: i32 = 2;
+/// How long the score is shown for before going back to the demo, in seconds
+const GAME_OVER_TIME: f32 = 8.0;
+/// How long input is ignored for after losing, so the score isn't skipped by accident
+const GAME_OVER_INPUT_DELAY: f32 = 1.0;
+/// How long full rows flash for before they're removed, in seconds
+const CLEAR_TIME: f32 = 0.3;
+/// Seconds between each move the demo makes
+const ATTRACT_MOVE_TIME: f32 = 0.08;
+/// The points for clearing 0 to 4 rows at once, multiplied by the level
+const LINE_SCORES: [u32; 5] = [0, 40, 100, 300, 1200];
+
+/// The cells of each piece in its spawn rotation, and the size of the box it rotates in
+const PIECES: [([(i8, i8); 4], i8); 7] = [
+    // I
+    ([(0, 1), (1, 1), (2, 1), (3, 1)], 4),
+    // O
+    ([(0, 0), (1, 0), (0, 1), (1, 1)], 2),
+    // T
+    ([(1, 0), (0, 1), (1, 1), (2, 1)], 3),
+    // S
+    ([(1, 0), (2, 0), (0, 1), (1, 1)], 3),
+    // Z
+    ([(0, 0), (1, 0), (1, 1), (2, 1)], 3),
+    // J
+    ([(0, 0), (0, 1), (1, 1), (2, 1)], 3),
+    // L
+    ([(2, 0), (0, 1), (1, 1), (2, 1)], 3),
+];
+
+const COLOURS: [Rgb888; 7] = [
+    Rgb888::new(0, 220, 255),
+    Rgb888::new(255, 220, 0),
+    Rgb888::new(180, 0, 255),
+    Rgb888::new(0, 230, 0),
+    Rgb888::new(255, 0, 0),
+    Rgb888::new(0, 60, 255),
+    Rgb888::new(255, 120, 0),
+];
+
+type Board = [[u8; BOARD_W]; BOARD_H];
+
+#[derive(Copy, Clone)]
+struct Piece {
+    kind: u8,
+    rotation: u8,
+    x: i32,
+    y: i32,
+}
+
+impl Piece {
+    fn spawn(kind: u8) -> Self {
+        Piece {
+            kind,
+            rotation: 0,
+            x: (BOARD_W as i32 - PIECES[kind as usize].1 as i32) / 2,
+            y: 0,
+        }
+    }
+
+    /// The board cells covered by the piece
+    fn cells(&self) -> [(i32, i32); 4] {
+        let (cells, size) = PIECES[self.kind as usize];
+        cells.map(|(mut x, mut y)| {
+            // rotate clockwise inside the piece's box
+            for _ in 0..self.rotation {
+                (x, y) = (size - 1 - y, x);
+            }
+            (self.x + x as i32, self.y + y as i32)
+        })
+    }
+
+    fn fits(&self, board: &Board) -> bool {
+        self.cells().iter().all(|&(x, y)| {
+            x >= 0
+                && y >= 0
+                && (x as usize) < BOARD_W
+                && (y as usize) < BOARD_H
+                && board[y as usize][x as usize] == 0
+        })
+    }
+
+    fn moved(&self, dx: i32, dy: i32) -> Self {
+        Piece {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..*self
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    /// The computer plays until someone presses a button
+    Attract,
+    Playing,
+    /// Showing the score, with the seconds left before going back to attract mode
+    GameOver(f32),
+}
+
+/// A falling block puzzle game, played with the controller input. Left and right
+/// move, up and A rotate, down drops by a row and B drops all the way.
+/// When nobody is playing the computer plays as an attract mode.
+///
+/// The game only changes state in `update` and `input`, so given the same rng
+/// and the same inputs it plays out the same way.
+pub struct Blocks<Rng, const W: usize, const H: usize> {
+    /// the settled blocks, 0 for empty or the piece kind plus one
+    board: Board,
+    piece: Piece,
+    next: u8,
+    /// a shuffled bag of every piece kind, so there are never long droughts of one piece
+    bag: [u8; 7],
+    bag_index: usize,
+    score: u32,
+    high_score: u32,
+    lines: u32,
+    mode: Mode,
+    /// seconds until the piece next falls by a row
+    fall_timer: f32,
+    /// full rows that are flashing before being removed, one bit per row
+    clearing: u16,
+    clear_timer: f32,
+    /// where the demo is moving the piece to, as (rotation, x)
+    target: (u8, i32),
+    move_timer: f32,
+    /// a clock used to flash text
+    time: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Blocks<Rng, W, H> {
+    const OFFSET_X: i32 = (W as i32 - BOARD_W as i32 * CELL) / 2;
+    const OFFSET_Y: i32 = (H as i32 - BOARD_H as i32 * CELL) / 2;
+    /// The middle of the panels either side of the board
+    const LEFT_PANEL: i32 = Self::OFFSET_X / 2;
+    const RIGHT_PANEL: i32 = (W as i32 + Self::OFFSET_X + BOARD_W as i32 * CELL) / 2;
+
+    pub fn new(rng: Rng) -> Self {
+        let mut this = Blocks {
+            board: [[0; BOARD_W]; BOARD_H],
+            piece: Piece::spawn(0),
+            next: 0,
+            bag: [0, 1, 2, 3, 4, 5, 6],
+            bag_index: 7,
+            score: 0,
+            high_score: 0,
+            lines: 0,
+            mode: Mode::Attract,
+            fall_timer: 0.0,
+            clearing: 0,
+            clear_timer: 0.0,
+            target: (0, 0),
+            move_timer: 0.0,
+            time: 0.0,
+            rng,
+        };
+        this.new_game(Mode::Attract);
+        this
+    }
+
+    fn level(&self) -> u32 {
+        self.lines / 10
+    }
+
+    /// Seconds between each row the piece falls
+    fn fall_time(&self) -> f32 {
+        (0.8 - self.level() as f32 * 0.07).max(0.08)
+    }
+
+    fn new_game(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.board = [[0; BOARD_W]; BOARD_H];
+        self.score = 0;
+        self.lines = 0;
+        self.clearing = 0;
+        self.bag_index = self.bag.len();
+        self.next = self.take_from_bag();
+        self.spawn();
+    }
+
+    fn take_from_bag(&mut self) -> u8 {
+        if self.bag_index >= self.bag.len() {
+            // Fisher-Yates shuffle
+            for i in (1..self.bag.len()).rev() {
+                let j = (self.rng.next_u32() % (i as u32 + 1)) as usize;
+                self.bag.swap(i, j);
+            }
+            self.bag_index = 0;
+        }
+        self.bag_index += 1;
+        self.bag[self.bag_index - 1]
+    }
+
+    /// Bring in the next piece, returns false if there's no room for it
+    fn spawn(&mut self) -> bool {
+        self.piece = Piece::spawn(self.next);
+        self.next = self.take_from_bag();
+        self.fall_timer = self.fall_time();
+        if self.mode == Mode::Attract {
+            self.target = Self::best_placement(&self.board, self.piece);
+        }
+        self.piece.fits(&self.board)
+    }
+
+    fn game_over(&mut self) {
+        match self.mode {
+            Mode::Playing => {
+                self.high_score = self.high_score.max(self.score);
+                self.mode = Mode::GameOver(GAME_OVER_TIME);
+            }
+            // the demo just starts again
+            _ => self.new_game(Mode::Attract),
+        }
+    }
+
+    fn try_move(&mut self, dx: i32, dy: i32) -> bool {
+        let moved = self.piece.moved(dx, dy);
+        if moved.fits(&self.board) {
+            self.piece = moved;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Rotate clockwise, nudging the piece sideways or up if it doesn't fit
+    fn rotate(&mut self) {
+        let rotated = Piece {
+            rotation: (self.piece.rotation + 1) % 4,
+            ..self.piece
+        };
+        for (dx, dy) in [(0, 0), (-1, 0), (1, 0), (-2, 0), (2, 0), (0, -1)] {
+            let kicked = rotated.moved(dx, dy);
+            if kicked.fits(&self.board) {
+                self.piece = kicked;
+                return;
+            }
+        }
+    }
+
+    /// Fix the piece into the board, and start clearing any full rows
+    fn lock(&mut self) {
+        for (x, y) in self.piece.cells() {
+            self.board[y as usize][x as usize] = self.piece.kind + 1;
+        }
+        self.clearing = Self::full_rows(&self.board);
+        if self.clearing != 0 {
+            self.clear_timer = CLEAR_TIME;
+        } else if !self.spawn() {
+            self.game_over();
+        }
+    }
+
+    fn full_rows(board: &Board) -> u16 {
+        board
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.iter().all(|&c| c != 0))
+            .fold(0, |rows, (y, _)| rows | 1 << y)
+    }
+
+    /// Remove the rows in `rows`, moving everything above them down.
+    /// Returns how many rows were removed.
+    fn remove_rows(board: &mut Board, rows: u16) -> u32 {
+        let mut to = BOARD_H;
+        for from in (0..BOARD_H).rev() {
+            if rows & (1 << from) == 0 {
+                to -= 1;
+                board[to] = board[from];
+            }
+        }
+        for row in board.iter_mut().take(to) {
+            *row = [0; BOARD_W];
+        }
+        rows.count_ones()
+    }
+
+    fn finish_clearing(&mut self) {
+        let cleared = Self::remove_rows(&mut self.board, self.clearing);
+        self.clearing = 0;
+        if self.mode == Mode::Playing {
+            self.score += LINE_SCORES[cleared.min(4) as usize] * (self.level() + 1);
+        }
+        self.lines += cleared;
+        if !self.spawn() {
+            self.game_over();
+        }
+    }
+
+    /// Move the piece down a row, locking it if it can't go any further
+    fn fall(&mut self) {
+        if !self.try_move(0, 1) {
+            self.lock();
+        }
+    }
+
+    fn hard_drop(&mut self) {
+        let mut rows = 0;
+        while self.try_move(0, 1) {
+            rows += 1;
+        }
+        if self.mode == Mode::Playing {
+            self.score += 2 * rows;
+        }
+        self.lock();
+    }
+
+    /// Rate a board for the demo: fewer holes, a lower stack and a flatter surface are better
+    fn evaluate(board: &Board, lines: u32) -> f32 {
+        let mut heights = [0i32; BOARD_W];
+        let mut holes = 0;
+        for (x, height) in heights.iter_mut().enumerate() {
+            let top = (0..BOARD_H).find(|&y| board[y][x] != 0).unwrap_or(BOARD_H);
+            *height = (BOARD_H - top) as i32;
+            holes += (top..BOARD_H).filter(|&y| board[y][x] == 0).count();
+        }
+        let total_height: i32 = heights.iter().sum();
+        let bumpiness: i32 = heights.windows(2).map(|w| (w[0] - w[1]).abs()).sum();
+        -0.51 * total_height as f32 + 0.76 * lines as f32
+            - 0.36 * holes as f32
+            - 0.18 * bumpiness as f32
+    }
+
+    /// Try dropping the piece in every rotation and column, and return the best as (rotation, x)
+    fn best_placement(board: &Board, piece: Piece) -> (u8, i32) {
+        let mut best = (piece.rotation, piece.x);
+        let mut best_score = f32::MIN;
+        for rotation in 0..4 {
+            for x in -2..BOARD_W as i32 {
+                let mut placed = Piece {
+                    rotation,
+                    x,
+                    ..piece
+                };
+                if !placed.fits(board) {
+                    continue;
+                }
+                while placed.moved(0, 1).fits(board) {
+                    placed = placed.moved(0, 1);
+                }
+                let mut after = *board;
+                for (cx, cy) in placed.cells() {
+                    after[cy as usize][cx as usize] = piece.kind + 1;
+                }
+                let full = Self::full_rows(&after);
+                let lines = Self::remove_rows(&mut after, full);
+                let score = Self::evaluate(&after, lines);
+                if score > best_score {
+                    best_score = score;
+                    best = (rotation, x);
+                }
+            }
+        }
+        best
+    }
+
+    /// Make one move of the demo towards its target
+    fn attract_move(&mut self) {
+        let (rotation, x) = self.target;
+        if self.piece.rotation != rotation {
+            let before = self.piece.rotation;
+            self.rotate();
+            if self.piece.rotation == before {
+                // blocked, so give up on the rotation and just drop it
+                self.hard_drop();
+            }
+        } else if self.piece.x != x {
+            if !self.try_move((x - self.piece.x).signum(), 0) {
+                self.hard_drop();
+            }
+        } else {
+            self.hard_drop();
+        }
+    }
+
+    fn step(&mut self, dt: f32) {
+        if self.clearing != 0 {
+            self.clear_timer -= dt;
+            if self.clear_timer <= 0.0 {
+                self.finish_clearing();
+            }
+            return;
+        }
+        if self.mode == Mode::Attract {
+            self.move_timer -= dt;
+            if self.move_timer <= 0.0 {
+                self.move_timer += ATTRACT_MOVE_TIME;
+                self.attract_move();
+                return;
+            }
+        }
+        self.fall_timer -= dt;
+        if self.fall_timer <= 0.0 {
+            self.fall_timer += self.fall_time();
+            self.fall();
+        }
+    }
+
+    fn cell_pixels(x: i32, y: i32, colour: Rgb888) -> impl Iterator<Item = Pixel<Rgb888>> {
+        let (px, py) = (Self::OFFSET_X + x * CELL, Self::OFFSET_Y + y * CELL);
+        (0..CELL * CELL).map(move |i| Pixel(Point::new(px + i % CELL, py + i / CELL), colour))
+    }
+
+    fn draw_number<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        target: &mut D,
+        n: u32,
+        position: Point,
+    ) {
+        let mut text: TextBuffer<10> = TextBuffer::new();
+        let _ = write!(text, "{}", n);
+        draw_text_centred(target, text.as_str(), position, Rgb888::new(255, 255, 255));
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum BlocksUpdate {
+    /// Go back to the demo
+    Reset,
+    ResetHighScore,
+}
+
+impl StateUpdate for BlocksUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Blocks<Rng, W, H> {
+    type StateUpdate = BlocksUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.25);
+        self.time = (self.time + dt) % 60.0;
+        if let Mode::GameOver(remaining) = self.mode {
+            if remaining > dt {
+                self.mode = Mode::GameOver(remaining - dt);
+            } else {
+                self.new_game(Mode::Attract);
+            }
+            return true;
+        }
+        self.step(dt);
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let game_over = matches!(self.mode, Mode::GameOver(_));
+        let flash = self.clear_timer % 0.1 < 0.05;
+        let _ = target.draw_iter((0..BOARD_H).flat_map(|y| {
+            let board = &self.board;
+            let clearing = self.clearing & (1 << y) != 0;
+            (0..BOARD_W).flat_map(move |x| {
+                let colour = match board[y][x] {
+                    _ if clearing && flash => Rgb888::new(255, 255, 255),
+                    0 => Rgb888::new(10, 10, 16),
+                    _ if game_over => Rgb888::new(50, 50, 50),
+                    kind => COLOURS[kind as usize - 1],
+                };
+                Self::cell_pixels(x as i32, y as i32, colour)
+            })
+        }));
+        if !game_over && self.clearing == 0 {
+            let colour = COLOURS[self.piece.kind as usize];
+            for (x, y) in self.piece.cells() {
+                let _ = target.draw_iter(Self::cell_pixels(x, y, colour));
+            }
+        }
+
+        let white = Rgb888::new(255, 255, 255);
+        let yellow = Rgb888::new(255, 200, 40);
+        let left = Self::LEFT_PANEL;
+        let right = Self::RIGHT_PANEL;
+        match self.mode {
+            Mode::Attract => {
+                draw_text_centred(target, "DEMO", Point::new(left, 1), yellow);
+                if self.time % 2.0 < 1.4 {
+                    draw_text_centred(target, "PRESS", Point::new(left, 12), white);
+                    draw_text_centred(target, "A KEY", Point::new(left, 19), white);
+                }
+            }
+            Mode::Playing | Mode::GameOver(_) => {
+                draw_text_centred(target, "SCORE", Point::new(left, 1), yellow);
+                Self::draw_number(target, self.score, Point::new(left, 8));
+                draw_text_centred(target, "LINES", Point::new(left, 17), yellow);
+                Self::draw_number(target, self.lines, Point::new(left, 24));
+            }
+        }
+
+        if game_over {
+            let middle = Self::OFFSET_X + BOARD_W as i32 * CELL / 2;
+            draw_text_centred(
+                target,
+                "GAME",
+                Point::new(middle, 9),
+                Rgb888::new(255, 40, 40),
+            );
+            draw_text_centred(
+                target,
+                "OVER",
+                Point::new(middle, 16),
+                Rgb888::new(255, 40, 40),
+            );
+            draw_text_centred(target, "BEST", Point::new(right, 1), yellow);
+            Self::draw_number(target, self.high_score, Point::new(right, 8));
+        } else {
+            draw_text_centred(target, "NEXT", Point::new(right, 1), yellow);
+            let (cells, size) = PIECES[self.next as usize];
+            let colour = COLOURS[self.next as usize];
+            let (px, py) = (right - size as i32 * CELL / 2, 9);
+            let _ = target.draw_iter(cells.into_iter().flat_map(|(x, y)| {
+                let (x, y) = (px + x as i32 * CELL, py + y as i32 * CELL);
+                (0..CELL * CELL).map(move |i| Pixel(Point::new(x + i % CELL, y + i / CELL), colour))
+            }));
+        }
+        let mut level: TextBuffer<8> = TextBuffer::new();
+        let _ = write!(level, "LV{}", self.level() + 1);
+        draw_text(target, level.as_str(), Point::new(right - 8, 24), white);
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            BlocksUpdate::Reset => self.reset(),
+            BlocksUpdate::ResetHighScore => self.high_score = 0,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Blocks::new(rng)
+    }
+
+    fn reset(&mut self) {
+        self.new_game(Mode::Attract);
+    }
+
+    fn input(&mut self, input: Input) {
+        match self.mode {
+            Mode::Attract => self.new_game(Mode::Playing),
+            Mode::GameOver(remaining) => {
+                if GAME_OVER_TIME - remaining > GAME_OVER_INPUT_DELAY {
+                    self.new_game(Mode::Playing);
+                }
+            }
+            Mode::Playing => {
+                // the piece is frozen while rows are being cleared
+                if self.clearing != 0 {
+                    return;
+                }
+                match input {
+                    Input::Left => {
+                        self.try_move(-1, 0);
+                    }
+                    Input::Right => {
+                        self.try_move(1, 0);
+                    }
+                    Input::Up | Input::A => self.rotate(),
+                    Input::Down => {
+                        self.score += 1;
+                        self.fall_timer = self.fall_time();
+                        self.fall();
+                    }
+                    Input::B => self.hard_drop(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    type TestBlocks = Blocks<XorShift, 64, 32>;
+
+    fn playing() -> TestBlocks {
+        let mut blocks = TestBlocks::new(XorShift(1));
+        blocks.input(Input::A);
+        blocks
+    }
+
+    /// Run the game for `seconds`, a twentieth of a second at a time
+    fn run(blocks: &mut TestBlocks, seconds: f32) {
+        for _ in 0..(seconds * 20.0) as u32 {
+            blocks.update(50_000);
+        }
+    }
+
+    #[test]
+    fn clearing_rows_scores() {
+        let mut blocks = playing();
+        assert!(blocks.mode == Mode::Playing);
+        // four full rows but for the first column, with a block sitting on top
+        for row in blocks.board[12..].iter_mut() {
+            *row = [1; BOARD_W];
+            row[0] = 0;
+        }
+        blocks.board[11][5] = 2;
+        // a vertical I in the first column
+        blocks.piece = Piece {
+            kind: 0,
+            rotation: 1,
+            x: -2,
+            y: 0,
+        };
+        blocks.input(Input::B);
+        assert_eq!(blocks.clearing, 0b1111 << 12);
+        // two points a row for the hard drop
+        assert_eq!(blocks.score, 24);
+        // the piece is frozen while the rows flash
+        let piece = blocks.piece;
+        blocks.input(Input::Left);
+        assert_eq!(blocks.piece.x, piece.x);
+
+        run(&mut blocks, CLEAR_TIME + 0.05);
+        assert_eq!(blocks.clearing, 0);
+        assert_eq!(blocks.lines, 4);
+        assert_eq!(blocks.score, 24 + LINE_SCORES[4]);
+        let mut expected = [[0; BOARD_W]; BOARD_H];
+        expected[15][5] = 2;
+        assert_eq!(blocks.board, expected);
+    }
+
+    #[test]
+    fn single_rows_score_by_level() {
+        let mut blocks = playing();
+        blocks.lines = 10;
+        blocks.board[15] = [1, 1, 1, 0, 0, 0, 0, 1, 1, 1];
+        // a flat I drops straight into the gap
+        blocks.piece = Piece::spawn(0);
+        blocks.hard_drop();
+        run(&mut blocks, CLEAR_TIME + 0.05);
+        assert_eq!(blocks.lines, 11);
+        assert_eq!(blocks.score, 2 * 14 + LINE_SCORES[1] * 2);
+        assert_eq!(blocks.board[15], [0; BOARD_W]);
+    }
+
+    #[test]
+    fn moves_and_rotates_with_the_input() {
+        let mut blocks = playing();
+        // a T
+        blocks.piece = Piece::spawn(2);
+        blocks.input(Input::Left);
+        assert_eq!(blocks.piece.x, 2);
+        blocks.input(Input::Up);
+        assert_eq!(blocks.piece.rotation, 1);
+        blocks.input(Input::A);
+        assert_eq!(blocks.piece.rotation, 2);
+        blocks.input(Input::Down);
+        assert_eq!(blocks.piece.y, 1);
+        assert_eq!(blocks.score, 1);
+        for _ in 0..BOARD_W {
+            blocks.input(Input::Left);
+        }
+        assert!(blocks.piece.cells().iter().any(|&(x, _)| x == 0));
+        assert!(blocks.piece.fits(&blocks.board));
+    }
+
+    #[test]
+    fn rotation_is_kicked_off_the_wall() {
+        let mut blocks = playing();
+        // a vertical I against the left wall can only lie flat if it moves right
+        blocks.piece = Piece {
+            kind: 0,
+            rotation: 1,
+            x: -2,
+            y: 4,
+        };
+        blocks.input(Input::Up);
+        assert_eq!(blocks.piece.rotation, 2);
+        assert_eq!(blocks.piece.x, 0);
+        assert!(blocks.piece.fits(&blocks.board));
+    }
+
+    #[test]
+    fn rotation_is_blocked_by_the_wall() {
+        let mut blocks = playing();
+        blocks.piece = Piece {
+            kind: 0,
+            rotation: 1,
+            x: -2,
+            y: 4,
+        };
+        // with the column beside it filled there's nowhere to kick to
+        for row in blocks.board.iter_mut() {
+            row[1] = 1;
+        }
+        blocks.input(Input::Up);
+        blocks.input(Input::A);
+        assert_eq!(blocks.piece.rotation, 1);
+        assert_eq!((blocks.piece.x, blocks.piece.y), (-2, 4));
+    }
+
+    #[test]
+    fn stacking_to_the_top_ends_the_game_then_restarts() {
+        let mut blocks = playing();
+        // nearly full rows, with a gap well away from where pieces come in
+        for row in blocks.board[2..].iter_mut() {
+            *row = [1; BOARD_W];
+            row[9] = 0;
+        }
+        // an O, which every piece overlaps when it comes in
+        blocks.piece = Piece::spawn(1);
+        blocks.input(Input::B);
+        assert!(matches!(blocks.mode, Mode::GameOver(_)));
+        assert_eq!(blocks.high_score, blocks.score);
+
+        // presses straight after losing don't skip the score
+        blocks.input(Input::A);
+        assert!(matches!(blocks.mode, Mode::GameOver(_)));
+        run(&mut blocks, GAME_OVER_INPUT_DELAY + 0.1);
+        blocks.input(Input::A);
+        assert!(blocks.mode == Mode::Playing);
+        assert_eq!(blocks.board, [[0; BOARD_W]; BOARD_H]);
+        assert_eq!((blocks.score, blocks.lines), (0, 0));
+    }
+
+    #[test]
+    fn demo_takes_over_when_nobody_plays() {
+        let mut blocks = playing();
+        blocks.score = 100;
+        blocks.game_over();
+        run(&mut blocks, GAME_OVER_TIME + 0.1);
+        assert!(blocks.mode == Mode::Attract);
+
+        // the demo clears rows on its own, but doesn't score
+        run(&mut blocks, 60.0);
+        assert!(blocks.mode == Mode::Attract);
+        assert!(blocks.lines > 0);
+        assert_eq!(blocks.score, 0);
+        assert_eq!(blocks.high_score, 100);
+
+        blocks.input(Input::Left);
+        assert!(blocks.mode == Mode::Playing);
+        assert_eq!(blocks.board, [[0; BOARD_W]; BOARD_H]);
+        assert_eq!(blocks.lines, 0);
+    }
+
+    #[test]
+    fn pieces_come_from_a_shuffled_bag() {
+        let mut blocks = TestBlocks::new(XorShift(5));
+        // starting a game has already taken the first pieces out
+        blocks.bag_index = blocks.bag.len();
+        for _ in 0..4 {
+            let mut seen = [false; 7];
+            for _ in 0..7 {
+                seen[blocks.take_from_bag() as usize] = true;
+            }
+            assert!(seen.iter().all(|&seen| seen));
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid};
+
+/// The maximum number of boids that can be simulated
+pub const MAX_BOIDS: usize = 320;
+
+/// The number of spatial bins in the x direction
+const BINS_X: usize = 16;
+/// The number of spatial bins in the y direction
+const BINS_Y: usize = 8;
+const N_BINS: usize = BINS_X * BINS_Y;
+
+#[derive(Copy, Clone)]
+struct Boid {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+}
+
+pub struct Boids<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    boids: [Boid; MAX_BOIDS],
+    n_boids: usize,
+    /// the boid indices, sorted by the bin they're in
+    binned: [u16; MAX_BOIDS],
+    /// the start of each bin in `binned`, with an extra entry for the end of the last bin
+    bin_starts: [u16; N_BINS + 1],
+    /// brightness of the trail left at each pixel
+    trails: Grid<u8, W, H>,
+    rng: Rng,
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    /// a point the boids try to get away from
+    predator: Option<(f32, f32)>,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Boids<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    /// the distance that boids can see each other over, this is no larger than a bin
+    const VIEW_RADIUS: f32 = 4.0;
+    /// the distance under which boids start pushing each other away
+    const SEPARATION_RADIUS: f32 = 1.5;
+    /// the distance under which the boids run from the predator
+    const PREDATOR_RADIUS: f32 = 10.0;
+    const MIN_SPEED: f32 = 6.0;
+    const MAX_SPEED: f32 = 18.0;
+    /// How much the trails fade per second
+    const TRAIL_FADE: f32 = 900.0;
+
+    pub fn new(n_boids: usize, rng: Rng) -> Self {
+        let mut this = Boids {
+            boids: [Boid {
+                x: 0.0,
+                y: 0.0,
+                vx: 0.0,
+                vy: 0.0,
+            }; MAX_BOIDS],
+            n_boids: n_boids.min(MAX_BOIDS),
+            binned: [0; MAX_BOIDS],
+            bin_starts: [0; N_BINS + 1],
+            trails: Grid::new(0),
+            rng,
+            separation: 3.0,
+            alignment: 1.0,
+            cohesion: 0.6,
+            predator: None,
+        };
+        this.scatter(0);
+        this
+    }
+
+    /// Give the boids from `start` onwards random positions and velocities
+    fn scatter(&mut self, start: usize) {
+        for boid in self.boids[start..].iter_mut() {
+            let angle = self.rng.unit_f32() * core::f32::consts::TAU;
+            let speed = Self::MIN_SPEED + self.rng.unit_f32() * (Self::MAX_SPEED - Self::MIN_SPEED);
+            *boid = Boid {
+                x: self.rng.unit_f32() * W as f32,
+                y: self.rng.unit_f32() * H as f32,
+                vx: speed * libm::cosf(angle),
+                vy: speed * libm::sinf(angle),
+            };
+        }
+    }
+
+    pub fn set_n_boids(&mut self, n_boids: usize) {
+        let n_boids = n_boids.min(MAX_BOIDS);
+        if n_boids > self.n_boids {
+            self.scatter(self.n_boids);
+        }
+        self.n_boids = n_boids;
+    }
+
+    fn bin_coords(x: f32, y: f32) -> (usize, usize) {
+        let bx = (x * BINS_X as f32 / W as f32) as usize;
+        let by = (y * BINS_Y as f32 / H as f32) as usize;
+        (bx.min(BINS_X - 1), by.min(BINS_Y - 1))
+    }
+
+    /// Counting sort the boids into their bins
+    fn bin(&mut self) {
+        let mut counts = [0u16; N_BINS];
+        for boid in self.boids[..self.n_boids].iter() {
+            let (bx, by) = Self::bin_coords(boid.x, boid.y);
+            counts[by * BINS_X + bx] += 1;
+        }
+        self.bin_starts[0] = 0;
+        for (i, count) in counts.iter().enumerate() {
+            self.bin_starts[i + 1] = self.bin_starts[i] + count;
+        }
+        let mut next = [0u16; N_BINS];
+        next.copy_from_slice(&self.bin_starts[..N_BINS]);
+        for (i, boid) in self.boids[..self.n_boids].iter().enumerate() {
+            let (bx, by) = Self::bin_coords(boid.x, boid.y);
+            let bin = by * BINS_X + bx;
+            self.binned[next[bin] as usize] = i as u16;
+            next[bin] += 1;
+        }
+    }
+
+    /// The shortest displacement from a to b on the wrapping board
+    fn wrapped_delta(a: f32, b: f32, size: f32) -> f32 {
+        let d = b - a;
+        if d > size / 2.0 {
+            d - size
+        } else if d < -size / 2.0 {
+            d + size
+        } else {
+            d
+        }
+    }
+
+    /// Wrap a position moved by less than a board width back onto the board
+    fn wrap(v: f32, size: f32) -> f32 {
+        if v < 0.0 {
+            v + size
+        } else if v >= size {
+            v - size
+        } else {
+            v
+        }
+    }
+
+    fn acceleration(&self, i: usize) -> (f32, f32) {
+        let boid = self.boids[i];
+        let (bx, by) = Self::bin_coords(boid.x, boid.y);
+        let (mut sep_x, mut sep_y) = (0.0, 0.0);
+        let (mut vel_x, mut vel_y) = (0.0, 0.0);
+        let (mut off_x, mut off_y) = (0.0, 0.0);
+        let mut count = 0;
+
+        for oy in [BINS_Y - 1, 0, 1] {
+            for ox in [BINS_X - 1, 0, 1] {
+                let bin = ((by + oy) % BINS_Y) * BINS_X + (bx + ox) % BINS_X;
+                let (start, end) = (self.bin_starts[bin], self.bin_starts[bin + 1]);
+                for &j in self.binned[start as usize..end as usize].iter() {
+                    if j as usize == i {
+                        continue;
+                    }
+                    let other = self.boids[j as usize];
+                    let dx = Self::wrapped_delta(boid.x, other.x, W as f32);
+                    let dy = Self::wrapped_delta(boid.y, other.y, H as f32);
+                    let dist_sq = dx * dx + dy * dy;
+                    if dist_sq > Self::VIEW_RADIUS * Self::VIEW_RADIUS {
+                        continue;
+                    }
+                    if dist_sq < Self::SEPARATION_RADIUS * Self::SEPARATION_RADIUS {
+                        let inv = 1.0 / dist_sq.max(0.01);
+                        sep_x -= dx * inv;
+                        sep_y -= dy * inv;
+                    }
+                    vel_x += other.vx;
+                    vel_y += other.vy;
+                    off_x += dx;
+                    off_y += dy;
+                    count += 1;
+                }
+            }
+        }
+
+        let (mut ax, mut ay) = (
+            self.separation * sep_x * Self::MAX_SPEED,
+            self.separation * sep_y * Self::MAX_SPEED,
+        );
+        if count > 0 {
+            let n = count as f32;
+            ax += self.alignment * (vel_x / n - boid.vx) + self.cohesion * off_x / n * 4.0;
+            ay += self.alignment * (vel_y / n - boid.vy) + self.cohesion * off_y / n * 4.0;
+        }
+
+        if let Some((px, py)) = self.predator {
+            let dx = Self::wrapped_delta(px, boid.x, W as f32);
+            let dy = Self::wrapped_delta(py, boid.y, H as f32);
+            let dist = libm::sqrtf(dx * dx + dy * dy).max(0.1);
+            if dist < Self::PREDATOR_RADIUS {
+                let strength = Self::MAX_SPEED * 8.0 * (1.0 - dist / Self::PREDATOR_RADIUS);
+                ax += dx / dist * strength;
+                ay += dy / dist * strength;
+            }
+        }
+
+        (ax, ay)
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.bin();
+        let mut accelerations = [(0.0, 0.0); MAX_BOIDS];
+        for (i, acc) in accelerations[..self.n_boids].iter_mut().enumerate() {
+            *acc = self.acceleration(i);
+        }
+
+        for (boid, (ax, ay)) in self.boids[..self.n_boids].iter_mut().zip(accelerations) {
+            boid.vx += ax * dt;
+            boid.vy += ay * dt;
+            let speed = libm::sqrtf(boid.vx * boid.vx + boid.vy * boid.vy);
+            let clamped = speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+            if speed > 0.0 {
+                boid.vx *= clamped / speed;
+                boid.vy *= clamped / speed;
+            }
+            boid.x = Self::wrap(boid.x + boid.vx * dt, W as f32);
+            boid.y = Self::wrap(boid.y + boid.vy * dt, H as f32);
+        }
+
+        let fade = ((Self::TRAIL_FADE * dt) as u32).clamp(1, 255) as u8;
+        self.trails
+            .buffer_mut()
+            .iter_mut()
+            .for_each(|t| *t = t.saturating_sub(fade));
+        for boid in self.boids[..self.n_boids].iter() {
+            self.trails.set(boid.x as i32, boid.y as i32, u8::MAX);
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum BoidsUpdate {
+    Reset,
+    SetSeparation(f32),
+    SetAlignment(f32),
+    SetCohesion(f32),
+    SetCount(u16),
+    SetPredator { x: f32, y: f32 },
+    RemovePredator,
+}
+
+impl StateUpdate for BoidsUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Boids<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = BoidsUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        // don't let a long frame throw the boids across the board
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.05);
+        self.step(dt);
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(self.trails.iter_with_index().map(|((x, y), t)| {
+            let t = *t as u16;
+            Pixel(Point::new(x, y), Rgb888::new(0, (t * 3 / 5) as u8, t as u8))
+        }));
+        let _ = target.draw_iter(
+            self.boids[..self.n_boids]
+                .iter()
+                .map(|b| Pixel(Point::new(b.x as i32, b.y as i32), Rgb888::WHITE)),
+        );
+        if let Some((x, y)) = self.predator {
+            let _ = target.draw_iter(core::iter::once(Pixel(
+                Point::new(x as i32, y as i32),
+                Rgb888::RED,
+            )));
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            BoidsUpdate::Reset => self.reset(),
+            BoidsUpdate::SetSeparation(w) => self.separation = w,
+            BoidsUpdate::SetAlignment(w) => self.alignment = w,
+            BoidsUpdate::SetCohesion(w) => self.cohesion = w,
+            BoidsUpdate::SetCount(n) => self.set_n_boids(n as usize),
+            BoidsUpdate::SetPredator { x, y } => self.predator = Some((x, y)),
+            BoidsUpdate::RemovePredator => self.predator = None,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Boids::new(200, rng)
+    }
+
+    fn reset(&mut self) {
+        self.scatter(0);
+        self.trails.buffer_mut().fill(0);
+    }
+}
@@ -0,0 +1,338 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    palette::{Palette, lerp},
+};
+
+const MAX_BALLS: usize = 32;
+/// Seconds of each physics step. Small enough that a ball at full speed moves less
+/// than a pixel, so balls can't pass through each other or the walls.
+const STEP_TIME: f32 = 1.0 / 240.0;
+/// The most physics steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 24;
+/// Pixels per second
+const MAX_SPEED: f32 = 200.0;
+/// Pixels per second squared
+const MAX_GRAVITY: f32 = 200.0;
+/// Below this closing speed, in pixels per second, collisions don't bounce, so that
+/// balls resting on each other settle instead of jittering
+const REST_SPEED: f32 = 3.0;
+/// How many times the collisions are resolved each step. Going over them more than
+/// once lets pushes pass through a pile of balls.
+const SOLVER_ITERATIONS: usize = 2;
+const MIN_RADIUS: f32 = 1.5;
+const MAX_RADIUS: f32 = 4.0;
+/// Pixels per second of the random push each ball gets from a shake
+const SHAKE_SPEED: f32 = 80.0;
+
+#[derive(Copy, Clone)]
+struct Ball {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    radius: f32,
+    /// 1 / mass, where the mass is the area
+    inverse_mass: f32,
+    /// where the ball's colour is in the palette
+    colour: f32,
+}
+
+/// Balls bouncing around the panel under gravity, off the walls and each other.
+///
+/// The physics runs in fixed steps however long the frames are, which keeps the
+/// collisions stable. Gravity can point any way, so the phone can send its tilt to
+/// roll the balls around.
+pub struct BouncingBalls<Rng, const W: usize, const H: usize> {
+    balls: [Ball; MAX_BALLS],
+    ball_count: usize,
+    /// pixels per second squared
+    gravity: (f32, f32),
+    /// the fraction of the closing speed kept after a bounce
+    restitution: f32,
+    palette: Palette,
+    time_banked: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> BouncingBalls<Rng, W, H> {
+    pub fn new(ball_count: usize, rng: Rng) -> Self {
+        let mut this = BouncingBalls {
+            balls: [Ball {
+                x: 0.0,
+                y: 0.0,
+                vx: 0.0,
+                vy: 0.0,
+                radius: 0.0,
+                inverse_mass: 0.0,
+                colour: 0.0,
+            }; MAX_BALLS],
+            ball_count: ball_count.clamp(1, MAX_BALLS),
+            gravity: (0.0, 60.0),
+            restitution: 0.85,
+            palette: Palette::Rainbow,
+            time_banked: 0.0,
+            rng,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    /// Add balls from `start` onwards, trying to find a space where they don't
+    /// overlap the balls already there
+    fn add_balls(&mut self, start: usize) {
+        for i in start..self.ball_count {
+            let radius = MIN_RADIUS + self.rng.unit_f32() * (MAX_RADIUS - MIN_RADIUS);
+            let mut position = (0.0, 0.0);
+            for _ in 0..20 {
+                position = (
+                    radius + self.rng.unit_f32() * (W as f32 - 2.0 * radius),
+                    radius + self.rng.unit_f32() * (H as f32 - 2.0 * radius),
+                );
+                let overlaps = self.balls[..i].iter().any(|other| {
+                    let (dx, dy) = (position.0 - other.x, position.1 - other.y);
+                    let gap = radius + other.radius;
+                    dx * dx + dy * dy < gap * gap
+                });
+                if !overlaps {
+                    break;
+                }
+            }
+            let angle = self.rng.unit_f32() * core::f32::consts::TAU;
+            let speed = self.rng.unit_f32() * SHAKE_SPEED;
+            self.balls[i] = Ball {
+                x: position.0,
+                y: position.1,
+                vx: speed * libm::cosf(angle),
+                vy: speed * libm::sinf(angle),
+                radius,
+                inverse_mass: 1.0 / (radius * radius),
+                colour: self.rng.unit_f32(),
+            };
+        }
+    }
+
+    fn shake(&mut self) {
+        for i in 0..self.ball_count {
+            let angle = self.rng.unit_f32() * core::f32::consts::TAU;
+            let speed = SHAKE_SPEED * (0.5 + 0.5 * self.rng.unit_f32());
+            self.balls[i].vx += speed * libm::cosf(angle);
+            self.balls[i].vy += speed * libm::sinf(angle);
+        }
+    }
+
+    /// Push a pair of overlapping balls apart, and bounce them if they're moving
+    /// towards each other
+    fn collide(a: &mut Ball, b: &mut Ball, restitution: f32) {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let gap = a.radius + b.radius;
+        let distance2 = dx * dx + dy * dy;
+        if distance2 >= gap * gap {
+            return;
+        }
+        let distance = libm::sqrtf(distance2);
+        // balls exactly on top of each other are pushed apart sideways
+        let (nx, ny) = if distance > 1e-4 {
+            (dx / distance, dy / distance)
+        } else {
+            (1.0, 0.0)
+        };
+        let total_inverse_mass = a.inverse_mass + b.inverse_mass;
+
+        // move each ball out of the overlap in proportion to how light it is
+        let overlap = (gap - distance) / total_inverse_mass;
+        a.x -= nx * overlap * a.inverse_mass;
+        a.y -= ny * overlap * a.inverse_mass;
+        b.x += nx * overlap * b.inverse_mass;
+        b.y += ny * overlap * b.inverse_mass;
+
+        let closing = (b.vx - a.vx) * nx + (b.vy - a.vy) * ny;
+        if closing >= 0.0 {
+            return;
+        }
+        let restitution = if -closing < REST_SPEED {
+            0.0
+        } else {
+            restitution
+        };
+        let impulse = -(1.0 + restitution) * closing / total_inverse_mass;
+        a.vx -= impulse * a.inverse_mass * nx;
+        a.vy -= impulse * a.inverse_mass * ny;
+        b.vx += impulse * b.inverse_mass * nx;
+        b.vy += impulse * b.inverse_mass * ny;
+    }
+
+    /// Keep a ball inside the panel, bouncing it off any wall it's moving into
+    fn collide_walls(ball: &mut Ball, restitution: f32) {
+        let bounce = |v: f32| {
+            if -v < REST_SPEED {
+                0.0
+            } else {
+                -v * restitution
+            }
+        };
+        let (max_x, max_y) = (W as f32 - ball.radius, H as f32 - ball.radius);
+        if ball.x < ball.radius {
+            ball.x = ball.radius;
+            if ball.vx < 0.0 {
+                ball.vx = bounce(ball.vx);
+            }
+        } else if ball.x > max_x {
+            ball.x = max_x;
+            if ball.vx > 0.0 {
+                ball.vx = -bounce(-ball.vx);
+            }
+        }
+        if ball.y < ball.radius {
+            ball.y = ball.radius;
+            if ball.vy < 0.0 {
+                ball.vy = bounce(ball.vy);
+            }
+        } else if ball.y > max_y {
+            ball.y = max_y;
+            if ball.vy > 0.0 {
+                ball.vy = -bounce(-ball.vy);
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        let (gx, gy) = self.gravity;
+        for ball in self.balls[..self.ball_count].iter_mut() {
+            ball.vx += gx * STEP_TIME;
+            ball.vy += gy * STEP_TIME;
+            let speed2 = ball.vx * ball.vx + ball.vy * ball.vy;
+            if speed2 > MAX_SPEED * MAX_SPEED {
+                let scale = MAX_SPEED / libm::sqrtf(speed2);
+                ball.vx *= scale;
+                ball.vy *= scale;
+            }
+            ball.x += ball.vx * STEP_TIME;
+            ball.y += ball.vy * STEP_TIME;
+        }
+
+        let balls = &mut self.balls[..self.ball_count];
+        for _ in 0..SOLVER_ITERATIONS {
+            for i in 1..balls.len() {
+                let (before, after) = balls.split_at_mut(i);
+                let b = &mut after[0];
+                for a in before.iter_mut() {
+                    Self::collide(a, b, self.restitution);
+                }
+            }
+            // the walls go last so that nothing is left pushed through them
+            for ball in balls.iter_mut() {
+                Self::collide_walls(ball, self.restitution);
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum BouncingBallsUpdate {
+    Reset,
+    SetBalls(u8),
+    /// In pixels per second squared, with y pointing down the panel. Send the phone's
+    /// tilt here to roll the balls around.
+    SetGravity {
+        x: f32,
+        y: f32,
+    },
+    /// How bouncy the balls are, from 0 for not at all to 1 for losing no speed
+    SetRestitution(f32),
+    /// Throw all the balls about
+    Shake,
+    SetPalette(Palette),
+}
+
+impl StateUpdate for BouncingBallsUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for BouncingBalls<Rng, W, H> {
+    type StateUpdate = BouncingBallsUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        self.time_banked = (self.time_banked + dt).min(STEP_TIME * MAX_STEPS as f32);
+        while self.time_banked >= STEP_TIME {
+            self.time_banked -= STEP_TIME;
+            self.step();
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        for ball in self.balls[..self.ball_count].iter() {
+            let colour = self.palette.sample(ball.colour);
+            let radius2 = ball.radius * ball.radius;
+            let (left, right) = (
+                libm::floorf(ball.x - ball.radius) as i32,
+                libm::ceilf(ball.x + ball.radius) as i32,
+            );
+            let (top, bottom) = (
+                libm::floorf(ball.y - ball.radius) as i32,
+                libm::ceilf(ball.y + ball.radius) as i32,
+            );
+            let pixels = (top..bottom).flat_map(|y| (left..right).map(move |x| (x, y)));
+            let _ = target.draw_iter(pixels.filter_map(|(x, y)| {
+                let (dx, dy) = (x as f32 + 0.5 - ball.x, y as f32 + 0.5 - ball.y);
+                let d2 = dx * dx + dy * dy;
+                if d2 > radius2 {
+                    return None;
+                }
+                // darken towards the edge, with a highlight up and to the left
+                let shade = lerp(Rgb888::BLACK, colour, 1.0 - 0.6 * d2 / radius2);
+                let (hx, hy) = (dx + 0.35 * ball.radius, dy + 0.35 * ball.radius);
+                let highlight = (1.0 - 2.5 * (hx * hx + hy * hy) / radius2).max(0.0);
+                Some(Pixel(
+                    Point::new(x, y),
+                    lerp(shade, Rgb888::WHITE, 0.6 * highlight),
+                ))
+            }));
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            BouncingBallsUpdate::Reset => self.reset(),
+            BouncingBallsUpdate::SetBalls(count) => {
+                let count = (count as usize).clamp(1, MAX_BALLS);
+                let old = self.ball_count;
+                self.ball_count = count;
+                self.add_balls(old);
+            }
+            BouncingBallsUpdate::SetGravity { x, y } => {
+                self.gravity = (
+                    x.clamp(-MAX_GRAVITY, MAX_GRAVITY),
+                    y.clamp(-MAX_GRAVITY, MAX_GRAVITY),
+                )
+            }
+            BouncingBallsUpdate::SetRestitution(restitution) => {
+                self.restitution = restitution.clamp(0.0, 1.0)
+            }
+            BouncingBallsUpdate::Shake => self.shake(),
+            BouncingBallsUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        BouncingBalls::new(12, rng)
+    }
+
+    fn reset(&mut self) {
+        self.time_banked = 0.0;
+        self.add_balls(0);
+    }
+}
@@ -0,0 +1,361 @@
+use core::fmt::Write;
+
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    text::{TextBuffer, draw_text, draw_text_centred},
+};
+
+const BRICK_W: usize = 4;
+const BRICK_H: usize = 3;
+const ROWS: usize = 5;
+const MAX_COLUMNS: usize = 32;
+/// The top of the bricks, below the score
+const BRICKS_TOP: f32 = 7.0;
+const PADDLE_W: f32 = 8.0;
+/// The fastest the paddle can move, in pixels per second
+const PADDLE_SPEED: f32 = 45.0;
+/// The steepest the ball can leave the paddle, as a fraction of its speed
+const MAX_BOUNCE: f32 = 0.8;
+const LIVES: u8 = 3;
+/// Seconds between losing a ball and serving the next one
+const SERVE_DELAY: f32 = 1.0;
+/// Seconds the game over screen is shown for
+const GAME_OVER_DELAY: f32 = 3.0;
+
+/// The colour of each row of bricks, from the top
+const ROW_COLOURS: [Rgb888; ROWS] = [
+    Rgb888::new(255, 40, 40),
+    Rgb888::new(255, 140, 0),
+    Rgb888::new(240, 230, 0),
+    Rgb888::new(40, 220, 40),
+    Rgb888::new(40, 120, 255),
+];
+
+/// Breakout, played by the computer.
+///
+/// The paddle aims for where the ball will come down, plus a random error that's
+/// picked each time the ball starts falling. The error grows with the imperfection
+/// setting, which is what makes it drop the ball.
+pub struct Breakout<Rng, const W: usize, const H: usize> {
+    bricks: [[bool; MAX_COLUMNS]; ROWS],
+    /// the centre of the paddle
+    paddle: f32,
+    ball: (f32, f32),
+    /// pixels per second
+    velocity: (f32, f32),
+    /// how far off the paddle is aiming this time, in pixels
+    error: f32,
+    score: u32,
+    lives: u8,
+    level: u8,
+    /// seconds until the ball is served, or the game restarts after a game over
+    waiting: f32,
+    game_over: bool,
+    /// 0 plays perfectly, 1 misses a lot
+    imperfection: f32,
+    serve_speed: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Breakout<Rng, W, H> {
+    const COLUMNS: usize = if W / BRICK_W < MAX_COLUMNS {
+        W / BRICK_W
+    } else {
+        MAX_COLUMNS
+    };
+    /// The y position of the top of the paddle, that the ball bounces off
+    const PADDLE_Y: f32 = H as f32 - 2.0;
+
+    pub fn new(rng: Rng) -> Self {
+        let mut this = Breakout {
+            bricks: [[true; MAX_COLUMNS]; ROWS],
+            paddle: W as f32 / 2.0,
+            ball: (0.0, 0.0),
+            velocity: (0.0, 0.0),
+            error: 0.0,
+            score: 0,
+            lives: LIVES,
+            level: 0,
+            waiting: 0.0,
+            game_over: false,
+            imperfection: 0.35,
+            serve_speed: 30.0,
+            rng,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    /// How fast the ball goes, getting faster with each level
+    fn speed(&self) -> f32 {
+        self.serve_speed * (1.0 + 0.15 * self.level as f32)
+    }
+
+    fn fill_bricks(&mut self) {
+        self.bricks = [[true; MAX_COLUMNS]; ROWS];
+    }
+
+    fn serve(&mut self) {
+        self.ball = (self.paddle, Self::PADDLE_Y - 1.0);
+        let speed = self.speed();
+        let sideways = (self.rng.unit_f32() - 0.5) * MAX_BOUNCE;
+        self.velocity = (
+            speed * sideways,
+            -speed * libm::sqrtf(1.0 - sideways * sideways),
+        );
+        self.waiting = SERVE_DELAY;
+    }
+
+    fn pick_error(&mut self) {
+        let reach = PADDLE_W / 2.0 * (0.3 + 2.6 * self.imperfection);
+        self.error = (self.rng.unit_f32() * 2.0 - 1.0) * reach;
+    }
+
+    /// The brick at a position, as (row, column)
+    fn brick_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        if x < 0.0 || y < BRICKS_TOP {
+            return None;
+        }
+        let column = x as usize / BRICK_W;
+        let row = (y - BRICKS_TOP) as usize / BRICK_H;
+        (row < ROWS && column < Self::COLUMNS && self.bricks[row][column]).then_some((row, column))
+    }
+
+    fn break_brick(&mut self, (row, column): (usize, usize)) {
+        self.bricks[row][column] = false;
+        // the higher rows are worth more
+        self.score += (ROWS - row) as u32 * 10;
+    }
+
+    /// Where the ball will cross the paddle, following the bounces off the side walls
+    fn predict(&self) -> f32 {
+        let (x, y) = self.ball;
+        let (vx, vy) = self.velocity;
+        let time = (Self::PADDLE_Y - y) / vy;
+        let width = W as f32 - 1.0;
+        // unfold the bounces into a straight line, then fold it back up
+        let folded = libm::fmodf(libm::fabsf(x + vx * time), 2.0 * width);
+        if folded > width {
+            2.0 * width - folded
+        } else {
+            folded
+        }
+    }
+
+    fn move_paddle(&mut self, dt: f32) {
+        let target = if self.velocity.1 > 0.0 && self.waiting <= 0.0 {
+            self.predict() + self.error
+        } else {
+            self.ball.0
+        };
+        let step = PADDLE_SPEED * dt;
+        self.paddle += (target - self.paddle).clamp(-step, step);
+        self.paddle = self.paddle.clamp(PADDLE_W / 2.0, W as f32 - PADDLE_W / 2.0);
+    }
+
+    fn move_ball(&mut self, dt: f32) {
+        let (old_x, old_y) = self.ball;
+        let (mut vx, mut vy) = self.velocity;
+        let (mut x, mut y) = (old_x + vx * dt, old_y + vy * dt);
+
+        let right = W as f32 - 0.01;
+        if x < 0.0 {
+            x = -x;
+            vx = -vx;
+        } else if x > right {
+            x = 2.0 * right - x;
+            vx = -vx;
+        }
+        if y < 0.0 {
+            y = -y;
+            vy = -vy;
+        }
+
+        if let Some(brick) = self.brick_at(x, y) {
+            // work out which side of the brick was hit from which move took the ball into it
+            let across = self.brick_at(x, old_y);
+            let down = self.brick_at(old_x, y);
+            match (across, down) {
+                (Some(_), None) => vx = -vx,
+                (None, Some(_)) => vy = -vy,
+                _ => {
+                    vx = -vx;
+                    vy = -vy;
+                }
+            }
+            self.break_brick(brick);
+            (x, y) = (old_x, old_y);
+        }
+
+        if vy > 0.0 && old_y < Self::PADDLE_Y && y >= Self::PADDLE_Y {
+            let offset = (x - self.paddle) / (PADDLE_W / 2.0);
+            if libm::fabsf(offset) <= 1.15 {
+                let speed = self.speed();
+                // where the ball hits on the paddle decides the angle it leaves at
+                let sideways = offset.clamp(-1.0, 1.0) * MAX_BOUNCE;
+                vx = speed * sideways;
+                vy = -speed * libm::sqrtf(1.0 - sideways * sideways);
+                y = 2.0 * Self::PADDLE_Y - y;
+            }
+        }
+
+        if vy > 0.0 && self.velocity.1 <= 0.0 {
+            // each time the ball starts coming down the paddle aims somewhere else
+            self.pick_error();
+        }
+        self.ball = (x, y);
+        self.velocity = (vx, vy);
+
+        if y > H as f32 {
+            self.lives -= 1;
+            if self.lives == 0 {
+                self.game_over = true;
+                self.waiting = GAME_OVER_DELAY;
+            } else {
+                self.serve();
+            }
+        } else if self
+            .bricks
+            .iter()
+            .all(|row| !row[..Self::COLUMNS].contains(&true))
+        {
+            self.level = self.level.saturating_add(1);
+            self.fill_bricks();
+            self.serve();
+        }
+    }
+
+    fn step(&mut self, dt: f32) {
+        if self.waiting > 0.0 {
+            self.waiting -= dt;
+            if self.waiting <= 0.0 && self.game_over {
+                self.reset();
+            }
+            // the ball sits on the paddle until it's served
+            if !self.game_over {
+                self.ball = (self.paddle, Self::PADDLE_Y - 1.0);
+            }
+        } else {
+            self.move_ball(dt);
+        }
+        self.move_paddle(dt);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum BreakoutUpdate {
+    Reset,
+    /// How often the paddle misses, from 0 for never to 1 for a lot
+    SetImperfection(f32),
+    /// The speed of the ball on the first level, in pixels per second
+    SetServeSpeed(f32),
+}
+
+impl StateUpdate for BreakoutUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Breakout<Rng, W, H> {
+    type StateUpdate = BreakoutUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let mut dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        // small steps so the ball can't skip through a brick
+        while dt > 0.0 {
+            let step = dt.min(0.005);
+            self.step(step);
+            dt -= step;
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let white = Rgb888::new(255, 255, 255);
+        for (row, bricks) in self.bricks.iter().enumerate() {
+            let y = BRICKS_TOP as i32 + (row * BRICK_H) as i32;
+            let _ = target.draw_iter(
+                bricks[..Self::COLUMNS]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, brick)| **brick)
+                    .flat_map(|(column, _)| {
+                        let x = (column * BRICK_W) as i32;
+                        // leave a gap on the right and bottom of each brick
+                        (0..(BRICK_W - 1) * (BRICK_H - 1)).map(move |i| {
+                            Point::new(
+                                x + (i % (BRICK_W - 1)) as i32,
+                                y + (i / (BRICK_W - 1)) as i32,
+                            )
+                        })
+                    })
+                    .map(|p| Pixel(p, ROW_COLOURS[row])),
+            );
+        }
+
+        let mut score: TextBuffer<10> = TextBuffer::new();
+        let _ = write!(score, "{}", self.score);
+        draw_text(target, score.as_str(), Point::new(0, 0), white);
+        // a dot for each ball left
+        let _ = target.draw_iter((0..self.lives as i32).map(|i| {
+            Pixel(
+                Point::new(W as i32 - 2 - i * 3, 2),
+                Rgb888::new(255, 80, 80),
+            )
+        }));
+
+        if self.game_over {
+            draw_text_centred(
+                target,
+                "GAME OVER",
+                Point::new(W as i32 / 2, H as i32 - 12),
+                Rgb888::new(255, 40, 40),
+            );
+            return;
+        }
+
+        let left = libm::roundf(self.paddle - PADDLE_W / 2.0) as i32;
+        let _ = target.draw_iter((left..left + PADDLE_W as i32).map(|x| {
+            Pixel(
+                Point::new(x, Self::PADDLE_Y as i32 + 1),
+                Rgb888::new(200, 200, 255),
+            )
+        }));
+        let (x, y) = self.ball;
+        let _ = target.draw_iter([Pixel(
+            Point::new(libm::floorf(x) as i32, libm::floorf(y) as i32),
+            white,
+        )]);
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            BreakoutUpdate::Reset => self.reset(),
+            BreakoutUpdate::SetImperfection(imperfection) => {
+                self.imperfection = imperfection.clamp(0.0, 1.0)
+            }
+            BreakoutUpdate::SetServeSpeed(speed) => self.serve_speed = speed.clamp(5.0, 80.0),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Breakout::new(rng)
+    }
+
+    fn reset(&mut self) {
+        self.fill_bricks();
+        self.score = 0;
+        self.lives = LIVES;
+        self.level = 0;
+        self.game_over = false;
+        self.paddle = W as f32 / 2.0;
+        self.serve();
+    }
+}
@@ -0,0 +1,608 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Drawable, Point, Primitive, Size},
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    palette::Palette,
+    text::{TextBuffer, draw_text, draw_text_centred},
+};
+
+const DAY_US: i64 = 86_400_000_000;
+/// Syncs closer together than this don't change the drift correction, because the
+/// delay in getting the time from the phone would swamp the drift
+const MIN_DRIFT_INTERVAL_US: u64 = 3_600_000_000;
+/// No real crystal is this far out, so anything bigger is a bad sync
+const MAX_CORRECTION_PPM: i64 = 500;
+
+/// Keeps the time of day by adding up the time between updates, corrected for the
+/// drift of the local clock using the times the phone sends.
+///
+/// This doesn't read a clock itself, so it can be driven by a fake time source on
+/// the host.
+pub struct WallClock {
+    /// microseconds since midnight
+    time_us: i64,
+    synced: bool,
+    /// uncorrected local microseconds since the drift was last measured
+    since_measured_us: u64,
+    /// the error fixed by syncs since the drift was last measured, which were too
+    /// close together to measure it themselves
+    unmeasured_error_us: i64,
+    /// how much time is added on to the local time, in parts per million
+    correction_ppm: i64,
+    /// the fraction of a microsecond of correction that hasn't been added on yet,
+    /// in millionths
+    correction_remainder: i64,
+}
+
+/// A time of day, from a [WallClock]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TimeOfDay {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub microseconds: u32,
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        WallClock {
+            time_us: 0,
+            synced: false,
+            since_measured_us: 0,
+            unmeasured_error_us: 0,
+            correction_ppm: 0,
+            correction_remainder: 0,
+        }
+    }
+
+    /// Move the clock on by some local time
+    pub fn advance(&mut self, delta_time_us: u32) {
+        self.since_measured_us += delta_time_us as u64;
+        self.correction_remainder += delta_time_us as i64 * self.correction_ppm;
+        let correction = self.correction_remainder / 1_000_000;
+        self.correction_remainder -= correction * 1_000_000;
+        self.time_us = (self.time_us + delta_time_us as i64 + correction).rem_euclid(DAY_US);
+    }
+
+    /// Set the time of day, in microseconds since midnight. If the drift hasn't been
+    /// measured for long enough, the difference from the time this clock had is used
+    /// to correct it.
+    pub fn sync(&mut self, time_us: u64) {
+        let time_us = (time_us % DAY_US as u64) as i64;
+        if self.synced {
+            // the error, going the short way around midnight
+            let mut error = time_us - self.time_us;
+            if error > DAY_US / 2 {
+                error -= DAY_US;
+            } else if error < -DAY_US / 2 {
+                error += DAY_US;
+            }
+            self.unmeasured_error_us += error;
+            if self.since_measured_us >= MIN_DRIFT_INTERVAL_US {
+                let drift_ppm =
+                    self.unmeasured_error_us * 1_000_000 / self.since_measured_us as i64;
+                self.correction_ppm = (self.correction_ppm + drift_ppm)
+                    .clamp(-MAX_CORRECTION_PPM, MAX_CORRECTION_PPM);
+                self.since_measured_us = 0;
+                self.unmeasured_error_us = 0;
+            }
+        } else {
+            self.since_measured_us = 0;
+            self.unmeasured_error_us = 0;
+        }
+        self.time_us = time_us;
+        self.correction_remainder = 0;
+        self.synced = true;
+    }
+
+    /// Whether the time has been set since starting up
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// The correction for the drift of the local clock, in parts per million
+    pub fn correction_ppm(&self) -> i32 {
+        self.correction_ppm as i32
+    }
+
+    pub fn time(&self) -> TimeOfDay {
+        let seconds = self.time_us / 1_000_000;
+        TimeOfDay {
+            hours: (seconds / 3600) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            microseconds: (self.time_us % 1_000_000) as u32,
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ClockFace {
+    /// Large seven segment digits
+    Digits,
+    /// Hands on a round face
+    Analog,
+    /// The time in words to the nearest five minutes, with a dot for each minute over.
+    /// Always uses the 12 hour clock.
+    Words,
+}
+
+/// The segments lit for each digit, with bit 0 to 6 being segments a to g
+const SEGMENTS: [u8; 10] = [0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f];
+const DIGIT_W: i32 = 10;
+const DIGIT_H: i32 = 20;
+const SEGMENT_THICKNESS: i32 = 2;
+
+const HOUR_WORDS: [&str; 12] = [
+    "TWELVE", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE", "TEN",
+    "ELEVEN",
+];
+/// The words for each five minutes past the hour, and whether it's past or to the hour
+const MINUTE_WORDS: [(&str, &str); 12] = [
+    ("", "O'CLOCK"),
+    ("FIVE", "PAST"),
+    ("TEN", "PAST"),
+    ("QUARTER", "PAST"),
+    ("TWENTY", "PAST"),
+    ("TWENTY FIVE", "PAST"),
+    ("HALF", "PAST"),
+    ("TWENTY FIVE", "TO"),
+    ("TWENTY", "TO"),
+    ("QUARTER", "TO"),
+    ("TEN", "TO"),
+    ("FIVE", "TO"),
+];
+
+/// The minute words, "PAST" or "TO", and the hour words for a time, to the five
+/// minutes below it
+fn time_in_words(time: TimeOfDay) -> (&'static str, &'static str, &'static str) {
+    let five = (time.minutes / 5) as usize;
+    let (minute_words, past_or_to) = MINUTE_WORDS[five];
+    // from twenty five to, it's counted to the next hour
+    let hour = if five >= 7 {
+        time.hours + 1
+    } else {
+        time.hours
+    };
+    (minute_words, past_or_to, HOUR_WORDS[hour as usize % 12])
+}
+
+struct Colours {
+    primary: Rgb888,
+    secondary: Rgb888,
+    accent: Rgb888,
+}
+
+fn fill<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+    target: &mut D,
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+    colour: Rgb888,
+) {
+    let _ = Rectangle::new(Point::new(x, y), Size::new(w as u32, h as u32))
+        .into_styled(PrimitiveStyle::with_fill(colour))
+        .draw(target);
+}
+
+/// Draw a seven segment digit with its top left corner at (x, y)
+fn draw_digit<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+    target: &mut D,
+    x: i32,
+    y: i32,
+    digit: u8,
+    colour: Rgb888,
+) {
+    let t = SEGMENT_THICKNESS;
+    let middle = DIGIT_H / 2 - t / 2;
+    let upper = middle - t;
+    let lower = DIGIT_H - t - (middle + t);
+    // (x, y, w, h) of segments a to g
+    let segments = [
+        (t, 0, DIGIT_W - 2 * t, t),
+        (DIGIT_W - t, t, t, upper),
+        (DIGIT_W - t, middle + t, t, lower),
+        (t, DIGIT_H - t, DIGIT_W - 2 * t, t),
+        (0, middle + t, t, lower),
+        (0, t, t, upper),
+        (t, middle, DIGIT_W - 2 * t, t),
+    ];
+    let lit = SEGMENTS[digit as usize % 10];
+    for (i, (sx, sy, w, h)) in segments.into_iter().enumerate() {
+        if lit & (1 << i) != 0 {
+            fill(target, x + sx, y + sy, w, h, colour);
+        }
+    }
+}
+
+/// A clock, showing the time set from the phone as digits, hands or words.
+///
+/// The time is kept by a [WallClock], so it gets more accurate the longer it runs
+/// with the phone setting the time now and again. Until the time is first set it
+/// blinks.
+pub struct Clock<const W: usize, const H: usize> {
+    clock: WallClock,
+    face: ClockFace,
+    palette: Palette,
+    twenty_four_hour: bool,
+    show_seconds: bool,
+}
+
+impl<const W: usize, const H: usize> Clock<W, H> {
+    pub fn new(face: ClockFace) -> Self {
+        Clock {
+            clock: WallClock::new(),
+            face,
+            palette: Palette::Fire,
+            twenty_four_hour: true,
+            show_seconds: true,
+        }
+    }
+
+    pub fn wall_clock(&self) -> &WallClock {
+        &self.clock
+    }
+
+    fn colours(&self) -> Colours {
+        Colours {
+            primary: self.palette.sample(0.85),
+            secondary: self.palette.sample(0.45),
+            accent: self.palette.sample(0.65),
+        }
+    }
+
+    /// The hour as it's shown, on the 12 or 24 hour clock
+    fn display_hour(&self, time: TimeOfDay) -> u8 {
+        if self.twenty_four_hour {
+            time.hours
+        } else {
+            match time.hours % 12 {
+                0 => 12,
+                hour => hour,
+            }
+        }
+    }
+
+    fn draw_digits<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        &self,
+        target: &mut D,
+        time: TimeOfDay,
+        colours: &Colours,
+    ) {
+        let gap = 2;
+        let colon_w = 2;
+        let total_w = 4 * DIGIT_W + colon_w + 4 * gap;
+        let mut x = (W as i32 - total_w) / 2;
+        let y = if self.show_seconds { 2 } else { 6 };
+
+        let hour = self.display_hour(time);
+        // no leading zero on the 12 hour clock
+        if self.twenty_four_hour || hour >= 10 {
+            draw_digit(target, x, y, hour / 10, colours.primary);
+        }
+        x += DIGIT_W + gap;
+        draw_digit(target, x, y, hour % 10, colours.primary);
+        x += DIGIT_W + gap;
+        if time.microseconds < 500_000 {
+            fill(target, x, y + 5, colon_w, 2, colours.accent);
+            fill(target, x, y + DIGIT_H - 7, colon_w, 2, colours.accent);
+        }
+        x += colon_w + gap;
+        draw_digit(target, x, y, time.minutes / 10, colours.primary);
+        x += DIGIT_W + gap;
+        draw_digit(target, x, y, time.minutes % 10, colours.primary);
+
+        let bottom = y + DIGIT_H + 3;
+        if self.show_seconds {
+            let mut seconds: TextBuffer<2> = TextBuffer::new();
+            let _ = write!(seconds, "{:02}", time.seconds);
+            draw_text_centred(
+                target,
+                seconds.as_str(),
+                Point::new(W as i32 / 2, bottom),
+                colours.secondary,
+            );
+        }
+        if !self.twenty_four_hour {
+            let am_pm = if time.hours < 12 { "AM" } else { "PM" };
+            draw_text(
+                target,
+                am_pm,
+                Point::new(x + DIGIT_W - 8, bottom),
+                colours.secondary,
+            );
+        }
+    }
+
+    fn draw_analog<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        &self,
+        target: &mut D,
+        time: TimeOfDay,
+        colours: &Colours,
+    ) {
+        let centre = Point::new(W as i32 / 2, H as i32 / 2);
+        let radius = (W.min(H) as f32 - 2.0) / 2.0;
+        let at = |fraction: f32, length: f32| {
+            let angle = fraction * core::f32::consts::TAU;
+            Point::new(
+                centre.x + libm::roundf(length * libm::sinf(angle)) as i32,
+                centre.y - libm::roundf(length * libm::cosf(angle)) as i32,
+            )
+        };
+
+        // a mark for each hour, bigger at the quarters
+        for hour in 0..12 {
+            let point = at(hour as f32 / 12.0, radius);
+            if hour % 3 == 0 {
+                let _ = Line::new(at(hour as f32 / 12.0, radius - 2.0), point)
+                    .into_styled(PrimitiveStyle::with_stroke(colours.accent, 1))
+                    .draw(target);
+            } else {
+                let _ = target.draw_iter([Pixel(point, colours.secondary)]);
+            }
+        }
+
+        let seconds = time.seconds as f32 + time.microseconds as f32 / 1_000_000.0;
+        let minutes = time.minutes as f32 + seconds / 60.0;
+        let hours = (time.hours % 12) as f32 + minutes / 60.0;
+        let mut hand = |fraction: f32, length: f32, colour: Rgb888| {
+            let _ = Line::new(centre, at(fraction, length))
+                .into_styled(PrimitiveStyle::with_stroke(colour, 1))
+                .draw(target);
+        };
+        hand(hours / 12.0, radius * 0.5, colours.primary);
+        hand(minutes / 60.0, radius * 0.8, colours.primary);
+        if self.show_seconds {
+            hand(seconds / 60.0, radius * 0.9, colours.accent);
+        }
+    }
+
+    fn draw_words<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        &self,
+        target: &mut D,
+        time: TimeOfDay,
+        colours: &Colours,
+    ) {
+        let (w, h) = (W as i32, H as i32);
+        let (minute_words, past_or_to, hour_words) = time_in_words(time);
+
+        let mut lines = [("IT IS", colours.secondary); 4];
+        let count = if minute_words.is_empty() {
+            lines[1] = (hour_words, colours.primary);
+            lines[2] = (past_or_to, colours.secondary);
+            3
+        } else {
+            lines[1] = (minute_words, colours.primary);
+            lines[2] = (past_or_to, colours.secondary);
+            lines[3] = (hour_words, colours.primary);
+            4
+        };
+        let line_h = 7;
+        let top = (h - count * line_h) / 2;
+        for (i, (text, colour)) in lines[..count as usize].iter().enumerate() {
+            draw_text_centred(
+                target,
+                text,
+                Point::new(w / 2, top + i as i32 * line_h),
+                *colour,
+            );
+        }
+
+        // a dot in a corner for each minute past the five
+        let corners = [(0, 0), (w - 2, 0), (w - 2, h - 2), (0, h - 2)];
+        for &(x, y) in corners.iter().take((time.minutes % 5) as usize) {
+            fill(target, x, y, 2, 2, colours.accent);
+        }
+
+        if self.show_seconds {
+            let length = (w - 8) * time.seconds as i32 / 59;
+            let _ = target
+                .draw_iter((4..4 + length).map(|x| Pixel(Point::new(x, h - 1), colours.secondary)));
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum ClockUpdate {
+    /// Forget the time and the drift correction
+    Reset,
+    /// The time of day, in milliseconds since midnight. Setting it every hour or so
+    /// lets the clock correct its drift. The time is lost when switching to another
+    /// visualisation, so it needs setting each time the clock is shown.
+    SetTime {
+        milliseconds: u32,
+    },
+    SetFace(ClockFace),
+    SetPalette(Palette),
+    SetTwentyFourHour(bool),
+    SetShowSeconds(bool),
+}
+
+impl StateUpdate for ClockUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Clock<W, H> {
+    type StateUpdate = ClockUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        self.clock.advance(delta_time_us);
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let time = self.clock.time();
+        // blink until the time is set, like a clock that's lost power
+        if !self.clock.is_synced() && time.microseconds >= 500_000 {
+            return;
+        }
+        let colours = self.colours();
+        match self.face {
+            ClockFace::Digits => self.draw_digits(target, time, &colours),
+            ClockFace::Analog => self.draw_analog(target, time, &colours),
+            ClockFace::Words => self.draw_words(target, time, &colours),
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            ClockUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            ClockUpdate::SetTime { milliseconds } => self.clock.sync(milliseconds as u64 * 1000),
+            ClockUpdate::SetFace(face) => self.face = face,
+            ClockUpdate::SetPalette(palette) => self.palette = palette,
+            ClockUpdate::SetTwentyFourHour(twenty_four_hour) => {
+                self.twenty_four_hour = twenty_four_hour
+            }
+            ClockUpdate::SetShowSeconds(show_seconds) => self.show_seconds = show_seconds,
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Clock::new(ClockFace::Digits)
+    }
+
+    fn reset(&mut self) {
+        self.clock = WallClock::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_US: u64 = 1_000_000;
+    const HOUR_US: u64 = 3600 * SECOND_US;
+
+    fn us(hours: u64, minutes: u64, seconds: u64) -> u64 {
+        (hours * 3600 + minutes * 60 + seconds) * SECOND_US
+    }
+
+    fn time(hours: u8, minutes: u8) -> TimeOfDay {
+        TimeOfDay {
+            hours,
+            minutes,
+            seconds: 0,
+            microseconds: 0,
+        }
+    }
+
+    #[test]
+    fn advances_from_the_time_set() {
+        let mut clock = WallClock::new();
+        assert!(!clock.is_synced());
+        clock.sync(us(10, 0, 0));
+        assert!(clock.is_synced());
+        clock.advance(1_500_000);
+        assert_eq!(
+            clock.time(),
+            TimeOfDay {
+                hours: 10,
+                minutes: 0,
+                seconds: 1,
+                microseconds: 500_000,
+            }
+        );
+        for _ in 0..60 {
+            clock.advance(1_000_000);
+        }
+        assert_eq!((clock.time().minutes, clock.time().seconds), (1, 1));
+    }
+
+    #[test]
+    fn wraps_at_midnight() {
+        let mut clock = WallClock::new();
+        clock.sync(us(23, 59, 59) + 500_000);
+        clock.advance(1_000_000);
+        assert_eq!(
+            clock.time(),
+            TimeOfDay {
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+                microseconds: 500_000,
+            }
+        );
+        // times past midnight from the phone wrap too
+        clock.sync(us(24, 0, 5));
+        assert_eq!(clock.time().seconds, 5);
+        assert_eq!(clock.time().hours, 0);
+    }
+
+    #[test]
+    fn converts_to_the_twelve_hour_clock() {
+        let mut clock = Clock::<64, 32>::new(ClockFace::Digits);
+        let hours = [0, 1, 11, 12, 13, 23];
+        assert_eq!(hours.map(|h| clock.display_hour(time(h, 30))), hours);
+        clock.twenty_four_hour = false;
+        assert_eq!(
+            hours.map(|h| clock.display_hour(time(h, 30))),
+            [12, 1, 11, 12, 1, 11]
+        );
+    }
+
+    #[test]
+    fn words_count_to_the_next_hour_from_twenty_five_to() {
+        assert_eq!(time_in_words(time(10, 0)), ("", "O'CLOCK", "TEN"));
+        assert_eq!(time_in_words(time(10, 30)), ("HALF", "PAST", "TEN"));
+        assert_eq!(time_in_words(time(10, 34)), ("HALF", "PAST", "TEN"));
+        assert_eq!(time_in_words(time(10, 35)), ("TWENTY FIVE", "TO", "ELEVEN"));
+        assert_eq!(time_in_words(time(11, 35)), ("TWENTY FIVE", "TO", "TWELVE"));
+        assert_eq!(time_in_words(time(23, 55)), ("FIVE", "TO", "TWELVE"));
+        assert_eq!(time_in_words(time(0, 5)), ("FIVE", "PAST", "TWELVE"));
+    }
+
+    #[test]
+    fn drift_correction_converges() {
+        // a local clock running 100ppm fast, set from the phone every hour
+        let fast_ppm = 100;
+        let mut clock = WallClock::new();
+        let mut true_us = us(8, 0, 0);
+        clock.sync(true_us);
+        let mut errors = [0i64; 6];
+        for error in errors.iter_mut() {
+            for _ in 0..3600 {
+                clock.advance((SECOND_US + fast_ppm) as u32);
+            }
+            true_us += HOUR_US;
+            *error = clock.time_us - true_us as i64;
+            clock.sync(true_us);
+        }
+        // 100ppm of an hour before there's any correction
+        assert_eq!(errors[0], 360_000);
+        assert!(
+            errors[2..].iter().all(|error| error.abs() < 5_000),
+            "{errors:?}"
+        );
+        assert!((clock.correction_ppm() + 100).abs() <= 1);
+    }
+
+    #[test]
+    fn close_syncs_dont_change_the_correction() {
+        let mut clock = WallClock::new();
+        clock.sync(us(8, 0, 0));
+        clock.advance(60_000_000);
+        clock.sync(us(8, 1, 1));
+        assert_eq!(clock.correction_ppm(), 0);
+        assert_eq!(clock.time().seconds, 1);
+    }
+}
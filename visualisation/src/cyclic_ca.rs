@@ -0,0 +1,240 @@
+use embedded_graphics::{Pixel, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, palette::Palette};
+
+/// The most steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 4;
+const MAX_STATES: u8 = 24;
+const MAX_RANGE: u8 = 3;
+/// How far above the threshold the rock-paper-scissors rule randomly raises the
+/// number of neighbours needed
+const RPS_JITTER: u32 = 2;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Neighbourhood {
+    /// The square around the cell
+    Moore,
+    /// The diamond around the cell, cells within the range in steps up, down, left
+    /// and right
+    VonNeumann,
+}
+
+impl Neighbourhood {
+    /// The number of cells around a cell, not counting itself
+    fn size(self, range: i32) -> u8 {
+        match self {
+            Neighbourhood::Moore => ((2 * range + 1) * (2 * range + 1) - 1) as u8,
+            Neighbourhood::VonNeumann => (2 * range * (range + 1)) as u8,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CyclicRule {
+    /// A cell always moves on to the next state when enough of its neighbours are in
+    /// it
+    Deterministic,
+    /// The number of neighbours needed goes up by a random amount each time, which
+    /// breaks up the spirals into rough, shifting fronts
+    RockPaperScissors,
+}
+
+/// A cyclic cellular automaton. Each cell holds one of `n` states, and moves on to
+/// the next one, wrapping back round to the first, when at least a threshold number
+/// of its neighbours are already in it. Each state eats the one before it, like
+/// rock-paper-scissors, which winds the random start up into rotating spirals.
+///
+/// The edges wrap around. If everything stops changing it starts again.
+pub struct CyclicCa<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    cells: Grid<u8, W, H>,
+    /// the next step is written into here, then the grids are swapped
+    next: Grid<u8, W, H>,
+    states: u8,
+    threshold: u8,
+    neighbourhood: Neighbourhood,
+    range: u8,
+    rule: CyclicRule,
+    palette: Palette,
+    steps_per_second: f32,
+    time_banked: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> CyclicCa<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(
+        states: u8,
+        threshold: u8,
+        neighbourhood: Neighbourhood,
+        range: u8,
+        rng: Rng,
+    ) -> Self {
+        let mut this = CyclicCa {
+            cells: Grid::new(0),
+            next: Grid::new(0),
+            states: states.clamp(2, MAX_STATES),
+            threshold: 1,
+            neighbourhood,
+            range: range.clamp(1, MAX_RANGE),
+            rule: CyclicRule::Deterministic,
+            palette: Palette::Rainbow,
+            steps_per_second: 15.0,
+            time_banked: 0.0,
+            rng,
+        };
+        this.set_threshold(threshold);
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn set_threshold(&mut self, threshold: u8) {
+        let most = self.neighbourhood.size(self.range as i32);
+        self.threshold = threshold.clamp(1, most);
+    }
+
+    /// How many of the cell's neighbours are in `state`
+    fn count_neighbours(&self, x: i32, y: i32, state: u8) -> u8 {
+        let range = self.range as i32;
+        let cells = self.cells.buffer();
+        let mut count = 0;
+        for dy in -range..=range {
+            let row = (y + dy).rem_euclid(H as i32) as usize * W;
+            // von Neumann neighbourhoods narrow towards the top and bottom
+            let reach = match self.neighbourhood {
+                Neighbourhood::Moore => range,
+                Neighbourhood::VonNeumann => range - dy.abs(),
+            };
+            for dx in -reach..=reach {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let column = (x + dx).rem_euclid(W as i32) as usize;
+                if cells[row + column] == state {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Run one step, returning whether any cell changed
+    fn step(&mut self) -> bool {
+        let mut changed = false;
+        for (x, y) in Grid::<u8, W, H>::iter_coords() {
+            let state = self.cells.get(x, y).copied().unwrap_or(0);
+            let successor = (state + 1) % self.states;
+            let needed = match self.rule {
+                CyclicRule::Deterministic => self.threshold,
+                CyclicRule::RockPaperScissors => {
+                    self.threshold + (self.rng.next_u32() % (RPS_JITTER + 1)) as u8
+                }
+            };
+            let next = if self.count_neighbours(x, y, successor) >= needed {
+                changed = true;
+                successor
+            } else {
+                state
+            };
+            self.next.set(x, y, next);
+        }
+        core::mem::swap(&mut self.cells, &mut self.next);
+        changed
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum CyclicCaUpdate {
+    Reset,
+    /// The number of states the cells cycle through. Starts again.
+    SetStates(u8),
+    /// How many neighbours need to be in the next state for a cell to move on to it
+    SetThreshold(u8),
+    SetNeighbourhood(Neighbourhood),
+    /// How far the neighbourhood reaches, from 1 to 3
+    SetRange(u8),
+    SetRule(CyclicRule),
+    SetStepsPerSecond(f32),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for CyclicCaUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for CyclicCa<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = CyclicCaUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let step_time = 1.0 / self.steps_per_second;
+        self.time_banked = (self.time_banked + delta_time_us as f32 / 1_000_000.0)
+            .min(step_time * MAX_STEPS as f32);
+        let mut stepped = false;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            if !self.step() {
+                self.reset();
+            }
+            stepped = true;
+        }
+        stepped
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let palette = self.palette;
+        let scale = 1.0 / self.states as f32;
+        let _ =
+            target.draw_iter(self.cells.iter_with_index().map(|((x, y), state)| {
+                Pixel(Point::new(x, y), palette.sample(*state as f32 * scale))
+            }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            CyclicCaUpdate::Reset => self.reset(),
+            CyclicCaUpdate::SetStates(states) => {
+                self.states = states.clamp(2, MAX_STATES);
+                self.reset();
+            }
+            CyclicCaUpdate::SetThreshold(threshold) => self.set_threshold(threshold),
+            CyclicCaUpdate::SetNeighbourhood(neighbourhood) => {
+                self.neighbourhood = neighbourhood;
+                self.set_threshold(self.threshold);
+            }
+            CyclicCaUpdate::SetRange(range) => {
+                self.range = range.clamp(1, MAX_RANGE);
+                self.set_threshold(self.threshold);
+            }
+            CyclicCaUpdate::SetRule(rule) => self.rule = rule,
+            CyclicCaUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 60.0)
+            }
+            CyclicCaUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        // the "313" rule, which quickly grows large spirals
+        CyclicCa::new(3, 3, Neighbourhood::Moore, 1, rng)
+    }
+
+    fn reset(&mut self) {
+        for cell in self.cells.buffer_mut().iter_mut() {
+            *cell = (self.rng.next_u32() % self.states as u32) as u8;
+        }
+        self.time_banked = 0.0;
+    }
+}
@@ -0,0 +1,269 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+
+use crate::{RngU32, StateUpdate, Visualisation};
+
+const GLYPH_W: usize = 3;
+const GLYPH_H: usize = 5;
+/// The horizontal and vertical space taken up by a glyph, including the gap
+const CELL_W: usize = GLYPH_W + 1;
+const CELL_H: usize = GLYPH_H + 1;
+const MAX_COLUMNS: usize = 32;
+const MAX_ROWS: usize = 16;
+
+/// 3x5 katakana-ish glyphs, one byte per row with the leftmost pixel in bit 2
+const GLYPHS: [[u8; GLYPH_H]; 16] = [
+    [0b111, 0b001, 0b010, 0b010, 0b100],
+    [0b001, 0b010, 0b110, 0b010, 0b010],
+    [0b010, 0b111, 0b101, 0b001, 0b010],
+    [0b111, 0b010, 0b010, 0b010, 0b111],
+    [0b010, 0b111, 0b011, 0b010, 0b110],
+    [0b100, 0b111, 0b100, 0b100, 0b011],
+    [0b101, 0b101, 0b001, 0b001, 0b110],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b111, 0b101, 0b001, 0b010],
+    [0b110, 0b001, 0b110, 0b001, 0b110],
+    [0b010, 0b010, 0b011, 0b010, 0b010],
+    [0b111, 0b101, 0b001, 0b010, 0b100],
+    [0b100, 0b110, 0b101, 0b100, 0b100],
+    [0b001, 0b111, 0b001, 0b011, 0b101],
+    [0b000, 0b111, 0b000, 0b111, 0b000],
+    [0b011, 0b100, 0b010, 0b001, 0b110],
+];
+
+#[derive(Copy, Clone)]
+struct Cell {
+    glyph: u8,
+    brightness: u8,
+}
+
+/// A drop falling down one of the columns
+#[derive(Copy, Clone)]
+struct Drop {
+    /// the position of the head, in rows. Negative if it's not yet on screen
+    y: f32,
+    /// rows per second
+    speed: f32,
+    active: bool,
+}
+
+pub struct DigitalRain<Rng, const W: usize, const H: usize> {
+    cells: [[Cell; MAX_ROWS]; MAX_COLUMNS],
+    drops: [Drop; MAX_COLUMNS],
+    rng: Rng,
+    colour: Rgb888,
+    /// the chance per second of a drop starting in an empty column
+    density: f32,
+    /// a multiplier on the speed of all drops
+    speed: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> DigitalRain<Rng, W, H> {
+    const COLUMNS: usize = if W.div_ceil(CELL_W) < MAX_COLUMNS {
+        W.div_ceil(CELL_W)
+    } else {
+        MAX_COLUMNS
+    };
+    const ROWS: usize = if H.div_ceil(CELL_H) < MAX_ROWS {
+        H.div_ceil(CELL_H)
+    } else {
+        MAX_ROWS
+    };
+    /// How much the tails fade per second
+    const FADE: f32 = 220.0;
+    /// The chance per second of a lit glyph changing
+    const FLICKER: f32 = 1.5;
+
+    pub fn new(rng: Rng) -> Self {
+        let mut this = DigitalRain {
+            cells: [[Cell {
+                glyph: 0,
+                brightness: 0,
+            }; MAX_ROWS]; MAX_COLUMNS],
+            drops: [Drop {
+                y: 0.0,
+                speed: 0.0,
+                active: false,
+            }; MAX_COLUMNS],
+            rng,
+            colour: Rgb888::new(40, 255, 70),
+            density: 0.6,
+            speed: 1.0,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn random_glyph(&mut self) -> u8 {
+        (self.rng.next_u32() % GLYPHS.len() as u32) as u8
+    }
+
+    fn start_drop(&mut self, column: usize) {
+        self.drops[column] = Drop {
+            // start a little above the screen so they don't all line up
+            y: -self.rng.unit_f32() * 3.0,
+            speed: 2.0 + self.rng.unit_f32() * 5.0,
+            active: true,
+        };
+    }
+
+    fn step(&mut self, dt: f32) {
+        let fade = ((Self::FADE * dt) as u32).clamp(1, 255) as u8;
+        for column in 0..Self::COLUMNS {
+            for row in 0..Self::ROWS {
+                let brightness = self.cells[column][row].brightness;
+                if brightness > 0 && self.rng.unit_f32() < Self::FLICKER * dt {
+                    self.cells[column][row].glyph = self.random_glyph();
+                }
+                self.cells[column][row].brightness = brightness.saturating_sub(fade);
+            }
+
+            let drop = self.drops[column];
+            if !drop.active {
+                if self.rng.unit_f32() < self.density * dt {
+                    self.start_drop(column);
+                }
+                continue;
+            }
+
+            let new_y = drop.y + drop.speed * self.speed * dt;
+            // light up every row the head has passed through this step
+            let first_row = libm::floorf(drop.y) as i32 + 1;
+            let last_row = libm::floorf(new_y) as i32;
+            for row in first_row.max(0)..=last_row.min(Self::ROWS as i32 - 1) {
+                let glyph = self.random_glyph();
+                self.cells[column][row as usize] = Cell {
+                    glyph,
+                    brightness: u8::MAX,
+                };
+            }
+            self.drops[column].y = new_y;
+            if new_y >= Self::ROWS as f32 {
+                self.drops[column].active = false;
+            }
+        }
+    }
+
+    fn scale(colour: Rgb888, brightness: u8) -> Rgb888 {
+        let b = brightness as u16;
+        Rgb888::new(
+            (colour.r() as u16 * b / 255) as u8,
+            (colour.g() as u16 * b / 255) as u8,
+            (colour.b() as u16 * b / 255) as u8,
+        )
+    }
+
+    fn draw_glyph<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        target: &mut D,
+        column: usize,
+        row: usize,
+        glyph: u8,
+        colour: Rgb888,
+    ) {
+        let bitmap = &GLYPHS[glyph as usize];
+        let (ox, oy) = ((column * CELL_W) as i32, (row * CELL_H) as i32);
+        let _ = target.draw_iter(
+            (0..GLYPH_H)
+                .flat_map(|y| (0..GLYPH_W).map(move |x| (x, y)))
+                .filter(|&(x, y)| bitmap[y] & (1 << (GLYPH_W - 1 - x)) != 0)
+                .map(|(x, y)| Pixel(Point::new(ox + x as i32, oy + y as i32), colour)),
+        );
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum DigitalRainUpdate {
+    Reset,
+    SetColour {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// The chance per second of a new drop starting in an empty column
+    SetDensity(f32),
+    /// A multiplier on the speed of the drops
+    SetSpeed(f32),
+}
+
+impl StateUpdate for DigitalRainUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for DigitalRain<Rng, W, H> {
+    type StateUpdate = DigitalRainUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        self.step((delta_time_us as f32 / 1_000_000.0).min(0.1));
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        for column in 0..Self::COLUMNS {
+            let drop = self.drops[column];
+            let head = if drop.active {
+                Some(libm::floorf(drop.y) as i32)
+            } else {
+                None
+            };
+            for row in 0..Self::ROWS {
+                let cell = self.cells[column][row];
+                if cell.brightness == 0 {
+                    continue;
+                }
+                let colour = if head == Some(row as i32) {
+                    // the head of the drop is drawn as a washed out version of the colour
+                    Rgb888::new(
+                        self.colour.r().saturating_add(180),
+                        self.colour.g().saturating_add(180),
+                        self.colour.b().saturating_add(180),
+                    )
+                } else {
+                    Self::scale(self.colour, cell.brightness)
+                };
+                Self::draw_glyph(target, column, row, cell.glyph, colour);
+            }
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            DigitalRainUpdate::Reset => self.reset(),
+            DigitalRainUpdate::SetColour { r, g, b } => self.colour = Rgb888::new(r, g, b),
+            DigitalRainUpdate::SetDensity(density) => self.density = density.max(0.0),
+            DigitalRainUpdate::SetSpeed(speed) => self.speed = speed.max(0.0),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        DigitalRain::new(rng)
+    }
+
+    fn reset(&mut self) {
+        for column in 0..Self::COLUMNS {
+            for row in 0..Self::ROWS {
+                let glyph = self.random_glyph();
+                self.cells[column][row] = Cell {
+                    glyph,
+                    brightness: 0,
+                };
+            }
+            self.start_drop(column);
+            // spread the drops out so the screen fills up quickly
+            self.drops[column].y -= self.rng.unit_f32() * Self::ROWS as f32;
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, palette::Palette};
+
+const MAX_WALKERS: usize = 64;
+/// The most walker steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 64;
+/// How far outside the cluster new walkers appear, in pixels
+const SPAWN_MARGIN: f32 = 3.0;
+/// How far outside the cluster a walker can wander before it's replaced by a new one
+const KILL_MARGIN: f32 = 8.0;
+/// Seconds the finished cluster is shown for before starting again
+const HOLD_TIME: f32 = 3.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DlaSeed {
+    /// A single particle in the middle
+    Point,
+    /// A line across the middle, which grows up and down
+    Line,
+    /// A ring, which grows both inwards and outwards
+    Ring,
+}
+
+/// Diffusion-limited aggregation. Particles random walk until they bump into the
+/// cluster and stick to it, which grows it into branching, coral-like shapes. Each
+/// particle is coloured by when it arrived.
+///
+/// Walkers are only spawned just outside the furthest part of the cluster, and are
+/// replaced if they wander too far away, so they don't spend ages walking around
+/// empty space.
+pub struct Dla<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    /// the order each particle arrived in, starting from 1 for the seed, or 0 if empty
+    arrivals: Grid<u16, W, H>,
+    particles: u16,
+    walkers: [(i32, i32); MAX_WALKERS],
+    walker_count: usize,
+    seed: DlaSeed,
+    /// how far the furthest particle is from the seed
+    reach: f32,
+    /// the chance of sticking each time a walker is next to the cluster
+    stickiness: f32,
+    /// steps each walker takes per second
+    speed: f32,
+    palette: Palette,
+    /// seconds left to show the finished cluster, or 0 while it's still growing
+    holding: f32,
+    time_banked: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Dla<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(seed: DlaSeed, walker_count: usize, stickiness: f32, rng: Rng) -> Self {
+        let mut this = Dla {
+            arrivals: Grid::new(0),
+            particles: 0,
+            walkers: [(0, 0); MAX_WALKERS],
+            walker_count: walker_count.clamp(1, MAX_WALKERS),
+            seed,
+            reach: 0.0,
+            stickiness,
+            speed: 100.0,
+            palette: Palette::Rainbow,
+            holding: 0.0,
+            time_banked: 0.0,
+            rng,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn centre() -> (f32, f32) {
+        ((W / 2) as f32, (H / 2) as f32)
+    }
+
+    fn ring_radius() -> f32 {
+        (W.min(H) / 3) as f32
+    }
+
+    /// How far a position is from the seed
+    fn distance(&self, x: i32, y: i32) -> f32 {
+        let (cx, cy) = Self::centre();
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        match self.seed {
+            DlaSeed::Point => libm::sqrtf(dx * dx + dy * dy),
+            DlaSeed::Line => libm::fabsf(dy),
+            DlaSeed::Ring => libm::fabsf(libm::sqrtf(dx * dx + dy * dy) - Self::ring_radius()),
+        }
+    }
+
+    /// Whether a particle stuck here means the cluster has grown as far as it can
+    fn reached_edge(&self, x: i32, y: i32) -> bool {
+        let top_or_bottom = y == 0 || y == H as i32 - 1;
+        match self.seed {
+            // the line already touches the sides
+            DlaSeed::Line => top_or_bottom,
+            DlaSeed::Point | DlaSeed::Ring => top_or_bottom || x == 0 || x == W as i32 - 1,
+        }
+    }
+
+    fn place_seed(&mut self) {
+        let (cx, cy) = Self::centre();
+        match self.seed {
+            DlaSeed::Point => self.arrivals.set(cx as i32, cy as i32, 1),
+            DlaSeed::Line => {
+                for x in 0..W as i32 {
+                    self.arrivals.set(x, cy as i32, 1);
+                }
+            }
+            DlaSeed::Ring => {
+                for (x, y) in Grid::<u16, W, H>::iter_coords() {
+                    if self.distance(x, y) < 0.5 {
+                        self.arrivals.set(x, y, 1);
+                    }
+                }
+            }
+        }
+        self.particles = 1;
+    }
+
+    /// A random position just outside the cluster
+    fn spawn(&mut self) -> (i32, i32) {
+        let (cx, cy) = Self::centre();
+        let d = self.reach + SPAWN_MARGIN;
+        let angle = self.rng.unit_f32() * core::f32::consts::TAU;
+        let outside = self.rng.next_u32().is_multiple_of(2);
+        let (x, y) = match self.seed {
+            DlaSeed::Point => (cx + d * libm::cosf(angle), cy + d * libm::sinf(angle)),
+            DlaSeed::Line => {
+                let x = self.rng.unit_f32() * W as f32;
+                (x, if outside { cy + d } else { cy - d })
+            }
+            DlaSeed::Ring => {
+                let inside = Self::ring_radius() - d;
+                let r = if outside || inside < 0.0 {
+                    Self::ring_radius() + d
+                } else {
+                    inside
+                };
+                (cx + r * libm::cosf(angle), cy + r * libm::sinf(angle))
+            }
+        };
+        (
+            (libm::roundf(x) as i32).clamp(0, W as i32 - 1),
+            (libm::roundf(y) as i32).clamp(0, H as i32 - 1),
+        )
+    }
+
+    fn touching_cluster(&self, x: i32, y: i32) -> bool {
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .into_iter()
+            .any(|(nx, ny)| self.arrivals.get(nx, ny).is_some_and(|a| *a > 0))
+    }
+
+    fn stick(&mut self, x: i32, y: i32) {
+        self.particles = self.particles.saturating_add(1);
+        self.arrivals.set(x, y, self.particles);
+        self.reach = self.reach.max(self.distance(x, y));
+        if self.reached_edge(x, y) || self.particles == u16::MAX {
+            self.holding = HOLD_TIME;
+        }
+    }
+
+    /// Move every walker one step, sticking the ones that touch the cluster
+    fn step(&mut self) {
+        for i in 0..self.walker_count {
+            let (x, y) = self.walkers[i];
+            if self.touching_cluster(x, y) && self.rng.chance(self.stickiness) {
+                if self.arrivals.get(x, y) == Some(&0) {
+                    self.stick(x, y);
+                    if self.holding > 0.0 {
+                        return;
+                    }
+                }
+                self.walkers[i] = self.spawn();
+                continue;
+            }
+
+            let (nx, ny) = match self.rng.next_u32() % 4 {
+                0 => (x - 1, y),
+                1 => (x + 1, y),
+                2 => (x, y - 1),
+                _ => (x, y + 1),
+            };
+            // walkers can't leave the board or walk into the cluster
+            if self.arrivals.get(nx, ny) == Some(&0) {
+                self.walkers[i] = (nx, ny);
+            }
+            let (x, y) = self.walkers[i];
+            if self.distance(x, y) > self.reach + KILL_MARGIN {
+                self.walkers[i] = self.spawn();
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum DlaUpdate {
+    Reset,
+    /// Start again from a different seed shape
+    SetSeed(DlaSeed),
+    SetWalkers(u8),
+    /// The chance of a walker sticking each time it touches the cluster. Lower values
+    /// let walkers get further in, which makes thicker, fuzzier branches.
+    SetStickiness(f32),
+    /// Steps each walker takes per second
+    SetSpeed(f32),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for DlaUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Dla<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = DlaUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        if self.holding > 0.0 {
+            self.holding -= dt;
+            if self.holding <= 0.0 {
+                self.reset();
+            }
+            return true;
+        }
+
+        let step_time = 1.0 / self.speed;
+        self.time_banked = (self.time_banked + dt).min(step_time * MAX_STEPS as f32);
+        while self.time_banked >= step_time && self.holding <= 0.0 {
+            self.time_banked -= step_time;
+            self.step();
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let palette = self.palette;
+        let scale = 1.0 / self.particles.max(1) as f32;
+        let _ = target.draw_iter(
+            self.arrivals
+                .iter_with_index()
+                .filter(|(_, a)| **a > 0)
+                .map(|((x, y), arrival)| {
+                    // start a little way up the gradient so the oldest particles aren't black
+                    let t = 0.15 + 0.85 * (*arrival - 1) as f32 * scale;
+                    Pixel(Point::new(x, y), palette.sample(t))
+                }),
+        );
+
+        if self.holding <= 0.0 {
+            let grey = Rgb888::new(50, 50, 50);
+            let _ = target.draw_iter(
+                self.walkers[..self.walker_count]
+                    .iter()
+                    .map(|&(x, y)| Pixel(Point::new(x, y), grey)),
+            );
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            DlaUpdate::Reset => self.reset(),
+            DlaUpdate::SetSeed(seed) => {
+                self.seed = seed;
+                self.reset();
+            }
+            DlaUpdate::SetWalkers(count) => {
+                let count = (count as usize).clamp(1, MAX_WALKERS);
+                for i in self.walker_count..count {
+                    self.walkers[i] = self.spawn();
+                }
+                self.walker_count = count;
+            }
+            DlaUpdate::SetStickiness(stickiness) => self.stickiness = stickiness.clamp(0.01, 1.0),
+            DlaUpdate::SetSpeed(speed) => self.speed = speed.clamp(1.0, 2000.0),
+            DlaUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Dla::new(DlaSeed::Point, 32, 1.0, rng)
+    }
+
+    fn reset(&mut self) {
+        self.arrivals.buffer_mut().fill(0);
+        self.place_seed();
+        self.reach = 0.0;
+        for i in 0..self.walker_count {
+            self.walkers[i] = self.spawn();
+        }
+        self.holding = 0.0;
+        self.time_banked = 0.0;
+    }
+}
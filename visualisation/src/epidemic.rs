@@ -0,0 +1,233 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, xorshift::XorShift};
+
+/// The most steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 4;
+/// How many cells are infected when the epidemic starts, or restarts after dying out
+const SEED_INFECTIONS: usize = 3;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Cell {
+    Susceptible,
+    Infected,
+    Recovered,
+}
+
+/// A lattice SIR epidemic. Each step a susceptible cell catches the infection from
+/// each infected neighbour with probability `infection`, infected cells recover with
+/// probability `recovery`, and recovered cells lose their immunity with probability
+/// `immunity_loss`.
+///
+/// If the infection dies out completely it's seeded again in a few random cells.
+pub struct Epidemic<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    cells: Grid<Cell, W, H>,
+    /// the next step is written into here, then the grids are swapped
+    next: Grid<Cell, W, H>,
+    rng: Rng,
+    infection: f32,
+    recovery: f32,
+    immunity_loss: f32,
+    steps_per_second: f32,
+    time_banked: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Epidemic<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(infection: f32, recovery: f32, immunity_loss: f32, rng: Rng) -> Self {
+        let mut this = Epidemic {
+            cells: Grid::new(Cell::Susceptible),
+            next: Grid::new(Cell::Susceptible),
+            rng,
+            infection,
+            recovery,
+            immunity_loss,
+            steps_per_second: 15.0,
+            time_banked: 0.0,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn seed(&mut self) {
+        for _ in 0..SEED_INFECTIONS {
+            let (x, y) = self.cells.random_coord(&mut self.rng);
+            self.cells.set(x, y, Cell::Infected);
+        }
+    }
+
+    fn infected_neighbours(&self, x: i32, y: i32) -> usize {
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .into_iter()
+            .filter(|&(nx, ny)| self.cells.get(nx, ny) == Some(&Cell::Infected))
+            .count()
+    }
+
+    fn step(&mut self) {
+        // the chance of catching it from any of 0 to 4 infected neighbours
+        let mut catch = [0.0; 5];
+        for (k, p) in catch.iter_mut().enumerate() {
+            *p = 1.0 - libm::powf(1.0 - self.infection, k as f32);
+        }
+
+        // every cell rolls each step, which is too many numbers to take from the rng
+        let mut rng = XorShift::seeded_from(&mut self.rng);
+        let mut any_infected = false;
+        for (x, y) in Grid::<Cell, W, H>::iter_coords() {
+            let cell = self.cells.get(x, y).copied().unwrap_or(Cell::Susceptible);
+            let next = match cell {
+                Cell::Susceptible => {
+                    let k = self.infected_neighbours(x, y);
+                    if k > 0 && rng.chance(catch[k]) {
+                        Cell::Infected
+                    } else {
+                        Cell::Susceptible
+                    }
+                }
+                Cell::Infected => {
+                    if rng.chance(self.recovery) {
+                        Cell::Recovered
+                    } else {
+                        Cell::Infected
+                    }
+                }
+                Cell::Recovered => {
+                    if rng.chance(self.immunity_loss) {
+                        Cell::Susceptible
+                    } else {
+                        Cell::Recovered
+                    }
+                }
+            };
+            any_infected |= next == Cell::Infected;
+            self.next.set(x, y, next);
+        }
+        core::mem::swap(&mut self.cells, &mut self.next);
+        if !any_infected {
+            self.seed();
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum EpidemicUpdate {
+    Reset,
+    /// The chance of catching the infection from each infected neighbour each step
+    SetInfection(f32),
+    /// The chance of an infected cell recovering each step
+    SetRecovery(f32),
+    /// The chance of a recovered cell becoming susceptible again each step
+    SetImmunityLoss(f32),
+    SetStepsPerSecond(f32),
+}
+
+impl StateUpdate for EpidemicUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Epidemic<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = EpidemicUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let step_time = 1.0 / self.steps_per_second;
+        self.time_banked = (self.time_banked + delta_time_us as f32 / 1_000_000.0)
+            .min(step_time * MAX_STEPS as f32);
+        let mut stepped = false;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step();
+            stepped = true;
+        }
+        stepped
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(self.cells.iter_with_index().map(|((x, y), cell)| {
+            let colour = match cell {
+                Cell::Susceptible => Rgb888::new(20, 40, 90),
+                Cell::Infected => Rgb888::new(255, 30, 30),
+                Cell::Recovered => Rgb888::new(40, 200, 90),
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            EpidemicUpdate::Reset => self.reset(),
+            EpidemicUpdate::SetInfection(p) => self.infection = p.clamp(0.0, 1.0),
+            EpidemicUpdate::SetRecovery(p) => self.recovery = p.clamp(0.0, 1.0),
+            EpidemicUpdate::SetImmunityLoss(p) => self.immunity_loss = p.clamp(0.0, 1.0),
+            EpidemicUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 120.0)
+            }
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Epidemic::new(0.3, 0.1, 0.01, rng)
+    }
+
+    fn reset(&mut self) {
+        self.cells.buffer_mut().fill(Cell::Susceptible);
+        self.seed();
+        self.time_banked = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::CountingRng;
+
+    type TestEpidemic = Epidemic<CountingRng, 64, 32>;
+
+    fn count(epidemic: &TestEpidemic, cell: Cell) -> usize {
+        epidemic
+            .cells
+            .buffer()
+            .iter()
+            .filter(|c| **c == cell)
+            .count()
+    }
+
+    #[test]
+    fn a_step_takes_one_number_from_the_rng() {
+        let mut epidemic = TestEpidemic::new(0.5, 0.0, 0.0, CountingRng::new(3));
+        epidemic.rng.count = 0;
+        for _ in 0..10 {
+            epidemic.step();
+        }
+        assert_eq!(epidemic.rng.count, 10);
+    }
+
+    #[test]
+    fn infection_spreads_and_is_reseeded_after_dying_out() {
+        let mut epidemic = TestEpidemic::new(1.0, 0.0, 0.0, CountingRng::new(3));
+        epidemic.cells.buffer_mut().fill(Cell::Susceptible);
+        epidemic.cells.set(10, 10, Cell::Infected);
+        epidemic.step();
+        assert_eq!(count(&epidemic, Cell::Infected), 5);
+
+        epidemic.run_state_update(EpidemicUpdate::SetRecovery(1.0));
+        epidemic.cells.buffer_mut().fill(Cell::Recovered);
+        epidemic.cells.set(10, 10, Cell::Infected);
+        epidemic.step();
+        // a few random cells, which could land on each other
+        assert!((1..=SEED_INFECTIONS).contains(&count(&epidemic, Cell::Infected)));
+    }
+}
@@ -0,0 +1,450 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, xorshift::XorShift};
+
+/// The maximum number of spawners that can be active at once
+const MAX_SPAWNERS: usize = 8;
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Material {
+    Empty,
+    Sand,
+    Water,
+    Stone,
+    Fire,
+    Smoke,
+    Plant,
+}
+
+impl Material {
+    /// The relative weight of the material, things fall through lighter things
+    fn density(self) -> u8 {
+        match self {
+            Material::Smoke => 0,
+            Material::Empty => 1,
+            Material::Fire => 1,
+            Material::Water => 2,
+            Material::Sand => 3,
+            Material::Plant | Material::Stone => u8::MAX,
+        }
+    }
+
+    /// whether the material can be displaced by something of a higher density
+    fn is_fluid(self) -> bool {
+        matches!(self, Material::Empty | Material::Water | Material::Smoke)
+    }
+}
+
+/// Something that keeps adding material at a point
+#[derive(Copy, Clone)]
+struct Spawner {
+    x: i32,
+    y: i32,
+    material: Material,
+    /// the chance out of 256 of spawning on each step
+    chance: u8,
+}
+
+pub struct FallingSand<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    grid: Grid<Material, W, H>,
+    /// set for cells that have already been moved into during this step
+    moved: Grid<bool, W, H>,
+    spawners: [Option<Spawner>; MAX_SPAWNERS],
+    rng: Rng,
+    /// a cheap rng for the rolls made for each cell, seeded from `rng` every step
+    cell_rng: XorShift,
+    /// the step counter, used to alternate the scan direction
+    tick: u32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> FallingSand<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(rng: Rng) -> Self {
+        let mut this = FallingSand {
+            grid: Grid::new(Material::Empty),
+            moved: Grid::new(false),
+            spawners: [None; MAX_SPAWNERS],
+            rng,
+            cell_rng: XorShift(1),
+            tick: 0,
+        };
+        this.add_default_scene();
+        this
+    }
+
+    /// A small scene so that there's something to look at before anything is sent
+    fn add_default_scene(&mut self) {
+        let (w, h) = (W as i32, H as i32);
+        self.stroke(w / 8, h * 2 / 3, w / 2 - 4, h * 3 / 4, 0, Material::Stone);
+        self.stroke(
+            w / 2 + 4,
+            h * 3 / 4,
+            w * 7 / 8,
+            h * 2 / 3,
+            0,
+            Material::Stone,
+        );
+        self.stroke(0, h - 1, w - 1, h - 1, 0, Material::Plant);
+        self.add_spawner(w / 4, 0, Material::Sand, 48);
+        self.add_spawner(w * 3 / 4, 0, Material::Water, 64);
+    }
+
+    pub fn add_spawner(&mut self, x: i32, y: i32, material: Material, chance: u8) {
+        if let Some(slot) = self.spawners.iter_mut().find(|s| s.is_none()) {
+            *slot = Some(Spawner {
+                x,
+                y,
+                material,
+                chance,
+            });
+        }
+    }
+
+    pub fn clear_spawners(&mut self) {
+        self.spawners = [None; MAX_SPAWNERS];
+    }
+
+    /// Fill a circle of the given radius with a material
+    pub fn brush(&mut self, x: i32, y: i32, radius: i32, material: Material) {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    self.grid.set(x + dx, y + dy, material);
+                }
+            }
+        }
+    }
+
+    /// Paint a line between two points with a brush of the given radius
+    pub fn stroke(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, radius: i32, material: Material) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+        for i in 0..=steps {
+            let x = x0 + (x1 - x0) * i / steps;
+            let y = y0 + (y1 - y0) * i / steps;
+            self.brush(x, y, radius, material);
+        }
+    }
+
+    fn chance(&mut self, chance: u8) -> bool {
+        (self.cell_rng.next_u32() & 0xff) < chance as u32
+    }
+
+    fn material(&self, x: i32, y: i32) -> Option<Material> {
+        self.grid.get(x, y).copied()
+    }
+
+    fn swap(&mut self, x: i32, y: i32, ox: i32, oy: i32) {
+        let a = self.grid.get(x, y).copied();
+        let b = self.grid.get(ox, oy).copied();
+        if let (Some(a), Some(b)) = (a, b) {
+            self.grid.set(x, y, b);
+            self.grid.set(ox, oy, a);
+            self.moved.set(ox, oy, true);
+        }
+    }
+
+    /// Try to move the material at (x, y) to each of the offsets in turn, swapping with
+    /// the first fluid that is lighter (when `sink` is true) or heavier (when it's false).
+    /// Returns true if the material moved.
+    fn try_moves(&mut self, x: i32, y: i32, offsets: &[(i32, i32)], sink: bool) -> bool {
+        let me = self.grid.get(x, y).copied().unwrap_or(Material::Stone);
+        for &(dx, dy) in offsets {
+            if let Some(other) = self.material(x + dx, y + dy)
+                && !self.moved.get(x + dx, y + dy).copied().unwrap_or(true)
+                && other.is_fluid()
+                && if sink {
+                    other.density() < me.density()
+                } else {
+                    other.density() > me.density()
+                }
+            {
+                self.swap(x, y, x + dx, y + dy);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// A random horizontal direction, -1 or 1
+    fn random_side(&mut self) -> i32 {
+        if self.cell_rng.next_u32() & 1 == 0 {
+            -1
+        } else {
+            1
+        }
+    }
+
+    fn update_cell(&mut self, x: i32, y: i32) {
+        match self.grid.get(x, y).copied() {
+            Some(Material::Sand) => {
+                let side = self.random_side();
+                self.try_moves(x, y, &[(0, 1), (side, 1), (-side, 1)], true);
+            }
+            Some(Material::Water) => {
+                let side = self.random_side();
+                self.try_moves(
+                    x,
+                    y,
+                    &[(0, 1), (side, 1), (-side, 1), (side, 0), (-side, 0)],
+                    true,
+                );
+            }
+            Some(Material::Smoke) => {
+                if self.chance(6) {
+                    self.grid.set(x, y, Material::Empty);
+                } else {
+                    let side = self.random_side();
+                    self.try_moves(x, y, &[(0, -1), (side, -1), (-side, -1), (side, 0)], false);
+                }
+            }
+            Some(Material::Fire) => self.update_fire(x, y),
+            Some(Material::Plant) => {
+                // plants slowly drink up neighbouring water and grow into it
+                let (dx, dy) =
+                    [(0, -1), (1, 0), (-1, 0), (0, 1)][(self.cell_rng.next_u32() % 4) as usize];
+                if self.material(x + dx, y + dy) == Some(Material::Water) && self.chance(20) {
+                    self.grid.set(x + dx, y + dy, Material::Plant);
+                    self.moved.set(x + dx, y + dy, true);
+                }
+            }
+            Some(Material::Stone) | Some(Material::Empty) | None => {}
+        }
+    }
+
+    fn update_fire(&mut self, x: i32, y: i32) {
+        for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+            match self.material(x + dx, y + dy) {
+                Some(Material::Water) => {
+                    // water puts the fire out, boiling off as smoke
+                    self.grid.set(x, y, Material::Smoke);
+                    self.grid.set(x + dx, y + dy, Material::Smoke);
+                    return;
+                }
+                Some(Material::Plant) if self.chance(64) => {
+                    self.grid.set(x + dx, y + dy, Material::Fire);
+                    self.moved.set(x + dx, y + dy, true);
+                }
+                _ => {}
+            }
+        }
+        if self.chance(24) {
+            self.grid.set(x, y, Material::Smoke);
+        } else if self.material(x, y - 1) == Some(Material::Empty) && self.chance(32) {
+            // flames flicker upwards, leaving smoke behind
+            self.grid.set(x, y - 1, Material::Fire);
+            self.moved.set(x, y - 1, true);
+            self.grid.set(x, y, Material::Smoke);
+        }
+    }
+
+    fn run_spawners(&mut self) {
+        for spawner in self.spawners.into_iter().flatten() {
+            if self.chance(spawner.chance)
+                && self.material(spawner.x, spawner.y) == Some(Material::Empty)
+            {
+                self.grid.set(spawner.x, spawner.y, spawner.material);
+            }
+        }
+    }
+
+    /// Advance the simulation one step. The cells are updated from the bottom row up
+    /// with the horizontal scan direction alternating each step, so the result only
+    /// depends on the random numbers drawn.
+    fn step(&mut self) {
+        self.cell_rng = XorShift::seeded_from(&mut self.rng);
+        self.run_spawners();
+        self.moved.buffer_mut().fill(false);
+        let left_to_right = self.tick.is_multiple_of(2);
+        for y in (0..H as i32).rev() {
+            for i in 0..W as i32 {
+                let x = if left_to_right { i } else { W as i32 - 1 - i };
+                if !self.moved.get(x, y).copied().unwrap_or(true) {
+                    self.update_cell(x, y);
+                }
+            }
+        }
+        self.tick = self.tick.wrapping_add(1);
+    }
+
+    fn colour(&self, x: i32, y: i32, material: Material) -> Rgb888 {
+        // a cheap per-pixel hash so that the materials have a bit of texture
+        let noise =
+            ((x as u32).wrapping_mul(73_856_093) ^ (y as u32).wrapping_mul(19_349_663)) >> 28;
+        let n = noise as u8;
+        match material {
+            Material::Empty => Rgb888::BLACK,
+            Material::Sand => Rgb888::new(200 + n * 3, 160 + n * 3, 60),
+            Material::Water => Rgb888::new(20, 60 + n * 2, 200),
+            Material::Stone => Rgb888::new(90 + n * 2, 90 + n * 2, 100 + n * 2),
+            Material::Fire => {
+                let flicker = ((noise + self.tick) % 4) as u8;
+                Rgb888::new(255, 80 + flicker * 40, 0)
+            }
+            Material::Smoke => Rgb888::new(50 + n, 50 + n, 50 + n),
+            Material::Plant => Rgb888::new(20, 150 + n * 4, 40),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum FallingSandUpdate {
+    Reset,
+    Clear,
+    Brush {
+        x: u8,
+        y: u8,
+        radius: u8,
+        material: Material,
+    },
+    Stroke {
+        x0: u8,
+        y0: u8,
+        x1: u8,
+        y1: u8,
+        radius: u8,
+        material: Material,
+    },
+    AddSpawner {
+        x: u8,
+        y: u8,
+        material: Material,
+        chance: u8,
+    },
+    ClearSpawners,
+}
+
+impl StateUpdate for FallingSandUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for FallingSand<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = FallingSandUpdate;
+
+    fn update(&mut self, _delta_time_us: u32) -> bool {
+        self.step();
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(
+            self.grid
+                .iter_with_index()
+                .map(|((x, y), m)| Pixel(Point::new(x, y), self.colour(x, y, *m))),
+        );
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            FallingSandUpdate::Reset => self.reset(),
+            FallingSandUpdate::Clear => {
+                self.grid.buffer_mut().fill(Material::Empty);
+                self.clear_spawners();
+            }
+            FallingSandUpdate::Brush {
+                x,
+                y,
+                radius,
+                material,
+            } => self.brush(x as i32, y as i32, radius as i32, material),
+            FallingSandUpdate::Stroke {
+                x0,
+                y0,
+                x1,
+                y1,
+                radius,
+                material,
+            } => self.stroke(
+                x0 as i32,
+                y0 as i32,
+                x1 as i32,
+                y1 as i32,
+                radius as i32,
+                material,
+            ),
+            FallingSandUpdate::AddSpawner {
+                x,
+                y,
+                material,
+                chance,
+            } => self.add_spawner(x as i32, y as i32, material, chance),
+            FallingSandUpdate::ClearSpawners => self.clear_spawners(),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        FallingSand::new(rng)
+    }
+
+    fn reset(&mut self) {
+        self.grid.buffer_mut().fill(Material::Empty);
+        self.clear_spawners();
+        self.add_default_scene();
+        self.tick = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::CountingRng;
+
+    type TestSand = FallingSand<CountingRng, 64, 32>;
+
+    fn count(sand: &TestSand, material: Material) -> usize {
+        sand.grid
+            .buffer()
+            .iter()
+            .filter(|m| **m == material)
+            .count()
+    }
+
+    #[test]
+    fn a_step_takes_one_number_from_the_rng() {
+        let mut sand = TestSand::new(CountingRng::new(3));
+        for _ in 0..100 {
+            sand.update(16_000);
+        }
+        sand.brush(32, 10, 4, Material::Water);
+        sand.brush(20, 10, 3, Material::Fire);
+        sand.rng.count = 0;
+        for _ in 0..10 {
+            sand.update(16_000);
+        }
+        assert_eq!(sand.rng.count, 10);
+    }
+
+    #[test]
+    fn sand_falls_and_piles_up() {
+        let mut sand = TestSand::new(CountingRng::new(5));
+        sand.run_state_update(FallingSandUpdate::Clear);
+        sand.stroke(0, 31, 63, 31, 0, Material::Stone);
+        sand.brush(32, 5, 3, Material::Sand);
+        let grains = count(&sand, Material::Sand);
+        for _ in 0..200 {
+            sand.update(16_000);
+        }
+        assert_eq!(count(&sand, Material::Sand), grains);
+        // all settled in a heap on the floor, with nothing left up high
+        assert!((0..20).all(|y| (0..64).all(|x| sand.material(x, y) == Some(Material::Empty))));
+        assert!(sand.material(32, 30) == Some(Material::Sand));
+    }
+}
@@ -0,0 +1,280 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+use heapless::Vec;
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    grid::Grid,
+    palette::{Palette, lerp},
+    particles::{Particle, ParticleSystem},
+};
+
+const MAX_PARTICLES: usize = 600;
+const MAX_ROCKETS: usize = 8;
+/// Pixels per second per second, down the panel
+const GRAVITY: f32 = 20.0;
+/// The fraction of their speed sparks lose each second
+const DRAG: f32 = 0.6;
+/// How fast the fastest sparks leave a burst, in pixels per second
+const BURST_SPEED: f32 = 22.0;
+/// Sparks the rockets leave behind them each second
+const TRAIL_RATE: f32 = 40.0;
+/// Tags sparks that crackle into a few white flashes when they die
+const CRACKLE: u8 = 1;
+
+const TRAIL_COLOUR: Rgb888 = Rgb888::new(255, 140, 30);
+const WILLOW_COLOUR: Rgb888 = Rgb888::new(255, 190, 70);
+const WILLOW_END_COLOUR: Rgb888 = Rgb888::new(60, 20, 0);
+const CRACKLE_COLOUR: Rgb888 = Rgb888::new(255, 255, 220);
+
+#[derive(Copy, Clone)]
+enum Burst {
+    /// sparks flying out at all speeds, filling a ball
+    Peony,
+    /// sparks all at the same speed, making a ring
+    Ring,
+    /// long lived golden sparks that drift down
+    Willow,
+    /// sparks that crackle into flashes as they go out
+    Crackle,
+}
+
+#[derive(Copy, Clone)]
+struct Rocket {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    burst: Burst,
+    colour: Rgb888,
+    /// trail sparks owed from previous updates
+    trail_banked: f32,
+}
+
+/// A fireworks show. Rockets launch from the bottom of the panel at random, leaving
+/// a trail of sparks, and burst at the top of their climb into peonies, rings,
+/// willows or crackling stars.
+pub struct Fireworks<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    rockets: Vec<Rocket, MAX_ROCKETS>,
+    sparks: ParticleSystem<MAX_PARTICLES>,
+    /// the sparks are added up in here, so they get brighter where they overlap
+    canvas: Grid<Rgb888, W, H>,
+    /// rockets launched per second, on average
+    frequency: f32,
+    palette: Palette,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Fireworks<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(frequency: f32, palette: Palette, rng: Rng) -> Self {
+        Fireworks {
+            rockets: Vec::new(),
+            sparks: ParticleSystem::new(GRAVITY, DRAG),
+            canvas: Grid::new(Rgb888::BLACK),
+            frequency,
+            palette,
+            rng,
+        }
+    }
+
+    /// Send up a rocket from somewhere along the bottom, if there's room for another
+    pub fn launch(&mut self) {
+        // climb to somewhere in the top half of the panel
+        let height = H as f32 * (0.55 + 0.3 * self.rng.unit_f32());
+        let burst = match self.rng.next_u32() % 4 {
+            0 => Burst::Peony,
+            1 => Burst::Ring,
+            2 => Burst::Willow,
+            _ => Burst::Crackle,
+        };
+        let rocket = Rocket {
+            x: W as f32 * (0.15 + 0.7 * self.rng.unit_f32()),
+            y: H as f32,
+            vx: (self.rng.unit_f32() - 0.5) * 6.0,
+            // fast enough to stop climbing at that height
+            vy: -libm::sqrtf(2.0 * GRAVITY * height),
+            burst,
+            colour: self.palette.sample(self.rng.unit_f32()),
+            trail_banked: 0.0,
+        };
+        let _ = self.rockets.push(rocket);
+    }
+
+    fn explode(&mut self, rocket: &Rocket) {
+        let (count, life, drag) = match rocket.burst {
+            Burst::Peony => (70, 1.4, 1.0),
+            Burst::Ring => (40, 1.2, 1.0),
+            Burst::Willow => (60, 2.6, 2.0),
+            Burst::Crackle => (50, 1.1, 1.0),
+        };
+        for i in 0..count.min(self.sparks.free()) {
+            let angle = match rocket.burst {
+                Burst::Ring => core::f32::consts::TAU * i as f32 / count as f32,
+                _ => core::f32::consts::TAU * self.rng.unit_f32(),
+            };
+            let speed = match rocket.burst {
+                Burst::Ring => BURST_SPEED,
+                // spread evenly through the ball, rather than bunched in the middle
+                _ => BURST_SPEED * libm::sqrtf(self.rng.unit_f32()),
+            };
+            let life = life * (0.8 + 0.4 * self.rng.unit_f32());
+            let mut spark = Particle::new(
+                rocket.x,
+                rocket.y,
+                rocket.vx + speed * libm::cosf(angle),
+                rocket.vy + speed * libm::sinf(angle),
+                life,
+                rocket.colour,
+            );
+            spark.drag = drag;
+            match rocket.burst {
+                Burst::Willow => {
+                    spark.start_colour = WILLOW_COLOUR;
+                    spark.end_colour = WILLOW_END_COLOUR;
+                }
+                Burst::Crackle => spark.tag = CRACKLE,
+                _ => {}
+            }
+            self.sparks.spawn(spark);
+        }
+    }
+
+    fn crackle(&mut self, x: f32, y: f32) {
+        for _ in 0..3 {
+            let angle = core::f32::consts::TAU * self.rng.unit_f32();
+            let speed = 8.0 * self.rng.unit_f32();
+            let mut flash = Particle::new(
+                x,
+                y,
+                speed * libm::cosf(angle),
+                speed * libm::sinf(angle),
+                0.1 + 0.1 * self.rng.unit_f32(),
+                CRACKLE_COLOUR,
+            );
+            flash.drag = 0.0;
+            self.sparks.spawn(flash);
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum FireworksUpdate {
+    Reset,
+    /// Send up a rocket straight away
+    Launch,
+    /// How many rockets go up each second, on average
+    SetFrequency(f32),
+    /// The colours the rockets burst into
+    SetPalette(Palette),
+}
+
+impl StateUpdate for FireworksUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Fireworks<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = FireworksUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        if self.rng.chance(self.frequency * dt) {
+            self.launch();
+        }
+
+        let mut i = 0;
+        while i < self.rockets.len() {
+            let rocket = &mut self.rockets[i];
+            rocket.vy += GRAVITY * dt;
+            rocket.x += rocket.vx * dt;
+            rocket.y += rocket.vy * dt;
+            rocket.trail_banked += TRAIL_RATE * dt;
+            let rocket = *rocket;
+            while self.rockets[i].trail_banked >= 1.0 {
+                self.rockets[i].trail_banked -= 1.0;
+                let mut spark = Particle::new(
+                    rocket.x,
+                    rocket.y,
+                    (self.rng.unit_f32() - 0.5) * 4.0,
+                    rocket.vy * 0.2,
+                    0.3 + 0.3 * self.rng.unit_f32(),
+                    TRAIL_COLOUR,
+                );
+                spark.end_colour = Rgb888::new(60, 0, 0);
+                self.sparks.spawn(spark);
+            }
+            // burst once it stops climbing
+            if rocket.vy >= 0.0 {
+                self.rockets.swap_remove(i);
+                self.explode(&rocket);
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut crackles: Vec<(f32, f32), 32> = Vec::new();
+        self.sparks.update(dt, |spark| {
+            if spark.tag == CRACKLE && self.rng.chance(0.5) {
+                let _ = crackles.push((spark.x, spark.y));
+            }
+        });
+        for (x, y) in crackles {
+            self.crackle(x, y);
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        self.canvas.buffer_mut().fill(Rgb888::BLACK);
+        self.sparks.draw_additive(self.canvas.buffer_mut(), W);
+        for rocket in self.rockets.iter() {
+            if let Some(pixel) = self
+                .canvas
+                .get_mut(libm::floorf(rocket.x) as i32, libm::floorf(rocket.y) as i32)
+            {
+                *pixel = lerp(Rgb888::WHITE, rocket.colour, 0.3);
+            }
+        }
+        let _ = target.draw_iter(
+            self.canvas
+                .iter_with_index()
+                .filter(|(_, colour)| **colour != Rgb888::BLACK)
+                .map(|((x, y), colour)| Pixel(Point::new(x, y), *colour)),
+        );
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            FireworksUpdate::Reset => self.reset(),
+            FireworksUpdate::Launch => self.launch(),
+            FireworksUpdate::SetFrequency(frequency) => self.frequency = frequency.clamp(0.0, 10.0),
+            FireworksUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Fireworks::new(0.8, Palette::Rainbow, rng)
+    }
+
+    fn reset(&mut self) {
+        self.rockets.clear();
+        self.sparks.clear();
+    }
+}
@@ -0,0 +1,242 @@
+use embedded_graphics::{Pixel, prelude::Point};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    grid::Grid,
+    noise::{Noise, NoiseKind},
+    palette::Palette,
+};
+
+const MAX_PARTICLES: usize = 512;
+/// The furthest a particle moves before the field is looked up again, in pixels
+const MAX_STEP: f32 = 0.5;
+/// Seconds a particle lives for before it's moved somewhere new, at the least and most
+const MIN_LIFETIME: f32 = 2.0;
+const MAX_LIFETIME: f32 = 8.0;
+/// How much brighter a pixel gets each time a particle moves over it
+const DEPOSIT: u8 = 90;
+/// The noise time wraps around here to keep the precision. The field jumps when it
+/// does, which is hours apart at normal speeds.
+const TIME_WRAP: f32 = 1024.0;
+
+#[derive(Copy, Clone)]
+struct Particle {
+    x: f32,
+    y: f32,
+    /// seconds left before the particle is moved somewhere new
+    life: f32,
+}
+
+/// Particles flowing along a field of directions taken from slowly changing noise,
+/// leaving trails behind them that fade away.
+///
+/// Particles are given a new random position when they leave the panel or get old,
+/// so that they don't all end up bunched in the same few streams.
+pub struct FlowField<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    particles: [Particle; MAX_PARTICLES],
+    n_particles: usize,
+    /// brightness of the trail at each pixel
+    trails: Grid<u8, W, H>,
+    noise: Noise,
+    kind: NoiseKind,
+    octaves: u8,
+    /// pixels across a feature of the field
+    scale: f32,
+    /// how quickly the field changes, in noise units per second
+    evolution: f32,
+    time: f32,
+    /// pixels per second
+    speed: f32,
+    /// seconds for a trail to fade away completely
+    trail_length: f32,
+    /// fractions of a step of brightness that haven't been taken off the trails yet
+    fade_banked: f32,
+    palette: Palette,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> FlowField<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(n_particles: usize, kind: NoiseKind, mut rng: Rng) -> Self {
+        let mut this = FlowField {
+            particles: [Particle {
+                x: 0.0,
+                y: 0.0,
+                life: 0.0,
+            }; MAX_PARTICLES],
+            n_particles: n_particles.clamp(1, MAX_PARTICLES),
+            trails: Grid::new(0),
+            noise: Noise::from_rng(&mut rng),
+            kind,
+            octaves: 2,
+            scale: 24.0,
+            evolution: 0.1,
+            time: 0.0,
+            speed: 12.0,
+            trail_length: 1.5,
+            fade_banked: 0.0,
+            palette: Palette::Ocean,
+            rng,
+        };
+        this.scatter(0);
+        this
+    }
+
+    /// Give the particles from `start` onwards new random positions and lifetimes
+    fn scatter(&mut self, start: usize) {
+        for i in start..self.n_particles {
+            self.particles[i] = self.random_particle();
+        }
+    }
+
+    fn random_particle(&mut self) -> Particle {
+        Particle {
+            x: self.rng.unit_f32() * W as f32,
+            y: self.rng.unit_f32() * H as f32,
+            life: MIN_LIFETIME + self.rng.unit_f32() * (MAX_LIFETIME - MIN_LIFETIME),
+        }
+    }
+
+    /// The direction of the flow at a position, as a unit vector
+    fn direction(&self, x: f32, y: f32) -> (f32, f32) {
+        let inverse_scale = 1.0 / self.scale;
+        let n = self.noise.fractal_3d(
+            self.kind,
+            x * inverse_scale,
+            y * inverse_scale,
+            self.time,
+            self.octaves,
+        );
+        // the noise is mostly well inside -1 to 1, so spread it over two turns to
+        // get flow in every direction
+        let angle = n * 2.0 * core::f32::consts::TAU;
+        (libm::cosf(angle), libm::sinf(angle))
+    }
+
+    fn step(&mut self, dt: f32) {
+        self.time = (self.time + self.evolution * dt) % TIME_WRAP;
+
+        // take the fade off in whole steps, keeping the remainder for the next frame
+        self.fade_banked += 255.0 / self.trail_length * dt;
+        let fade = self.fade_banked.min(255.0) as u8;
+        self.fade_banked -= fade as f32;
+        if fade > 0 {
+            self.trails
+                .buffer_mut()
+                .iter_mut()
+                .for_each(|t| *t = t.saturating_sub(fade));
+        }
+
+        let distance = self.speed * dt;
+        let steps = libm::ceilf(distance / MAX_STEP).max(1.0);
+        let step = distance / steps;
+        for i in 0..self.n_particles {
+            let mut particle = self.particles[i];
+            particle.life -= dt;
+            for _ in 0..steps as u32 {
+                let (dx, dy) = self.direction(particle.x, particle.y);
+                particle.x += dx * step;
+                particle.y += dy * step;
+                if let Some(t) = self.trails.get_mut(
+                    libm::floorf(particle.x) as i32,
+                    libm::floorf(particle.y) as i32,
+                ) {
+                    *t = t.saturating_add(DEPOSIT);
+                }
+            }
+            let off_panel = particle.x < 0.0
+                || particle.y < 0.0
+                || particle.x >= W as f32
+                || particle.y >= H as f32;
+            if off_panel || particle.life <= 0.0 {
+                particle = self.random_particle();
+            }
+            self.particles[i] = particle;
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum FlowFieldUpdate {
+    Reset,
+    SetParticles(u16),
+    SetNoise(NoiseKind),
+    /// More octaves give the flow more small swirls
+    SetOctaves(u8),
+    /// The size of the swirls in the flow, in pixels
+    SetScale(f32),
+    /// How quickly the flow changes
+    SetEvolution(f32),
+    /// In pixels per second
+    SetSpeed(f32),
+    /// Seconds for a trail to fade away
+    SetTrailLength(f32),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for FlowFieldUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for FlowField<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = FlowFieldUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        self.step(dt);
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let palette = self.palette;
+        let _ = target.draw_iter(
+            self.trails
+                .iter_with_index()
+                .map(|((x, y), t)| Pixel(Point::new(x, y), palette.sample(*t as f32 / 255.0))),
+        );
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            FlowFieldUpdate::Reset => self.reset(),
+            FlowFieldUpdate::SetParticles(count) => {
+                let count = (count as usize).clamp(1, MAX_PARTICLES);
+                let old = self.n_particles;
+                self.n_particles = count;
+                self.scatter(old);
+            }
+            FlowFieldUpdate::SetNoise(kind) => self.kind = kind,
+            FlowFieldUpdate::SetOctaves(octaves) => self.octaves = octaves.clamp(1, 4),
+            FlowFieldUpdate::SetScale(scale) => self.scale = scale.clamp(4.0, 200.0),
+            FlowFieldUpdate::SetEvolution(evolution) => self.evolution = evolution.clamp(0.0, 2.0),
+            FlowFieldUpdate::SetSpeed(speed) => self.speed = speed.clamp(1.0, 60.0),
+            FlowFieldUpdate::SetTrailLength(length) => self.trail_length = length.clamp(0.1, 20.0),
+            FlowFieldUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        FlowField::new(300, NoiseKind::Simplex, rng)
+    }
+
+    fn reset(&mut self) {
+        self.noise = Noise::from_rng(&mut self.rng);
+        self.time = 0.0;
+        self.trails.buffer_mut().fill(0);
+        self.scatter(0);
+    }
+}
@@ -0,0 +1,215 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, xorshift::XorShift};
+
+/// The most steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Tree,
+    Burning,
+}
+
+/// The Drossel-Schwabl forest fire model. Each step burning trees burn out, trees
+/// next to a fire catch light, trees are struck by lightning with probability `f`
+/// and empty ground grows a tree with probability `p`.
+///
+/// With `f` much smaller than `p` the forest sits near a critical state, with fires
+/// of every size.
+pub struct ForestFire<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    cells: Grid<Cell, W, H>,
+    /// the next step is written into here, then the grids are swapped
+    next: Grid<Cell, W, H>,
+    /// how brightly each cell is glowing from a recent fire
+    glow: Grid<u8, W, H>,
+    rng: Rng,
+    /// the chance of a tree growing on an empty cell each step
+    growth: f32,
+    /// the chance of a tree being struck by lightning each step
+    lightning: f32,
+    steps_per_second: f32,
+    time_banked: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> ForestFire<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(growth: f32, lightning: f32, rng: Rng) -> Self {
+        let mut this = ForestFire {
+            cells: Grid::new(Cell::Empty),
+            next: Grid::new(Cell::Empty),
+            glow: Grid::new(0),
+            rng,
+            growth,
+            lightning,
+            steps_per_second: 20.0,
+            time_banked: 0.0,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn next_to_fire(&self, x: i32, y: i32) -> bool {
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .into_iter()
+            .any(|(nx, ny)| self.cells.get(nx, ny) == Some(&Cell::Burning))
+    }
+
+    fn step(&mut self) {
+        // every cell rolls each step, which is too many numbers to take from the rng
+        let mut rng = XorShift::seeded_from(&mut self.rng);
+        for (x, y) in Grid::<Cell, W, H>::iter_coords() {
+            let cell = self.cells.get(x, y).copied().unwrap_or(Cell::Empty);
+            let next = match cell {
+                Cell::Burning => Cell::Empty,
+                Cell::Tree => {
+                    if self.next_to_fire(x, y) || rng.chance(self.lightning) {
+                        Cell::Burning
+                    } else {
+                        Cell::Tree
+                    }
+                }
+                Cell::Empty => {
+                    if rng.chance(self.growth) {
+                        Cell::Tree
+                    } else {
+                        Cell::Empty
+                    }
+                }
+            };
+            self.next.set(x, y, next);
+            if let Some(glow) = self.glow.get_mut(x, y) {
+                *glow = if next == Cell::Burning {
+                    u8::MAX
+                } else {
+                    glow.saturating_sub(24)
+                };
+            }
+        }
+        core::mem::swap(&mut self.cells, &mut self.next);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum ForestFireUpdate {
+    Reset,
+    /// The chance of a tree growing on an empty cell each step
+    SetGrowth(f32),
+    /// The chance of a tree being struck by lightning each step
+    SetLightning(f32),
+    SetStepsPerSecond(f32),
+}
+
+impl StateUpdate for ForestFireUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for ForestFire<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = ForestFireUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let step_time = 1.0 / self.steps_per_second;
+        self.time_banked = (self.time_banked + delta_time_us as f32 / 1_000_000.0)
+            .min(step_time * MAX_STEPS as f32);
+        let mut stepped = false;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step();
+            stepped = true;
+        }
+        stepped
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(self.cells.iter_with_index().map(|((x, y), cell)| {
+            let glow = self.glow.get(x, y).copied().unwrap_or(0);
+            let colour = match cell {
+                Cell::Burning => Rgb888::new(255, 200, 40),
+                Cell::Tree => Rgb888::new(0, 110, 20),
+                // cooling embers
+                Cell::Empty => Rgb888::new(glow, glow / 4, 0),
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            ForestFireUpdate::Reset => self.reset(),
+            ForestFireUpdate::SetGrowth(growth) => self.growth = growth.clamp(0.0, 1.0),
+            ForestFireUpdate::SetLightning(lightning) => self.lightning = lightning.clamp(0.0, 1.0),
+            ForestFireUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 120.0)
+            }
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        ForestFire::new(0.01, 0.00002, rng)
+    }
+
+    fn reset(&mut self) {
+        // start with a half grown forest, so there's something to burn straight away
+        let mut rng = XorShift::seeded_from(&mut self.rng);
+        for cell in self.cells.buffer_mut().iter_mut() {
+            *cell = if rng.chance(0.5) {
+                Cell::Tree
+            } else {
+                Cell::Empty
+            };
+        }
+        self.glow.buffer_mut().fill(0);
+        self.time_banked = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::CountingRng;
+
+    type TestForest = ForestFire<CountingRng, 64, 32>;
+
+    fn count(forest: &TestForest, cell: Cell) -> usize {
+        forest.cells.buffer().iter().filter(|c| **c == cell).count()
+    }
+
+    #[test]
+    fn a_step_takes_one_number_from_the_rng() {
+        let mut forest = TestForest::new(0.01, 0.001, CountingRng::new(3));
+        forest.rng.count = 0;
+        for _ in 0..10 {
+            forest.step();
+        }
+        assert_eq!(forest.rng.count, 10);
+    }
+
+    #[test]
+    fn fire_spreads_through_the_trees_and_burns_out() {
+        let mut forest = TestForest::new(0.0, 0.0, CountingRng::new(3));
+        forest.cells.buffer_mut().fill(Cell::Tree);
+        forest.cells.set(0, 0, Cell::Burning);
+        forest.step();
+        assert_eq!(count(&forest, Cell::Burning), 2);
+        assert_eq!(count(&forest, Cell::Empty), 1);
+        for _ in 0..200 {
+            forest.step();
+        }
+        assert_eq!(count(&forest, Cell::Empty), 64 * 32);
+    }
+}
@@ -0,0 +1,246 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, palette::Palette};
+
+/// Points on the edge of the Mandelbrot set that are worth zooming into
+const TARGETS: [(f32, f32); 5] = [
+    // seahorse valley
+    (-0.743_643_9, 0.131_825_9),
+    // elephant valley
+    (0.282_2, 0.010_2),
+    // a mini mandelbrot on the real axis
+    (-1.768_778_8, -0.001_739),
+    // a double spiral
+    (-0.761_574, -0.084_759_6),
+    // the tip of the antenna
+    (-1.999_9, 0.0),
+];
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum FractalMode {
+    /// Zoom into each of the targets on the Mandelbrot set in turn
+    Mandelbrot,
+    /// Move the Julia set parameter around a circle
+    Julia,
+}
+
+/// Mandelbrot zooms and morphing Julia sets. The image is rendered a few rows each
+/// update, so a frame with a high iteration count is spread over many updates.
+pub struct Fractal<const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    /// the smooth iteration count of each pixel, negative inside the set
+    iterations: Grid<f32, W, H>,
+    /// the next row to be rendered
+    row: usize,
+    rows_per_update: usize,
+    mode: FractalMode,
+    palette: Palette,
+    max_iterations: u16,
+    target: (f32, f32),
+    target_index: usize,
+    /// the seconds since the zoom into the current target started
+    zoom_time: f32,
+    /// the zoom speed, in e-foldings per second
+    zoom_speed: f32,
+    /// the angle of the Julia parameter around its circle
+    julia_angle: f32,
+    /// how fast the Julia parameter moves, in radians per second
+    julia_speed: f32,
+    /// shifts the palette over time so the colours flow outwards
+    colour_offset: f32,
+}
+
+impl<const W: usize, const H: usize> Fractal<W, H>
+where
+    [(); W * H]:,
+{
+    /// the half height of the view at the start of a zoom
+    const START_SCALE: f32 = 1.5;
+    /// the smallest half height before f32 runs out of precision and the zoom restarts
+    const MIN_SCALE: f32 = 2e-5;
+    const JULIA_RADIUS: f32 = 0.7885;
+
+    pub fn new(mode: FractalMode) -> Self {
+        Fractal {
+            iterations: Grid::new(-1.0),
+            row: 0,
+            rows_per_update: 4,
+            mode,
+            palette: Palette::Electric,
+            max_iterations: 96,
+            target: TARGETS[0],
+            target_index: 0,
+            zoom_time: 0.0,
+            zoom_speed: 0.4,
+            julia_angle: 0.0,
+            julia_speed: 0.15,
+            colour_offset: 0.0,
+        }
+    }
+
+    pub fn set_target(&mut self, x: f32, y: f32) {
+        self.target = (x, y);
+        self.zoom_time = 0.0;
+    }
+
+    fn next_target(&mut self) {
+        self.target_index = (self.target_index + 1) % TARGETS.len();
+        let (x, y) = TARGETS[self.target_index];
+        self.set_target(x, y);
+    }
+
+    /// The half height of the current view
+    fn scale(&self) -> f32 {
+        match self.mode {
+            FractalMode::Mandelbrot => {
+                Self::START_SCALE * libm::expf(-self.zoom_speed * self.zoom_time)
+            }
+            FractalMode::Julia => Self::START_SCALE,
+        }
+    }
+
+    /// The escape time of a point, smoothed so that the bands blend into each other.
+    /// Returns a negative number for points that don't escape.
+    fn smooth_iterations(&self, mut zx: f32, mut zy: f32, cx: f32, cy: f32) -> f32 {
+        for n in 0..self.max_iterations {
+            let (x2, y2) = (zx * zx, zy * zy);
+            if x2 + y2 > 256.0 {
+                // log2(log|z|), with log|z| = log(|z|^2) / 2
+                let log_z = libm::logf(x2 + y2) / 2.0;
+                return n as f32 + 1.0 - libm::log2f(log_z / core::f32::consts::LN_2);
+            }
+            zy = 2.0 * zx * zy + cy;
+            zx = x2 - y2 + cx;
+        }
+        -1.0
+    }
+
+    fn render_row(&mut self, y: usize) {
+        let scale = self.scale();
+        let pixel_size = 2.0 * scale / H as f32;
+        let (centre_x, centre_y) = match self.mode {
+            FractalMode::Mandelbrot => self.target,
+            FractalMode::Julia => (0.0, 0.0),
+        };
+        let (julia_x, julia_y) = (
+            Self::JULIA_RADIUS * libm::cosf(self.julia_angle),
+            Self::JULIA_RADIUS * libm::sinf(self.julia_angle),
+        );
+        let py = centre_y + (H as f32 / 2.0 - y as f32 - 0.5) * pixel_size;
+        for x in 0..W {
+            let px = centre_x + (x as f32 - W as f32 / 2.0 + 0.5) * pixel_size;
+            let value = match self.mode {
+                FractalMode::Mandelbrot => self.smooth_iterations(0.0, 0.0, px, py),
+                FractalMode::Julia => self.smooth_iterations(px, py, julia_x, julia_y),
+            };
+            self.iterations.set(x as i32, y as i32, value);
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum FractalUpdate {
+    Reset,
+    SetMode(FractalMode),
+    /// Zoom into one of the built in targets
+    SetTarget(u8),
+    /// Zoom into any point on the complex plane
+    SetCustomTarget {
+        x: f32,
+        y: f32,
+    },
+    /// The speed of the zoom, in e-foldings per second
+    SetZoomSpeed(f32),
+    /// The speed the Julia parameter moves, in radians per second
+    SetJuliaSpeed(f32),
+    SetMaxIterations(u16),
+    SetPalette(Palette),
+    /// How many rows are rendered each update, lower this if updates take too long
+    SetRowsPerUpdate(u8),
+}
+
+impl StateUpdate for FractalUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Fractal<W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = FractalUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = delta_time_us as f32 / 1_000_000.0;
+        self.colour_offset = (self.colour_offset + dt * 0.1) % 2.0;
+        match self.mode {
+            FractalMode::Mandelbrot => {
+                self.zoom_time += dt;
+                if self.scale() < Self::MIN_SCALE {
+                    self.next_target();
+                }
+            }
+            FractalMode::Julia => {
+                self.julia_angle =
+                    (self.julia_angle + self.julia_speed * dt) % core::f32::consts::TAU;
+            }
+        }
+        for _ in 0..self.rows_per_update {
+            self.render_row(self.row);
+            self.row = (self.row + 1) % H;
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(self.iterations.iter_with_index().map(|((x, y), n)| {
+            let colour = if *n < 0.0 {
+                Rgb888::new(0, 0, 0)
+            } else {
+                self.palette
+                    .sample_cyclic(libm::sqrtf(*n) * 0.25 + self.colour_offset)
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            FractalUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            FractalUpdate::SetMode(mode) => self.mode = mode,
+            FractalUpdate::SetTarget(i) => {
+                self.target_index = i as usize % TARGETS.len();
+                let (x, y) = TARGETS[self.target_index];
+                self.set_target(x, y);
+            }
+            FractalUpdate::SetCustomTarget { x, y } => self.set_target(x, y),
+            FractalUpdate::SetZoomSpeed(speed) => {
+                // keep the current view when changing speed
+                self.zoom_time *= self.zoom_speed / speed.max(1e-3);
+                self.zoom_speed = speed.max(1e-3);
+            }
+            FractalUpdate::SetJuliaSpeed(speed) => self.julia_speed = speed,
+            FractalUpdate::SetMaxIterations(n) => self.max_iterations = n.max(1),
+            FractalUpdate::SetPalette(palette) => self.palette = palette,
+            FractalUpdate::SetRowsPerUpdate(n) => self.rows_per_update = (n as usize).clamp(1, H),
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Fractal::new(FractalMode::Mandelbrot)
+    }
+
+    fn reset(&mut self) {
+        self.target_index = 0;
+        self.set_target(TARGETS[0].0, TARGETS[0].1);
+        self.julia_angle = 0.0;
+        self.row = 0;
+    }
+}
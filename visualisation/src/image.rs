@@ -0,0 +1,428 @@
+//! A compact container for small images, used to upload them to the panel.
+//!
+//! An image is a 16 byte header, then the palette if it has one, then the pixels in
+//! rows from the top left. Numbers are little endian.
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic, `HUBI`                                 |
+//! | 4      | 1    | version, currently 1                          |
+//! | 5      | 1    | pixel format, see [PixelFormat]               |
+//! | 6      | 1    | width                                         |
+//! | 7      | 1    | height                                        |
+//! | 8      | 2    | palette entries, 0 unless the format is indexed |
+//! | 10     | 2    | reserved, 0                                   |
+//! | 12     | 4    | CRC-32 of everything after the header         |
+//!
+//! Palette entries are 3 bytes of red, green and blue.
+
+use embedded_graphics::pixelcolor::Rgb888;
+
+pub const MAGIC: [u8; 4] = *b"HUBI";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 16;
+pub const MAX_PALETTE_LEN: usize = 256;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+    /// One byte per pixel, indexing into the palette
+    Indexed = 0,
+    /// Two bytes per pixel, with 5 bits of red in the top of the second byte
+    Rgb565 = 1,
+    /// Three bytes per pixel, red then green then blue
+    Rgb888 = 2,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Indexed => 1,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Rgb888 => 3,
+        }
+    }
+
+    fn from_u8(format: u8) -> Option<Self> {
+        match format {
+            0 => Some(PixelFormat::Indexed),
+            1 => Some(PixelFormat::Rgb565),
+            2 => Some(PixelFormat::Rgb888),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageError {
+    /// Not enough bytes for the header
+    TooShort,
+    BadMagic,
+    UnsupportedVersion,
+    UnknownFormat,
+    /// The palette is empty for an indexed image, too big, or there for an image
+    /// that isn't indexed
+    BadPalette,
+    /// The number of bytes doesn't match the header
+    WrongLength,
+    BadChecksum,
+    /// The buffer to encode into is too small
+    BufferTooSmall,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ImageHeader {
+    pub format: PixelFormat,
+    pub width: u8,
+    pub height: u8,
+    /// the number of palette entries
+    pub palette_len: u16,
+}
+
+impl ImageHeader {
+    /// The number of bytes after the header
+    pub fn payload_len(&self) -> usize {
+        self.palette_len as usize * 3
+            + self.width as usize * self.height as usize * self.format.bytes_per_pixel()
+    }
+
+    /// The number of bytes of the whole encoded image
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.payload_len()
+    }
+
+    fn check_palette(&self) -> Result<(), ImageError> {
+        let palette_len = self.palette_len as usize;
+        let valid = match self.format {
+            PixelFormat::Indexed => (1..=MAX_PALETTE_LEN).contains(&palette_len),
+            PixelFormat::Rgb565 | PixelFormat::Rgb888 => palette_len == 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ImageError::BadPalette)
+        }
+    }
+}
+
+/// The CRC-32 used by zip and ethernet. Done a bit at a time, which is slow but
+/// only happens once per image.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// An encoded image, borrowing the bytes it was read from
+#[derive(Copy, Clone)]
+pub struct Image<'a> {
+    header: ImageHeader,
+    palette: &'a [u8],
+    pixels: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    /// Read an image, checking the header and the length but not the checksum.
+    /// For images that have already been checked with [Image::decode].
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(ImageError::TooShort);
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(ImageError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(ImageError::UnsupportedVersion);
+        }
+        let header = ImageHeader {
+            format: PixelFormat::from_u8(bytes[5]).ok_or(ImageError::UnknownFormat)?,
+            width: bytes[6],
+            height: bytes[7],
+            palette_len: u16::from_le_bytes([bytes[8], bytes[9]]),
+        };
+        header.check_palette()?;
+        if bytes.len() != header.encoded_len() {
+            return Err(ImageError::WrongLength);
+        }
+        let (palette, pixels) = bytes[HEADER_LEN..].split_at(header.palette_len as usize * 3);
+        Ok(Image {
+            header,
+            palette,
+            pixels,
+        })
+    }
+
+    /// Read an image, checking the header, length and checksum
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        let image = Self::parse(bytes)?;
+        let checksum = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        if crc32(&bytes[HEADER_LEN..]) != checksum {
+            return Err(ImageError::BadChecksum);
+        }
+        Ok(image)
+    }
+
+    pub fn header(&self) -> ImageHeader {
+        self.header
+    }
+
+    /// The colour of a pixel, or None if it's outside the image. Indexes past the
+    /// end of the palette are black.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Rgb888> {
+        let (w, h) = (self.header.width as i32, self.header.height as i32);
+        if x < 0 || y < 0 || x >= w || y >= h {
+            return None;
+        }
+        let bytes_per_pixel = self.header.format.bytes_per_pixel();
+        let i = (y * w + x) as usize * bytes_per_pixel;
+        let pixel = &self.pixels[i..i + bytes_per_pixel];
+        Some(match self.header.format {
+            PixelFormat::Indexed => {
+                let entry = pixel[0] as usize * 3;
+                match self.palette.get(entry..entry + 3) {
+                    Some(rgb) => Rgb888::new(rgb[0], rgb[1], rgb[2]),
+                    None => Rgb888::new(0, 0, 0),
+                }
+            }
+            PixelFormat::Rgb565 => {
+                let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let (r, g, b) = (value >> 11, (value >> 5) & 0x3f, value & 0x1f);
+                // spread the bits out to fill the whole byte
+                Rgb888::new(
+                    (r << 3 | r >> 2) as u8,
+                    (g << 2 | g >> 4) as u8,
+                    (b << 3 | b >> 2) as u8,
+                )
+            }
+            PixelFormat::Rgb888 => Rgb888::new(pixel[0], pixel[1], pixel[2]),
+        })
+    }
+}
+
+/// Encode an image into `buffer`, returning the number of bytes used. The palette
+/// is 3 bytes per entry and has to be empty unless the format is indexed.
+pub fn encode(
+    format: PixelFormat,
+    width: u8,
+    height: u8,
+    palette: &[u8],
+    pixels: &[u8],
+    buffer: &mut [u8],
+) -> Result<usize, ImageError> {
+    if !palette.len().is_multiple_of(3) || palette.len() / 3 > MAX_PALETTE_LEN {
+        return Err(ImageError::BadPalette);
+    }
+    let header = ImageHeader {
+        format,
+        width,
+        height,
+        palette_len: (palette.len() / 3) as u16,
+    };
+    header.check_palette()?;
+    if pixels.len() != width as usize * height as usize * format.bytes_per_pixel() {
+        return Err(ImageError::WrongLength);
+    }
+    let len = header.encoded_len();
+    if buffer.len() < len {
+        return Err(ImageError::BufferTooSmall);
+    }
+
+    let (head, payload) = buffer[..len].split_at_mut(HEADER_LEN);
+    let (palette_out, pixels_out) = payload.split_at_mut(palette.len());
+    palette_out.copy_from_slice(palette);
+    pixels_out.copy_from_slice(pixels);
+    head[0..4].copy_from_slice(&MAGIC);
+    head[4] = VERSION;
+    head[5] = format as u8;
+    head[6] = width;
+    head[7] = height;
+    head[8..10].copy_from_slice(&header.palette_len.to_le_bytes());
+    head[10..12].fill(0);
+    head[12..16].copy_from_slice(&crc32(payload).to_le_bytes());
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [u8; 9] = [255, 0, 0, 0, 255, 0, 10, 20, 30];
+
+    fn encoded<const N: usize>(
+        format: PixelFormat,
+        width: u8,
+        height: u8,
+        palette: &[u8],
+        pixels: &[u8],
+    ) -> [u8; N] {
+        let mut buffer = [0; N];
+        assert_eq!(
+            encode(format, width, height, palette, pixels, &mut buffer),
+            Ok(N)
+        );
+        buffer
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn indexed_round_trip() {
+        let bytes = encoded::<{ HEADER_LEN + 9 + 6 }>(
+            PixelFormat::Indexed,
+            3,
+            2,
+            &PALETTE,
+            &[0, 1, 2, 2, 1, 3],
+        );
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(
+            image.header(),
+            ImageHeader {
+                format: PixelFormat::Indexed,
+                width: 3,
+                height: 2,
+                palette_len: 3,
+            }
+        );
+        assert_eq!(image.pixel(0, 0), Some(Rgb888::new(255, 0, 0)));
+        assert_eq!(image.pixel(1, 0), Some(Rgb888::new(0, 255, 0)));
+        assert_eq!(image.pixel(0, 1), Some(Rgb888::new(10, 20, 30)));
+        // past the end of the palette
+        assert_eq!(image.pixel(2, 1), Some(Rgb888::new(0, 0, 0)));
+        assert_eq!(image.pixel(3, 0), None);
+        assert_eq!(image.pixel(0, 2), None);
+        assert_eq!(image.pixel(-1, 0), None);
+    }
+
+    #[test]
+    fn rgb565_round_trip() {
+        let pixels = [0xf800u16, 0x07e0, 0x001f, 0x8410].map(u16::to_le_bytes);
+        let bytes =
+            encoded::<{ HEADER_LEN + 8 }>(PixelFormat::Rgb565, 2, 2, &[], pixels.as_flattened());
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.pixel(0, 0), Some(Rgb888::new(255, 0, 0)));
+        assert_eq!(image.pixel(1, 0), Some(Rgb888::new(0, 255, 0)));
+        assert_eq!(image.pixel(0, 1), Some(Rgb888::new(0, 0, 255)));
+        assert_eq!(image.pixel(1, 1), Some(Rgb888::new(132, 130, 132)));
+    }
+
+    #[test]
+    fn rgb888_round_trip() {
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let bytes = encoded::<{ HEADER_LEN + 9 }>(PixelFormat::Rgb888, 1, 3, &[], &pixels);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.pixel(0, 0), Some(Rgb888::new(1, 2, 3)));
+        assert_eq!(image.pixel(0, 2), Some(Rgb888::new(7, 8, 9)));
+        assert_eq!(image.pixel(1, 0), None);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let bytes = encoded::<{ HEADER_LEN + 3 }>(PixelFormat::Rgb888, 1, 1, &[], &[1, 2, 3]);
+        assert_eq!(
+            Image::decode(&bytes[..HEADER_LEN - 1]).err(),
+            Some(ImageError::TooShort)
+        );
+
+        let mut bad = bytes;
+        bad[0] = b'X';
+        assert_eq!(Image::decode(&bad).err(), Some(ImageError::BadMagic));
+        let mut bad = bytes;
+        bad[4] = VERSION + 1;
+        assert_eq!(
+            Image::decode(&bad).err(),
+            Some(ImageError::UnsupportedVersion)
+        );
+        let mut bad = bytes;
+        bad[5] = 3;
+        assert_eq!(Image::decode(&bad).err(), Some(ImageError::UnknownFormat));
+    }
+
+    #[test]
+    fn rejects_the_wrong_length() {
+        let bytes = encoded::<{ HEADER_LEN + 3 }>(PixelFormat::Rgb888, 1, 1, &[], &[1, 2, 3]);
+        assert_eq!(
+            Image::decode(&bytes[..HEADER_LEN + 2]).err(),
+            Some(ImageError::WrongLength)
+        );
+        let mut longer = [0; HEADER_LEN + 4];
+        longer[..HEADER_LEN + 3].copy_from_slice(&bytes);
+        assert_eq!(Image::decode(&longer).err(), Some(ImageError::WrongLength));
+        let mut buffer = [0; 64];
+        assert_eq!(
+            encode(PixelFormat::Rgb888, 2, 1, &[], &[1, 2, 3], &mut buffer),
+            Err(ImageError::WrongLength)
+        );
+        assert_eq!(
+            encode(
+                PixelFormat::Rgb888,
+                1,
+                1,
+                &[],
+                &[1, 2, 3],
+                &mut buffer[..HEADER_LEN]
+            ),
+            Err(ImageError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_palettes() {
+        let mut buffer = [0; 64];
+        // a palette on an image that isn't indexed
+        assert_eq!(
+            encode(PixelFormat::Rgb888, 1, 1, &PALETTE, &[1, 2, 3], &mut buffer),
+            Err(ImageError::BadPalette)
+        );
+        // no palette for an indexed image
+        assert_eq!(
+            encode(PixelFormat::Indexed, 1, 1, &[], &[0], &mut buffer),
+            Err(ImageError::BadPalette)
+        );
+        // not a whole number of entries
+        assert_eq!(
+            encode(PixelFormat::Indexed, 1, 1, &PALETTE[..4], &[0], &mut buffer),
+            Err(ImageError::BadPalette)
+        );
+        // too many entries
+        assert_eq!(
+            encode(
+                PixelFormat::Indexed,
+                1,
+                1,
+                &[0; (MAX_PALETTE_LEN + 1) * 3],
+                &[0],
+                &mut [0; 1024]
+            ),
+            Err(ImageError::BadPalette)
+        );
+
+        let mut bytes =
+            encoded::<{ HEADER_LEN + 3 + 1 }>(PixelFormat::Indexed, 1, 1, &PALETTE[..3], &[0]);
+        bytes[8] = 0;
+        assert_eq!(Image::decode(&bytes).err(), Some(ImageError::BadPalette));
+    }
+
+    #[test]
+    fn rejects_a_corrupted_payload() {
+        let mut bytes =
+            encoded::<{ HEADER_LEN + 9 + 2 }>(PixelFormat::Indexed, 2, 1, &PALETTE, &[0, 1]);
+        bytes[HEADER_LEN + 9] = 2;
+        assert_eq!(Image::decode(&bytes).err(), Some(ImageError::BadChecksum));
+        // parse doesn't check the checksum
+        assert!(Image::parse(&bytes).is_ok());
+        let mut bytes =
+            encoded::<{ HEADER_LEN + 9 + 2 }>(PixelFormat::Indexed, 2, 1, &PALETTE, &[0, 1]);
+        bytes[12] ^= 1;
+        assert_eq!(Image::decode(&bytes).err(), Some(ImageError::BadChecksum));
+    }
+}
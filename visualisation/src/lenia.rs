@@ -0,0 +1,335 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid};
+
+/// The largest kernel radius that there's space for
+pub const MAX_RADIUS: usize = 13;
+const MAX_TAPS: usize = (2 * MAX_RADIUS + 1) * (2 * MAX_RADIUS + 1);
+
+/// The Orbium glider, from Bert Chan's Lenia, in hundredths. It's designed for a radius of 13
+const ORBIUM: [[u8; 20]; 20] = [
+    [
+        0, 0, 0, 0, 0, 0, 10, 14, 10, 0, 0, 3, 3, 0, 0, 30, 0, 0, 0, 0,
+    ],
+    [
+        0, 0, 0, 0, 0, 8, 24, 30, 30, 18, 14, 15, 16, 15, 9, 20, 0, 0, 0, 0,
+    ],
+    [
+        0, 0, 0, 0, 0, 15, 34, 44, 46, 38, 18, 14, 11, 13, 19, 18, 45, 0, 0, 0,
+    ],
+    [
+        0, 0, 0, 0, 6, 13, 39, 50, 50, 37, 6, 0, 0, 0, 2, 16, 68, 0, 0, 0,
+    ],
+    [
+        0, 0, 0, 11, 17, 17, 33, 40, 38, 28, 14, 0, 0, 0, 0, 0, 18, 42, 0, 0,
+    ],
+    [
+        0, 0, 9, 18, 13, 6, 8, 26, 32, 32, 27, 0, 0, 0, 0, 0, 0, 82, 0, 0,
+    ],
+    [
+        27, 0, 16, 12, 0, 0, 0, 25, 38, 44, 45, 34, 0, 0, 0, 0, 0, 22, 17, 0,
+    ],
+    [
+        0, 7, 20, 2, 0, 0, 0, 31, 48, 57, 60, 57, 0, 0, 0, 0, 0, 0, 49, 0,
+    ],
+    [
+        0, 59, 19, 0, 0, 0, 0, 20, 57, 69, 76, 76, 49, 0, 0, 0, 0, 0, 36, 0,
+    ],
+    [
+        0, 58, 19, 0, 0, 0, 0, 0, 67, 83, 90, 92, 87, 12, 0, 0, 0, 0, 22, 7,
+    ],
+    [
+        0, 0, 46, 0, 0, 0, 0, 0, 70, 93, 100, 100, 100, 61, 0, 0, 0, 0, 18, 11,
+    ],
+    [
+        0, 0, 82, 0, 0, 0, 0, 0, 47, 100, 100, 98, 100, 96, 27, 0, 0, 0, 19, 10,
+    ],
+    [
+        0, 0, 46, 0, 0, 0, 0, 0, 25, 100, 100, 84, 92, 97, 54, 14, 4, 10, 21, 5,
+    ],
+    [
+        0, 0, 0, 40, 0, 0, 0, 0, 9, 80, 100, 82, 80, 85, 63, 31, 18, 19, 20, 1,
+    ],
+    [
+        0, 0, 0, 36, 10, 0, 0, 0, 5, 54, 86, 79, 74, 72, 60, 39, 28, 24, 13, 0,
+    ],
+    [
+        0, 0, 0, 1, 30, 7, 0, 0, 8, 36, 64, 70, 64, 60, 51, 39, 29, 19, 4, 0,
+    ],
+    [
+        0, 0, 0, 0, 10, 24, 14, 10, 15, 29, 45, 53, 52, 46, 40, 31, 21, 8, 0, 0,
+    ],
+    [
+        0, 0, 0, 0, 0, 8, 21, 21, 22, 29, 36, 39, 37, 33, 26, 18, 9, 0, 0, 0,
+    ],
+    [
+        0, 0, 0, 0, 0, 0, 3, 13, 19, 22, 24, 24, 23, 18, 13, 5, 0, 0, 0, 0,
+    ],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 6, 8, 9, 7, 5, 1, 0, 0, 0, 0, 0],
+];
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum LeniaPreset {
+    /// A single Orbium glider
+    Orbium,
+    /// A few blobs of random noise with the Orbium parameters
+    Soup,
+}
+
+/// One row of the kernel, whose weights are stored contiguously
+#[derive(Copy, Clone)]
+struct KernelRow {
+    dy: i8,
+    /// the row covers dx from -half_width to half_width
+    half_width: u8,
+    /// the index of the row's first weight
+    start: u16,
+}
+
+/// A continuous cellular automaton, where each cell is updated by a growth
+/// function of the weighted average of a ring of neighbours around it
+pub struct Lenia<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    board_1: Grid<f32, W, H>,
+    board_2: Grid<f32, W, H>,
+    board_1_current: bool,
+    /// the weights of the normalised kernel, stored row by row
+    weights: [f32; MAX_TAPS],
+    rows: [KernelRow; 2 * MAX_RADIUS + 1],
+    n_rows: usize,
+    rng: Rng,
+    radius: u8,
+    /// the centre of the growth function
+    mu: f32,
+    /// the width of the growth function
+    sigma: f32,
+    /// the fraction of the growth applied each step
+    dt: f32,
+    preset: LeniaPreset,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Lenia<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(preset: LeniaPreset, rng: Rng) -> Self {
+        let mut this = Lenia {
+            board_1: Grid::new(0.0),
+            board_2: Grid::new(0.0),
+            board_1_current: true,
+            weights: [0.0; MAX_TAPS],
+            rows: [KernelRow {
+                dy: 0,
+                half_width: 0,
+                start: 0,
+            }; 2 * MAX_RADIUS + 1],
+            n_rows: 0,
+            rng,
+            radius: 13,
+            mu: 0.15,
+            sigma: 0.015,
+            dt: 0.1,
+            preset,
+        };
+        this.load_preset(preset);
+        this
+    }
+
+    pub fn load_preset(&mut self, preset: LeniaPreset) {
+        self.preset = preset;
+        self.radius = 13;
+        self.mu = 0.15;
+        self.sigma = 0.015;
+        self.dt = 0.1;
+        self.build_kernel();
+
+        self.board_1_current = true;
+        self.board_1.buffer_mut().fill(0.0);
+        match preset {
+            LeniaPreset::Orbium => {
+                let (ox, oy) = (W as i32 / 2 - 10, H as i32 / 2 - 10);
+                for (y, row) in ORBIUM.iter().enumerate() {
+                    for (x, v) in row.iter().enumerate() {
+                        self.board_1
+                            .set(ox + x as i32, oy + y as i32, *v as f32 / 100.0);
+                    }
+                }
+            }
+            LeniaPreset::Soup => {
+                for _ in 0..4 {
+                    let (cx, cy) = self.board_1.random_coord(&mut self.rng);
+                    let r = self.radius as i32 * 3 / 4;
+                    for dy in -r..r {
+                        for dx in -r..r {
+                            let v = self.rng.unit_f32();
+                            let (x, y) = (
+                                (cx + dx).rem_euclid(W as i32),
+                                (cy + dy).rem_euclid(H as i32),
+                            );
+                            self.board_1.set(x, y, v);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_radius(&mut self, radius: u8) {
+        self.radius = radius.clamp(1, MAX_RADIUS as u8);
+        self.build_kernel();
+    }
+
+    /// Fill in the kernel with a smooth bump on a ring at half the radius, normalised
+    /// so that the weights sum to one. Each row of the kernel only covers the disc
+    /// of the radius, so the convolution doesn't spend time on the corners.
+    fn build_kernel(&mut self) {
+        let r = self.radius as i32;
+        let mut n = 0;
+        let mut total = 0.0;
+        for (row, dy) in (-r..=r).enumerate() {
+            let half_width = libm::sqrtf((r * r - dy * dy) as f32) as i32;
+            self.rows[row] = KernelRow {
+                dy: dy as i8,
+                half_width: half_width as u8,
+                start: n as u16,
+            };
+            for dx in -half_width..=half_width {
+                let dist = libm::sqrtf((dx * dx + dy * dy) as f32) / r as f32;
+                let weight = if dist <= 0.0 || dist >= 1.0 {
+                    0.0
+                } else {
+                    libm::expf(4.0 - 1.0 / (dist * (1.0 - dist)))
+                };
+                self.weights[n] = weight;
+                total += weight;
+                n += 1;
+            }
+        }
+        self.weights[..n].iter_mut().for_each(|w| *w /= total);
+        self.n_rows = 2 * r as usize + 1;
+    }
+
+    /// How much a cell with the given potential grows, between -1 and 1
+    fn growth(potential: f32, mu: f32, sigma: f32) -> f32 {
+        let d = potential - mu;
+        2.0 * libm::expf(-d * d / (2.0 * sigma * sigma)) - 1.0
+    }
+
+    fn step(&mut self) {
+        let (write, read) = if self.board_1_current {
+            (&mut self.board_2, &self.board_1)
+        } else {
+            (&mut self.board_1, &self.board_2)
+        };
+        let cells = read.buffer();
+
+        for y in 0..H {
+            let mut potentials = [0.0f32; W];
+            for row in self.rows[..self.n_rows].iter() {
+                let sy = (y as i32 + row.dy as i32).rem_euclid(H as i32) as usize;
+                let source = &cells[sy * W..(sy + 1) * W];
+                let k = row.half_width as usize;
+                let weights = &self.weights[row.start as usize..row.start as usize + 2 * k + 1];
+                for (x, potential) in potentials.iter_mut().enumerate() {
+                    *potential += if x >= k && x + k < W {
+                        // away from the edges the row is a plain dot product
+                        weights
+                            .iter()
+                            .zip(source[x - k..=x + k].iter())
+                            .map(|(w, c)| w * c)
+                            .sum::<f32>()
+                    } else {
+                        weights
+                            .iter()
+                            .enumerate()
+                            .map(|(i, w)| w * source[(x + W + i - k) % W])
+                            .sum::<f32>()
+                    };
+                }
+            }
+            for (x, potential) in potentials.into_iter().enumerate() {
+                let index = y * W + x;
+                let growth = Self::growth(potential, self.mu, self.sigma);
+                write.buffer_mut()[index] = (cells[index] + self.dt * growth).clamp(0.0, 1.0);
+            }
+        }
+
+        self.board_1_current = !self.board_1_current;
+    }
+
+    fn colour(v: f32) -> Rgb888 {
+        // black -> blue -> cyan -> yellow
+        let v = v.clamp(0.0, 1.0);
+        let r = ((v - 0.6).max(0.0) * 2.5 * 255.0) as u8;
+        let g = ((v - 0.25).max(0.0) * 1.33 * 255.0).min(255.0) as u8;
+        let b = ((v * 3.0).min(1.0) * (1.0 - (v - 0.6).max(0.0) * 2.5) * 255.0) as u8;
+        Rgb888::new(r, g, b)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum LeniaUpdate {
+    Reset,
+    LoadPreset(LeniaPreset),
+    /// The radius of the kernel, up to `MAX_RADIUS`
+    SetRadius(u8),
+    SetMu(f32),
+    SetSigma(f32),
+    SetTimeStep(f32),
+}
+
+impl StateUpdate for LeniaUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Lenia<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = LeniaUpdate;
+
+    fn update(&mut self, _delta_time_us: u32) -> bool {
+        self.step();
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let board = if self.board_1_current {
+            &self.board_1
+        } else {
+            &self.board_2
+        };
+        let _ = target.draw_iter(
+            board
+                .iter_with_index()
+                .map(|((x, y), v)| Pixel(Point::new(x, y), Self::colour(*v))),
+        );
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            LeniaUpdate::Reset => self.reset(),
+            LeniaUpdate::LoadPreset(preset) => self.load_preset(preset),
+            LeniaUpdate::SetRadius(radius) => self.set_radius(radius),
+            LeniaUpdate::SetMu(mu) => self.mu = mu,
+            LeniaUpdate::SetSigma(sigma) => self.sigma = sigma.max(1e-4),
+            LeniaUpdate::SetTimeStep(dt) => self.dt = dt.clamp(0.0, 1.0),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Lenia::new(LeniaPreset::Orbium, rng)
+    }
+
+    fn reset(&mut self) {
+        self.load_preset(self.preset);
+    }
+}
@@ -2,21 +2,94 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
 
+pub use attractor::{Attractor, AttractorKind, AttractorUpdate};
+pub use audio::{AudioAnalyser, AudioFrame};
+pub use blocks::{Blocks, BlocksUpdate};
+pub use boids::{Boids, BoidsUpdate};
+pub use bouncing_balls::{BouncingBalls, BouncingBallsUpdate};
+pub use breakout::{Breakout, BreakoutUpdate};
+pub use clock::{Clock, ClockFace, ClockUpdate, TimeOfDay, WallClock};
 use core::convert::Infallible;
+pub use cyclic_ca::{CyclicCa, CyclicCaUpdate, CyclicRule, Neighbourhood};
+pub use digital_rain::{DigitalRain, DigitalRainUpdate};
+pub use dla::{Dla, DlaSeed, DlaUpdate};
 use embedded_graphics::pixelcolor::Rgb888;
 use embedded_graphics::prelude::DrawTarget;
+pub use epidemic::{Epidemic, EpidemicUpdate};
+pub use falling_sand::{FallingSand, FallingSandUpdate, Material};
+pub use fireworks::{Fireworks, FireworksUpdate};
+pub use flow_field::{FlowField, FlowFieldUpdate};
+pub use forest_fire::{ForestFire, ForestFireUpdate};
+pub use fractal::{Fractal, FractalMode, FractalUpdate};
 pub use game_of_life::{GameOfLife, GameOfLifeUpdate};
 pub use ising::{Ising, IsingUpdate};
+pub use lenia::{Lenia, LeniaPreset, LeniaUpdate};
+pub use marquee::{
+    MAX_MESSAGE_LEN, Marquee, MarqueeFont, MarqueeMessage, MarqueeUpdate, ScrollDirection,
+    TextColour,
+};
+pub use maze::{Maze, MazeGenerator, MazeSolver, MazeUpdate};
+pub use metaballs::{Metaballs, MetaballsUpdate};
+pub use polyhedron::{MeshKind, Polyhedron, PolyhedronUpdate, RenderMode};
+pub use pong::{Pong, PongUpdate};
+pub use ripple::{Ripple, RippleUpdate};
 pub use sand_pile::{SandPile, SandPileStateUpdate};
+pub use self_similar::{
+    IfsMap, IfsSystem, LSystem, LSystemRule, SelfSimilar, SelfSimilarPreset, SelfSimilarUpdate,
+};
+pub use slideshow::{IMAGE_CHUNK_LEN, Slideshow, SlideshowUpdate, Transition};
+pub use snake::{Snake, SnakeUpdate};
+pub use sorting::{SortAlgorithm, Sorting, SortingUpdate};
+pub use spectrum::{Spectrum, SpectrumMode, SpectrumUpdate};
+pub use starfield::{Starfield, StarfieldUpdate};
 pub use test_vis::{TestVis, TestVisUpdate};
 pub use turmite::{Turmite, TurmiteUpdate};
+pub use wator::{Wator, WatorUpdate};
 
+mod attractor;
+pub mod audio;
+mod blocks;
+mod boids;
+mod bouncing_balls;
+mod breakout;
+mod clock;
+mod cyclic_ca;
+mod digital_rain;
+mod dla;
+mod epidemic;
+mod falling_sand;
+mod fireworks;
+mod flow_field;
+mod forest_fire;
+mod fractal;
 mod game_of_life;
 mod grid;
+pub mod image;
 mod ising;
+mod lenia;
+mod marquee;
+mod maze;
+mod metaballs;
+pub mod noise;
+pub mod palette;
+pub mod particles;
+mod polyhedron;
+mod pong;
+mod queue;
+pub mod render3d;
+mod ripple;
 mod sand_pile;
+mod self_similar;
+mod slideshow;
+mod snake;
+mod sorting;
+mod spectrum;
+mod starfield;
 mod test_vis;
+mod text;
 mod turmite;
+mod wator;
+mod xorshift;
 
 pub trait RngU32 {
     fn next_u32(&mut self) -> u32;
@@ -25,6 +98,22 @@ pub trait RngU32 {
         let n = (self.next_u32() % 100_000) as f32 / 100_000.0;
         n
     }
+    /// true with probability `p`, with the full resolution of a u32 so that
+    /// very small probabilities still work
+    fn chance(&mut self, p: f32) -> bool {
+        self.next_u32() < (p * u32::MAX as f32) as u32
+    }
+}
+
+/// A button press from a controller, for the visualisations that can be played
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Input {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
 }
 
 pub trait StateUpdate: serde::Serialize + for<'de> serde::Deserialize<'de> {}
@@ -37,8 +126,13 @@ pub trait Visualisation<Rng: RngU32> {
     /// The update function, returns true if we should draw a new frame
     fn update(&mut self, delta_time_us: u32) -> bool;
     fn draw<D: DrawTarget<Color = Rgb888, Error = Infallible>>(&mut self, target: &mut D);
+    /// Handle a button press from a controller, ignored by default
+    fn input(&mut self, _input: Input) {}
+    /// Handle a block of sound from the microphone, ignored by default
+    fn audio(&mut self, _frame: &AudioFrame) {}
 }
 
+/// Variants are encoded by their position, so new ones go on the end
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum VisualisationUpdate {
     SandPile(SandPileStateUpdate),
@@ -46,6 +140,37 @@ pub enum VisualisationUpdate {
     GameOfLife(GameOfLifeUpdate),
     Turmite(TurmiteUpdate),
     Ising(IsingUpdate),
+    Boids(BoidsUpdate),
+    FallingSand(FallingSandUpdate),
+    DigitalRain(DigitalRainUpdate),
+    Polyhedron(PolyhedronUpdate),
+    Starfield(StarfieldUpdate),
+    Lenia(LeniaUpdate),
+    Fractal(FractalUpdate),
+    Maze(MazeUpdate),
+    Snake(SnakeUpdate),
+    Blocks(BlocksUpdate),
+    Pong(PongUpdate),
+    Breakout(BreakoutUpdate),
+    Ripple(RippleUpdate),
+    ForestFire(ForestFireUpdate),
+    Epidemic(EpidemicUpdate),
+    Attractor(AttractorUpdate),
+    Dla(DlaUpdate),
+    Metaballs(MetaballsUpdate),
+    Clock(ClockUpdate),
+    Marquee(MarqueeUpdate),
+    Slideshow(SlideshowUpdate),
+    FlowField(FlowFieldUpdate),
+    BouncingBalls(BouncingBallsUpdate),
+    CyclicCa(CyclicCaUpdate),
+    Spectrum(SpectrumUpdate),
+    Sorting(SortingUpdate),
+    Wator(WatorUpdate),
+    SelfSimilar(SelfSimilarUpdate),
+    Fireworks(FireworksUpdate),
+    /// Controller input, sent to whichever visualisation is running
+    Input(Input),
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -55,6 +180,35 @@ pub enum SetState {
     GameOfLife,
     Turmite,
     Ising,
+    Boids,
+    FallingSand,
+    DigitalRain,
+    Polyhedron,
+    Starfield,
+    Lenia,
+    Fractal,
+    Maze,
+    Snake,
+    Blocks,
+    Pong,
+    Breakout,
+    Ripple,
+    ForestFire,
+    Epidemic,
+    Attractor,
+    Dla,
+    Metaballs,
+    Clock,
+    Marquee,
+    Slideshow,
+    FlowField,
+    BouncingBalls,
+    CyclicCa,
+    Spectrum,
+    Sorting,
+    Wator,
+    SelfSimilar,
+    Fireworks,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -69,10 +223,37 @@ pub enum CurrentVisualisationState<Rng> {
     GameOfLife(GameOfLife<Rng, 64, 32>),
     Turmite(Turmite<64, 32>),
     Ising(Ising<Rng, 64, 32>),
+    Boids(Boids<Rng, 64, 32>),
+    FallingSand(FallingSand<Rng, 64, 32>),
+    DigitalRain(DigitalRain<Rng, 64, 32>),
+    Polyhedron(Polyhedron<64, 32>),
+    Starfield(Starfield<Rng, 64, 32>),
+    Lenia(Lenia<Rng, 64, 32>),
+    Fractal(Fractal<64, 32>),
+    Maze(Maze<Rng, 64, 32>),
+    Snake(Snake<Rng, 64, 32>),
+    Blocks(Blocks<Rng, 64, 32>),
+    Pong(Pong<Rng, 64, 32>),
+    Breakout(Breakout<Rng, 64, 32>),
+    Ripple(Ripple<Rng, 64, 32>),
+    ForestFire(ForestFire<Rng, 64, 32>),
+    Epidemic(Epidemic<Rng, 64, 32>),
+    Attractor(Attractor<Rng, 64, 32>),
+    Dla(Dla<Rng, 64, 32>),
+    Metaballs(Metaballs<Rng, 64, 32>),
+    Clock(Clock<64, 32>),
+    Marquee(Marquee<64, 32>),
+    Slideshow(Slideshow<64, 32>),
+    FlowField(FlowField<Rng, 64, 32>),
+    BouncingBalls(BouncingBalls<Rng, 64, 32>),
+    CyclicCa(CyclicCa<Rng, 64, 32>),
+    Spectrum(Spectrum<64, 32>),
+    Sorting(Sorting<Rng, 64, 32>),
+    Wator(Wator<Rng, 64, 32>),
+    SelfSimilar(SelfSimilar<Rng, 64, 32>),
+    Fireworks(Fireworks<Rng, 64, 32>),
 }
 
-pub 
-
 impl<Rng: RngU32> CurrentVisualisationState<Rng> {
     pub fn update(&mut self, delta_time_us: u32) -> bool {
         match self {
@@ -85,6 +266,47 @@ impl<Rng: RngU32> CurrentVisualisationState<Rng> {
                 <Turmite<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
             }
             CurrentVisualisationState::Ising(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Boids(s) => s.update(delta_time_us),
+            CurrentVisualisationState::FallingSand(s) => s.update(delta_time_us),
+            CurrentVisualisationState::DigitalRain(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Polyhedron(s) => {
+                <Polyhedron<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::Starfield(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Lenia(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Fractal(s) => {
+                <Fractal<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::Maze(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Snake(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Blocks(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Pong(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Breakout(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Ripple(s) => s.update(delta_time_us),
+            CurrentVisualisationState::ForestFire(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Epidemic(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Attractor(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Dla(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Metaballs(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Clock(s) => {
+                <Clock<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::Marquee(s) => {
+                <Marquee<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::Slideshow(s) => {
+                <Slideshow<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::FlowField(s) => s.update(delta_time_us),
+            CurrentVisualisationState::BouncingBalls(s) => s.update(delta_time_us),
+            CurrentVisualisationState::CyclicCa(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Spectrum(s) => {
+                <Spectrum<64, 32> as Visualisation<Rng>>::update(s, delta_time_us)
+            }
+            CurrentVisualisationState::Sorting(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Wator(s) => s.update(delta_time_us),
+            CurrentVisualisationState::SelfSimilar(s) => s.update(delta_time_us),
+            CurrentVisualisationState::Fireworks(s) => s.update(delta_time_us),
         }
     }
 
@@ -99,6 +321,167 @@ impl<Rng: RngU32> CurrentVisualisationState<Rng> {
                 <Turmite<64, 32> as Visualisation<Rng>>::draw(s, target)
             }
             CurrentVisualisationState::Ising(s) => s.draw(target),
+            CurrentVisualisationState::Boids(s) => s.draw(target),
+            CurrentVisualisationState::FallingSand(s) => s.draw(target),
+            CurrentVisualisationState::DigitalRain(s) => s.draw(target),
+            CurrentVisualisationState::Polyhedron(s) => {
+                <Polyhedron<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::Starfield(s) => s.draw(target),
+            CurrentVisualisationState::Lenia(s) => s.draw(target),
+            CurrentVisualisationState::Fractal(s) => {
+                <Fractal<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::Maze(s) => s.draw(target),
+            CurrentVisualisationState::Snake(s) => s.draw(target),
+            CurrentVisualisationState::Blocks(s) => s.draw(target),
+            CurrentVisualisationState::Pong(s) => s.draw(target),
+            CurrentVisualisationState::Breakout(s) => s.draw(target),
+            CurrentVisualisationState::Ripple(s) => s.draw(target),
+            CurrentVisualisationState::ForestFire(s) => s.draw(target),
+            CurrentVisualisationState::Epidemic(s) => s.draw(target),
+            CurrentVisualisationState::Attractor(s) => s.draw(target),
+            CurrentVisualisationState::Dla(s) => s.draw(target),
+            CurrentVisualisationState::Metaballs(s) => s.draw(target),
+            CurrentVisualisationState::Clock(s) => {
+                <Clock<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::Marquee(s) => {
+                <Marquee<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::Slideshow(s) => {
+                <Slideshow<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::FlowField(s) => s.draw(target),
+            CurrentVisualisationState::BouncingBalls(s) => s.draw(target),
+            CurrentVisualisationState::CyclicCa(s) => s.draw(target),
+            CurrentVisualisationState::Spectrum(s) => {
+                <Spectrum<64, 32> as Visualisation<Rng>>::draw(s, target)
+            }
+            CurrentVisualisationState::Sorting(s) => s.draw(target),
+            CurrentVisualisationState::Wator(s) => s.draw(target),
+            CurrentVisualisationState::SelfSimilar(s) => s.draw(target),
+            CurrentVisualisationState::Fireworks(s) => s.draw(target),
+        }
+    }
+
+    pub fn input(&mut self, input: Input) {
+        match self {
+            CurrentVisualisationState::SandPile(sand_pile) => sand_pile.input(input),
+            CurrentVisualisationState::TestVis(test_vis) => {
+                <TestVis as Visualisation<Rng>>::input(test_vis, input)
+            }
+            CurrentVisualisationState::GameOfLife(s) => s.input(input),
+            CurrentVisualisationState::Turmite(s) => {
+                <Turmite<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Ising(s) => s.input(input),
+            CurrentVisualisationState::Boids(s) => s.input(input),
+            CurrentVisualisationState::FallingSand(s) => s.input(input),
+            CurrentVisualisationState::DigitalRain(s) => s.input(input),
+            CurrentVisualisationState::Polyhedron(s) => {
+                <Polyhedron<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Starfield(s) => s.input(input),
+            CurrentVisualisationState::Lenia(s) => s.input(input),
+            CurrentVisualisationState::Fractal(s) => {
+                <Fractal<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Maze(s) => s.input(input),
+            CurrentVisualisationState::Snake(s) => s.input(input),
+            CurrentVisualisationState::Blocks(s) => s.input(input),
+            CurrentVisualisationState::Pong(s) => s.input(input),
+            CurrentVisualisationState::Breakout(s) => s.input(input),
+            CurrentVisualisationState::Ripple(s) => s.input(input),
+            CurrentVisualisationState::ForestFire(s) => s.input(input),
+            CurrentVisualisationState::Epidemic(s) => s.input(input),
+            CurrentVisualisationState::Attractor(s) => s.input(input),
+            CurrentVisualisationState::Dla(s) => s.input(input),
+            CurrentVisualisationState::Metaballs(s) => s.input(input),
+            CurrentVisualisationState::Clock(s) => {
+                <Clock<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Marquee(s) => {
+                <Marquee<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Slideshow(s) => {
+                <Slideshow<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::FlowField(s) => s.input(input),
+            CurrentVisualisationState::BouncingBalls(s) => s.input(input),
+            CurrentVisualisationState::CyclicCa(s) => s.input(input),
+            CurrentVisualisationState::Spectrum(s) => {
+                <Spectrum<64, 32> as Visualisation<Rng>>::input(s, input)
+            }
+            CurrentVisualisationState::Sorting(s) => s.input(input),
+            CurrentVisualisationState::Wator(s) => s.input(input),
+            CurrentVisualisationState::SelfSimilar(s) => s.input(input),
+            CurrentVisualisationState::Fireworks(s) => s.input(input),
+        }
+    }
+
+    /// A new beat sensitivity sent to the running visualisation, to pass on to the
+    /// [AudioAnalyser]
+    pub fn take_beat_sensitivity(&mut self) -> Option<f32> {
+        match self {
+            CurrentVisualisationState::Spectrum(s) => s.take_beat_sensitivity(),
+            _ => None,
+        }
+    }
+
+    /// Pass a block of sound from the microphone on to the running visualisation
+    pub fn audio(&mut self, frame: &AudioFrame) {
+        match self {
+            CurrentVisualisationState::SandPile(sand_pile) => sand_pile.audio(frame),
+            CurrentVisualisationState::TestVis(test_vis) => {
+                <TestVis as Visualisation<Rng>>::audio(test_vis, frame)
+            }
+            CurrentVisualisationState::GameOfLife(s) => s.audio(frame),
+            CurrentVisualisationState::Turmite(s) => {
+                <Turmite<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Ising(s) => s.audio(frame),
+            CurrentVisualisationState::Boids(s) => s.audio(frame),
+            CurrentVisualisationState::FallingSand(s) => s.audio(frame),
+            CurrentVisualisationState::DigitalRain(s) => s.audio(frame),
+            CurrentVisualisationState::Polyhedron(s) => {
+                <Polyhedron<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Starfield(s) => s.audio(frame),
+            CurrentVisualisationState::Lenia(s) => s.audio(frame),
+            CurrentVisualisationState::Fractal(s) => {
+                <Fractal<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Maze(s) => s.audio(frame),
+            CurrentVisualisationState::Snake(s) => s.audio(frame),
+            CurrentVisualisationState::Blocks(s) => s.audio(frame),
+            CurrentVisualisationState::Pong(s) => s.audio(frame),
+            CurrentVisualisationState::Breakout(s) => s.audio(frame),
+            CurrentVisualisationState::Ripple(s) => s.audio(frame),
+            CurrentVisualisationState::ForestFire(s) => s.audio(frame),
+            CurrentVisualisationState::Epidemic(s) => s.audio(frame),
+            CurrentVisualisationState::Attractor(s) => s.audio(frame),
+            CurrentVisualisationState::Dla(s) => s.audio(frame),
+            CurrentVisualisationState::Metaballs(s) => s.audio(frame),
+            CurrentVisualisationState::Clock(s) => {
+                <Clock<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Marquee(s) => {
+                <Marquee<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Slideshow(s) => {
+                <Slideshow<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::FlowField(s) => s.audio(frame),
+            CurrentVisualisationState::BouncingBalls(s) => s.audio(frame),
+            CurrentVisualisationState::CyclicCa(s) => s.audio(frame),
+            CurrentVisualisationState::Spectrum(s) => {
+                <Spectrum<64, 32> as Visualisation<Rng>>::audio(s, frame)
+            }
+            CurrentVisualisationState::Sorting(s) => s.audio(frame),
+            CurrentVisualisationState::Wator(s) => s.audio(frame),
+            CurrentVisualisationState::SelfSimilar(s) => s.audio(frame),
+            CurrentVisualisationState::Fireworks(s) => s.audio(frame),
         }
     }
 }
@@ -0,0 +1,301 @@
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    Pixel,
+    mono_font::{
+        MonoFont, MonoTextStyle,
+        iso_8859_1::{FONT_4X6, FONT_6X10, FONT_10X20},
+    },
+    pixelcolor::Rgb888,
+    prelude::{Dimensions, DrawTarget, Drawable, Point, RgbColor},
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+use heapless::{Deque, String};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    palette::Palette,
+    text::{PROPORTIONAL_HEIGHT, draw_proportional, proportional_width},
+};
+
+/// The longest message, in bytes of UTF-8
+pub const MAX_MESSAGE_LEN: usize = 128;
+const MAX_MESSAGES: usize = 8;
+/// Seconds for the rainbow effect to go through all its colours
+const RAINBOW_PERIOD: f32 = 2.0;
+/// How much of the rainbow is spread across each pixel of text
+const RAINBOW_SPREAD: f32 = 0.02;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MarqueeFont {
+    /// 4x6 pixels
+    Small,
+    /// 6x10 pixels
+    Medium,
+    /// 10x20 pixels
+    Large,
+    /// 5 pixels high with narrow letters, to fit the most text on the panel. Only
+    /// covers ASCII, and shows everything in capitals.
+    Proportional,
+}
+
+impl MarqueeFont {
+    fn mono(self) -> Option<&'static MonoFont<'static>> {
+        match self {
+            MarqueeFont::Small => Some(&FONT_4X6),
+            MarqueeFont::Medium => Some(&FONT_6X10),
+            MarqueeFont::Large => Some(&FONT_10X20),
+            MarqueeFont::Proportional => None,
+        }
+    }
+
+    fn height(self) -> i32 {
+        match self.mono() {
+            Some(font) => font.character_size.height as i32,
+            None => PROPORTIONAL_HEIGHT,
+        }
+    }
+
+    fn width(self, text: &str) -> i32 {
+        match self.mono() {
+            Some(font) => text.chars().count() as i32 * advance(font),
+            None => proportional_width(text),
+        }
+    }
+}
+
+/// How far along each character of a mono font is from the last
+fn advance(font: &MonoFont) -> i32 {
+    (font.character_size.width + font.character_spacing) as i32
+}
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum TextColour {
+    Solid {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// Rainbow colours flowing along the text
+    Rainbow,
+    /// A palette from the top of the text to the bottom
+    Gradient(Palette),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ScrollDirection {
+    Left,
+    Right,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct MarqueeMessage {
+    pub text: String<MAX_MESSAGE_LEN>,
+    pub colour: TextColour,
+}
+
+/// Passes drawing on to another target, choosing the colour of each pixel from
+/// where it is
+struct Recolour<'a, D, F> {
+    target: &'a mut D,
+    colour: F,
+}
+
+impl<D: Dimensions, F> Dimensions for Recolour<'_, D, F> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D: DrawTarget<Color = Rgb888, Error = Infallible>, F: Fn(Point) -> Rgb888> DrawTarget
+    for Recolour<'_, D, F>
+{
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Rgb888>>>(
+        &mut self,
+        pixels: I,
+    ) -> Result<(), Infallible> {
+        let colour = &self.colour;
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, _)| Pixel(point, colour(point))),
+        )
+    }
+}
+
+/// Scrolls messages sent from the phone across the panel, one after another.
+///
+/// The queued messages take turns, so a single message repeats forever.
+pub struct Marquee<const W: usize, const H: usize> {
+    messages: Deque<MarqueeMessage, MAX_MESSAGES>,
+    font: MarqueeFont,
+    /// pixels per second
+    speed: f32,
+    direction: ScrollDirection,
+    /// how far the current message has moved since it started coming on, in pixels
+    offset: f32,
+    /// seconds, wrapped around to keep the precision
+    time: f32,
+}
+
+impl<const W: usize, const H: usize> Marquee<W, H> {
+    pub fn new(font: MarqueeFont, speed: f32) -> Self {
+        let mut messages = Deque::new();
+        let mut text = String::new();
+        let _ = text.push_str("HELLO WORLD");
+        let _ = messages.push_back(MarqueeMessage {
+            text,
+            colour: TextColour::Rainbow,
+        });
+        Marquee {
+            messages,
+            font,
+            speed,
+            direction: ScrollDirection::Left,
+            offset: 0.0,
+            time: 0.0,
+        }
+    }
+
+    fn queue(&mut self, message: MarqueeMessage) {
+        if self.messages.is_full() {
+            self.messages.pop_front();
+            self.offset = 0.0;
+        }
+        let _ = self.messages.push_back(message);
+    }
+
+    /// Draw in a mono font, leaving out the characters that are off the panel
+    fn draw_mono<D: DrawTarget<Color = Rgb888, Error = Infallible>>(
+        target: &mut D,
+        font: &MonoFont,
+        text: &str,
+        position: Point,
+    ) {
+        let advance = advance(font);
+        let skip = (-position.x / advance).max(0) as usize;
+        let count = W / advance as usize + 2;
+        let start = text.char_indices().nth(skip).map_or(text.len(), |(i, _)| i);
+        let end = text[start..]
+            .char_indices()
+            .nth(count)
+            .map_or(text.len(), |(i, _)| start + i);
+        let _ = Text::with_baseline(
+            &text[start..end],
+            Point::new(position.x + skip as i32 * advance, position.y),
+            MonoTextStyle::new(font, Rgb888::WHITE),
+            Baseline::Top,
+        )
+        .draw(target);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum MarqueeUpdate {
+    Reset,
+    /// Replace all the queued messages with this one
+    SetMessage(MarqueeMessage),
+    /// Add a message to the end of the queue, dropping the oldest if it's full
+    QueueMessage(MarqueeMessage),
+    ClearMessages,
+    SetFont(MarqueeFont),
+    /// In pixels per second
+    SetSpeed(f32),
+    SetDirection(ScrollDirection),
+}
+
+impl StateUpdate for MarqueeUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Marquee<W, H> {
+    type StateUpdate = MarqueeUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        self.time = (self.time + dt) % RAINBOW_PERIOD;
+        let Some(message) = self.messages.front() else {
+            return true;
+        };
+        self.offset += self.speed * dt;
+        // the whole message has gone past, so move on to the next one
+        let distance = (self.font.width(&message.text) + W as i32) as f32;
+        if self.offset >= distance {
+            self.offset = 0.0;
+            if let Some(message) = self.messages.pop_front() {
+                let _ = self.messages.push_back(message);
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let Some(message) = self.messages.front() else {
+            return;
+        };
+        let width = self.font.width(&message.text);
+        let height = self.font.height();
+        let offset = libm::floorf(self.offset) as i32;
+        let x = match self.direction {
+            ScrollDirection::Left => W as i32 - offset,
+            ScrollDirection::Right => offset - width,
+        };
+        let top = (H as i32 - height) / 2;
+
+        let phase = self.time / RAINBOW_PERIOD;
+        let colour = message.colour;
+        let mut target = Recolour {
+            target,
+            colour: |point: Point| match colour {
+                TextColour::Solid { r, g, b } => Rgb888::new(r, g, b),
+                TextColour::Rainbow => {
+                    let t = point.x as f32 * RAINBOW_SPREAD - phase;
+                    Palette::Rainbow.sample(t - libm::floorf(t))
+                }
+                TextColour::Gradient(palette) => {
+                    palette.sample(0.25 + 0.75 * (point.y - top) as f32 / height as f32)
+                }
+            },
+        };
+        let position = Point::new(x, top);
+        match self.font.mono() {
+            Some(font) => Self::draw_mono(&mut target, font, &message.text, position),
+            None => draw_proportional(&mut target, &message.text, position, Rgb888::WHITE),
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            MarqueeUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            MarqueeUpdate::SetMessage(message) => {
+                self.messages.clear();
+                self.queue(message);
+                self.offset = 0.0;
+            }
+            MarqueeUpdate::QueueMessage(message) => self.queue(message),
+            MarqueeUpdate::ClearMessages => self.messages.clear(),
+            MarqueeUpdate::SetFont(font) => self.font = font,
+            MarqueeUpdate::SetSpeed(speed) => self.speed = speed.clamp(1.0, 200.0),
+            MarqueeUpdate::SetDirection(direction) => self.direction = direction,
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Marquee::new(MarqueeFont::Medium, 20.0)
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0.0;
+        self.time = 0.0;
+    }
+}
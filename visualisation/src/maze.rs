@@ -0,0 +1,620 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid, queue::Queue};
+
+/// Room for every cell of a 64x32 maze, which is the most any of the algorithms need
+const QUEUE_SIZE: usize = 1024;
+
+/// Offsets to the neighbouring pixels, indexed by direction
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum MazeGenerator {
+    RecursiveBacktracker,
+    Prims,
+    Wilsons,
+}
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum MazeSolver {
+    BreadthFirst,
+    AStar,
+    WallFollower,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Tile {
+    Wall,
+    Open,
+    /// being worked on by the generator
+    Carving,
+    /// looked at by the solver
+    Explored,
+    /// on the solution
+    Path,
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    Generating,
+    Solving,
+    /// showing the solution before starting again, with the seconds left
+    Finished(f32),
+}
+
+/// Generates a maze one step at a time, then solves it one step at a time.
+///
+/// The maze is drawn on the pixel grid with cells on odd coordinates and the
+/// walls between them on even coordinates, so cells are at `(2cx + 1, 2cy + 1)`.
+pub struct Maze<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    tiles: Grid<Tile, W, H>,
+    /// the direction each pixel was reached from, used to trace paths back
+    came_from: Grid<u8, W, H>,
+    /// the distance from the entrance for A*
+    cost: Grid<u16, W, H>,
+    /// the stack, frontier or open set for whichever algorithm is running
+    queue: Queue<(u8, u8), QUEUE_SIZE>,
+    rng: Rng,
+    phase: Phase,
+    generator: MazeGenerator,
+    solver: MazeSolver,
+    /// move on to the next generator and solver after each maze
+    cycle: bool,
+    steps_per_second: f32,
+    /// time that hasn't been used up by steps yet, in seconds
+    time_banked: f32,
+    /// the position, direction and start of the current walk for the wall
+    /// follower and Wilson's algorithm
+    walker: (i32, i32),
+    walker_direction: u8,
+    walk_start: (i32, i32),
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Maze<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    const CELLS_W: i32 = (W as i32 - 1) / 2;
+    const CELLS_H: i32 = (H as i32 - 1) / 2;
+    const ENTRANCE: (i32, i32) = (0, 1);
+    const EXIT: (i32, i32) = (2 * Self::CELLS_W, 2 * Self::CELLS_H - 1);
+    /// How long the solution stays on screen, in seconds
+    const SHOW_SOLUTION: f32 = 3.0;
+
+    pub fn new(generator: MazeGenerator, solver: MazeSolver, rng: Rng) -> Self {
+        let mut this = Maze {
+            tiles: Grid::new(Tile::Wall),
+            came_from: Grid::new(0),
+            cost: Grid::new(0),
+            queue: Queue::new(),
+            rng,
+            phase: Phase::Generating,
+            generator,
+            solver,
+            cycle: true,
+            steps_per_second: 120.0,
+            time_banked: 0.0,
+            walker: (0, 0),
+            walker_direction: 0,
+            walk_start: (0, 0),
+        };
+        this.start_generating();
+        this
+    }
+
+    fn random_cell(&mut self) -> (i32, i32) {
+        let rn = self.rng.next_u32();
+        let cx = (rn & 0xffff) as i32 % Self::CELLS_W;
+        let cy = (rn >> 16) as i32 % Self::CELLS_H;
+        (2 * cx + 1, 2 * cy + 1)
+    }
+
+    fn is_cell(x: i32, y: i32) -> bool {
+        x > 0 && y > 0 && x < 2 * Self::CELLS_W && y < 2 * Self::CELLS_H && x % 2 == 1 && y % 2 == 1
+    }
+
+    fn tile(&self, x: i32, y: i32) -> Tile {
+        self.tiles.get(x, y).copied().unwrap_or(Tile::Wall)
+    }
+
+    /// The cells two pixels away in each direction, with the direction index
+    fn neighbour_cells(x: i32, y: i32) -> impl Iterator<Item = (u8, i32, i32)> {
+        DIRECTIONS
+            .into_iter()
+            .enumerate()
+            .map(move |(d, (dx, dy))| (d as u8, x + 2 * dx, y + 2 * dy))
+            .filter(|(_, x, y)| Self::is_cell(*x, *y))
+    }
+
+    /// Pick a random neighbouring cell whose tile is `tile`
+    fn random_neighbour(&mut self, x: i32, y: i32, tile: Tile) -> Option<(i32, i32)> {
+        let mut options = [(0, 0); 4];
+        let mut n = 0;
+        for (_, nx, ny) in Self::neighbour_cells(x, y) {
+            if self.tile(nx, ny) == tile {
+                options[n] = (nx, ny);
+                n += 1;
+            }
+        }
+        if n == 0 {
+            None
+        } else {
+            Some(options[(self.rng.next_u32() % n as u32) as usize])
+        }
+    }
+
+    /// Knock down the wall between two neighbouring cells
+    fn carve(&mut self, (ax, ay): (i32, i32), (bx, by): (i32, i32), tile: Tile) {
+        self.tiles.set((ax + bx) / 2, (ay + by) / 2, tile);
+        self.tiles.set(bx, by, tile);
+    }
+
+    fn push(&mut self, (x, y): (i32, i32)) {
+        self.queue.push((x as u8, y as u8));
+    }
+
+    fn start_generating(&mut self) {
+        self.tiles.buffer_mut().fill(Tile::Wall);
+        self.queue.clear();
+        self.phase = Phase::Generating;
+        let start = self.random_cell();
+        match self.generator {
+            MazeGenerator::RecursiveBacktracker => {
+                self.tiles.set(start.0, start.1, Tile::Carving);
+                self.push(start);
+            }
+            MazeGenerator::Prims => {
+                self.tiles.set(start.0, start.1, Tile::Open);
+                for (_, nx, ny) in Self::neighbour_cells(start.0, start.1) {
+                    self.tiles.set(nx, ny, Tile::Carving);
+                    self.push((nx, ny));
+                }
+            }
+            MazeGenerator::Wilsons => {
+                self.tiles.set(start.0, start.1, Tile::Open);
+                self.start_walk();
+            }
+        }
+    }
+
+    /// Start a new random walk for Wilson's algorithm from a cell that's not in the maze.
+    /// Returns false if every cell is already in the maze.
+    fn start_walk(&mut self) -> bool {
+        // try a few random cells first, then fall back to the first one that's free
+        let mut start = None;
+        for _ in 0..8 {
+            let cell = self.random_cell();
+            if self.tile(cell.0, cell.1) == Tile::Wall {
+                start = Some(cell);
+                break;
+            }
+        }
+        if start.is_none() {
+            start = (0..Self::CELLS_H)
+                .flat_map(|cy| (0..Self::CELLS_W).map(move |cx| (2 * cx + 1, 2 * cy + 1)))
+                .find(|&(x, y)| self.tile(x, y) == Tile::Wall);
+        }
+        match start {
+            Some(cell) => {
+                self.walk_start = cell;
+                self.walker = cell;
+                self.tiles.set(cell.0, cell.1, Tile::Carving);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Do one step of generating the maze, returns true when the maze is complete
+    fn generate_step(&mut self) -> bool {
+        match self.generator {
+            MazeGenerator::RecursiveBacktracker => {
+                let Some((x, y)) = self.queue.last() else {
+                    return true;
+                };
+                let (x, y) = (x as i32, y as i32);
+                match self.random_neighbour(x, y, Tile::Wall) {
+                    Some(next) => {
+                        self.carve((x, y), next, Tile::Carving);
+                        self.push(next);
+                    }
+                    None => {
+                        // backtrack, leaving open corridor behind
+                        self.queue.pull();
+                        self.tiles.set(x, y, Tile::Open);
+                        if let Some((px, py)) = self.queue.last() {
+                            self.tiles
+                                .set((x + px as i32) / 2, (y + py as i32) / 2, Tile::Open);
+                        }
+                    }
+                }
+                self.queue.is_empty()
+            }
+            MazeGenerator::Prims => {
+                if self.queue.is_empty() {
+                    return true;
+                }
+                let i = self.rng.next_u32() as usize % self.queue.len();
+                let Some((x, y)) = self.queue.swap_remove(i) else {
+                    return true;
+                };
+                let (x, y) = (x as i32, y as i32);
+                if let Some(from) = self.random_neighbour(x, y, Tile::Open) {
+                    self.carve(from, (x, y), Tile::Open);
+                }
+                for (_, nx, ny) in Self::neighbour_cells(x, y) {
+                    if self.tile(nx, ny) == Tile::Wall {
+                        self.tiles.set(nx, ny, Tile::Carving);
+                        self.push((nx, ny));
+                    }
+                }
+                self.queue.is_empty()
+            }
+            MazeGenerator::Wilsons => self.wilsons_step(),
+        }
+    }
+
+    /// One step of a loop-erased random walk. The direction taken out of each cell
+    /// is remembered, so when the walk hits the maze, following the directions from
+    /// the start gives the walk with its loops erased.
+    fn wilsons_step(&mut self) -> bool {
+        let (x, y) = self.walker;
+        let options: [(u8, i32, i32); 4] = {
+            let mut options = [(0, 0, 0); 4];
+            for (i, n) in Self::neighbour_cells(x, y).enumerate() {
+                options[i] = n;
+            }
+            options
+        };
+        let n = Self::neighbour_cells(x, y).count();
+        let (d, nx, ny) = options[(self.rng.next_u32() % n as u32) as usize];
+        self.came_from.set(x, y, d);
+        self.walker = (nx, ny);
+
+        if self.tile(nx, ny) != Tile::Open {
+            self.tiles.set(nx, ny, Tile::Carving);
+            return false;
+        }
+
+        // the walk reached the maze, clear the walk and carve its loop-erased version
+        self.tiles.buffer_mut().iter_mut().for_each(|t| {
+            if *t == Tile::Carving {
+                *t = Tile::Wall
+            }
+        });
+        let mut cell = self.walk_start;
+        while self.tile(cell.0, cell.1) != Tile::Open {
+            let d = self.came_from.get(cell.0, cell.1).copied().unwrap_or(0);
+            let (dx, dy) = DIRECTIONS[d as usize];
+            let next = (cell.0 + 2 * dx, cell.1 + 2 * dy);
+            self.tiles.set(cell.0, cell.1, Tile::Open);
+            self.tiles.set(cell.0 + dx, cell.1 + dy, Tile::Open);
+            cell = next;
+        }
+        !self.start_walk()
+    }
+
+    fn start_solving(&mut self) {
+        self.phase = Phase::Solving;
+        // open up the entrance and exit
+        self.tiles
+            .set(Self::ENTRANCE.0, Self::ENTRANCE.1, Tile::Open);
+        self.tiles.set(Self::EXIT.0, Self::EXIT.1, Tile::Open);
+        self.queue.clear();
+        self.tiles
+            .set(Self::ENTRANCE.0, Self::ENTRANCE.1, Tile::Explored);
+        self.cost.set(Self::ENTRANCE.0, Self::ENTRANCE.1, 0);
+        self.push(Self::ENTRANCE);
+        self.walker = Self::ENTRANCE;
+        // start off facing right, into the maze
+        self.walker_direction = 0;
+    }
+
+    /// Wipe out what the solver has done so far and start again from the entrance
+    fn restart_solving(&mut self) {
+        self.tiles.buffer_mut().iter_mut().for_each(|t| {
+            if *t == Tile::Explored || *t == Tile::Path {
+                *t = Tile::Open
+            }
+        });
+        self.start_solving();
+    }
+
+    fn heuristic((x, y): (i32, i32)) -> u16 {
+        ((Self::EXIT.0 - x).abs() + (Self::EXIT.1 - y).abs()) as u16
+    }
+
+    /// Do one step of solving the maze, returns true when the exit has been found
+    fn solve_step(&mut self) -> bool {
+        let current = match self.solver {
+            MazeSolver::BreadthFirst => match self.queue.pull_front() {
+                Some((x, y)) => (x as i32, y as i32),
+                None => return true,
+            },
+            MazeSolver::AStar => {
+                // the open set is small in a maze, so a linear search for the best is fine
+                let best = (0..self.queue.len())
+                    .filter_map(|i| self.queue.get(i).map(|p| (i, p)))
+                    .min_by_key(|(_, (x, y))| {
+                        let (x, y) = (*x as i32, *y as i32);
+                        self.cost.get(x, y).copied().unwrap_or(0) + Self::heuristic((x, y))
+                    });
+                match best.and_then(|(i, _)| self.queue.swap_remove(i)) {
+                    Some((x, y)) => (x as i32, y as i32),
+                    None => return true,
+                }
+            }
+            MazeSolver::WallFollower => return self.wall_follower_step(),
+        };
+
+        if current == Self::EXIT {
+            self.trace_path();
+            return true;
+        }
+        let cost = self.cost.get(current.0, current.1).copied().unwrap_or(0);
+        for (d, (dx, dy)) in DIRECTIONS.into_iter().enumerate() {
+            let (nx, ny) = (current.0 + dx, current.1 + dy);
+            if self.tile(nx, ny) == Tile::Open {
+                self.tiles.set(nx, ny, Tile::Explored);
+                self.came_from.set(nx, ny, d as u8);
+                self.cost.set(nx, ny, cost + 1);
+                self.push((nx, ny));
+            }
+        }
+        false
+    }
+
+    /// Keep a hand on the left wall: turn left if possible, otherwise go straight,
+    /// otherwise turn right, otherwise turn around
+    fn wall_follower_step(&mut self) -> bool {
+        let (x, y) = self.walker;
+        for turn in [3, 0, 1, 2] {
+            let d = (self.walker_direction + turn) % 4;
+            let (dx, dy) = DIRECTIONS[d as usize];
+            let (nx, ny) = (x + dx, y + dy);
+            if self.tile(nx, ny) != Tile::Wall {
+                self.walker = (nx, ny);
+                self.walker_direction = d;
+                // going back over the trail means it was a dead end
+                let tile = if self.tile(nx, ny) == Tile::Path {
+                    Tile::Explored
+                } else {
+                    Tile::Path
+                };
+                self.tiles.set(x, y, tile);
+                self.tiles.set(nx, ny, Tile::Path);
+                return (nx, ny) == Self::EXIT;
+            }
+        }
+        true
+    }
+
+    fn trace_path(&mut self) {
+        let mut cell = Self::EXIT;
+        self.tiles.set(cell.0, cell.1, Tile::Path);
+        while cell != Self::ENTRANCE {
+            let d = self.came_from.get(cell.0, cell.1).copied().unwrap_or(0);
+            let (dx, dy) = DIRECTIONS[d as usize];
+            cell = (cell.0 - dx, cell.1 - dy);
+            self.tiles.set(cell.0, cell.1, Tile::Path);
+        }
+    }
+
+    fn next_algorithms(&mut self) {
+        self.generator = match self.generator {
+            MazeGenerator::RecursiveBacktracker => MazeGenerator::Prims,
+            MazeGenerator::Prims => MazeGenerator::Wilsons,
+            MazeGenerator::Wilsons => MazeGenerator::RecursiveBacktracker,
+        };
+        self.solver = match self.solver {
+            MazeSolver::BreadthFirst => MazeSolver::AStar,
+            MazeSolver::AStar => MazeSolver::WallFollower,
+            MazeSolver::WallFollower => MazeSolver::BreadthFirst,
+        };
+    }
+
+    fn step(&mut self) {
+        match self.phase {
+            Phase::Generating => {
+                if self.generate_step() {
+                    self.start_solving();
+                }
+            }
+            Phase::Solving => {
+                if self.solve_step() {
+                    self.phase = Phase::Finished(Self::SHOW_SOLUTION);
+                }
+            }
+            Phase::Finished(_) => {}
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum MazeUpdate {
+    Reset,
+    SetGenerator(MazeGenerator),
+    SetSolver(MazeSolver),
+    /// Whether to move on to a different generator and solver after each maze
+    SetCycle(bool),
+    SetStepsPerSecond(f32),
+}
+
+impl StateUpdate for MazeUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Maze<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = MazeUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = delta_time_us as f32 / 1_000_000.0;
+        if let Phase::Finished(remaining) = self.phase {
+            if remaining > dt {
+                self.phase = Phase::Finished(remaining - dt);
+            } else {
+                if self.cycle {
+                    self.next_algorithms();
+                }
+                self.start_generating();
+            }
+            return true;
+        }
+
+        // don't try to catch up on more than a fraction of a second
+        self.time_banked = (self.time_banked + dt).min(0.25);
+        let step_time = 1.0 / self.steps_per_second;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step();
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let _ = target.draw_iter(self.tiles.iter_with_index().map(|((x, y), tile)| {
+            let colour = match tile {
+                Tile::Wall => Rgb888::new(0, 0, 0),
+                Tile::Open => Rgb888::new(40, 40, 70),
+                Tile::Carving => Rgb888::new(40, 200, 80),
+                Tile::Explored => Rgb888::new(110, 40, 120),
+                Tile::Path => Rgb888::new(255, 220, 40),
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            MazeUpdate::Reset => self.reset(),
+            MazeUpdate::SetGenerator(generator) => {
+                self.generator = generator;
+                self.start_generating();
+            }
+            MazeUpdate::SetSolver(solver) => {
+                self.solver = solver;
+                // the new solver can't carry on from the old one's marks
+                if !matches!(self.phase, Phase::Generating) {
+                    self.restart_solving();
+                }
+            }
+            MazeUpdate::SetCycle(cycle) => self.cycle = cycle,
+            MazeUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 10_000.0)
+            }
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Maze::new(
+            MazeGenerator::RecursiveBacktracker,
+            MazeSolver::BreadthFirst,
+            rng,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.time_banked = 0.0;
+        self.start_generating();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    type TestMaze = Maze<XorShift, 64, 32>;
+
+    fn generated(solver: MazeSolver) -> TestMaze {
+        let mut maze = TestMaze::new(MazeGenerator::RecursiveBacktracker, solver, XorShift(3));
+        while matches!(maze.phase, Phase::Generating) {
+            maze.step();
+        }
+        maze
+    }
+
+    fn solve(maze: &mut TestMaze) {
+        for _ in 0..10_000 {
+            if matches!(maze.phase, Phase::Finished(_)) {
+                return;
+            }
+            maze.step();
+        }
+        panic!("the maze wasn't solved");
+    }
+
+    fn path_length(maze: &TestMaze) -> usize {
+        maze.tiles
+            .buffer()
+            .iter()
+            .filter(|t| **t == Tile::Path)
+            .count()
+    }
+
+    #[test]
+    fn huge_step_rates_are_capped() {
+        for steps in [1e6, 1e9, f32::MAX, f32::INFINITY] {
+            let mut maze = generated(MazeSolver::BreadthFirst);
+            maze.run_state_update(MazeUpdate::SetStepsPerSecond(steps));
+            assert_eq!(maze.steps_per_second, 10_000.0);
+            maze.update(16_000);
+            maze.update(1_000_000);
+        }
+    }
+
+    #[test]
+    fn every_solver_finds_the_exit() {
+        for solver in [
+            MazeSolver::BreadthFirst,
+            MazeSolver::AStar,
+            MazeSolver::WallFollower,
+        ] {
+            let mut maze = generated(solver);
+            solve(&mut maze);
+            assert!(maze.tile(TestMaze::EXIT.0, TestMaze::EXIT.1) == Tile::Path);
+            assert!(maze.tile(TestMaze::ENTRANCE.0, TestMaze::ENTRANCE.1) == Tile::Path);
+        }
+    }
+
+    #[test]
+    fn changing_solver_mid_solve_starts_again() {
+        let mut shortest = generated(MazeSolver::BreadthFirst);
+        solve(&mut shortest);
+
+        let mut maze = generated(MazeSolver::WallFollower);
+        for _ in 0..50 {
+            maze.step();
+        }
+        maze.run_state_update(MazeUpdate::SetSolver(MazeSolver::BreadthFirst));
+        solve(&mut maze);
+        assert!(maze.tile(TestMaze::EXIT.0, TestMaze::EXIT.1) == Tile::Path);
+        assert_eq!(path_length(&maze), path_length(&shortest));
+    }
+
+    #[test]
+    fn changing_solver_when_finished_shows_the_new_solution() {
+        let mut shortest = generated(MazeSolver::AStar);
+        solve(&mut shortest);
+
+        let mut maze = generated(MazeSolver::WallFollower);
+        solve(&mut maze);
+        maze.run_state_update(MazeUpdate::SetSolver(MazeSolver::AStar));
+        assert!(matches!(maze.phase, Phase::Solving));
+        solve(&mut maze);
+        assert_eq!(path_length(&maze), path_length(&shortest));
+    }
+}
@@ -0,0 +1,175 @@
+use embedded_graphics::{Pixel, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, palette::Palette};
+
+const MAX_BALLS: usize = 12;
+/// The speed range of the balls at a speed setting of 1, in pixels per second
+const MIN_BALL_SPEED: f32 = 6.0;
+const MAX_BALL_SPEED: f32 = 14.0;
+/// Added to the squared distance so the field stays finite at a ball's centre
+const SOFTENING: f32 = 0.5;
+
+/// An approximate 1/x for positive x, within 0.3%. A first guess is made by flipping
+/// the bits of the float, then refined with one Newton-Raphson step, which is quicker
+/// than a divide when it's done for every ball at every pixel.
+fn approx_recip(x: f32) -> f32 {
+    let guess = f32::from_bits(0x7EF3_11C7 - x.to_bits());
+    guess * (2.0 - x * guess)
+}
+
+#[derive(Copy, Clone)]
+struct Ball {
+    position: (f32, f32),
+    /// pixels per second, at a speed setting of 1
+    velocity: (f32, f32),
+    /// the squared radius, which is the strength of its field
+    radius2: f32,
+}
+
+/// Metaballs: each ball gives off a field that falls off with the square of the
+/// distance, and the fields are added up at every pixel. Where the total passes the
+/// threshold is inside the blob, so balls merge smoothly as they get close, and the
+/// field below the threshold is drawn as a soft glow around them.
+pub struct Metaballs<Rng, const W: usize, const H: usize> {
+    balls: [Ball; MAX_BALLS],
+    ball_count: usize,
+    speed: f32,
+    threshold: f32,
+    palette: Palette,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Metaballs<Rng, W, H> {
+    pub fn new(ball_count: usize, rng: Rng) -> Self {
+        let mut this = Metaballs {
+            balls: [Ball {
+                position: (0.0, 0.0),
+                velocity: (0.0, 0.0),
+                radius2: 0.0,
+            }; MAX_BALLS],
+            ball_count: ball_count.clamp(1, MAX_BALLS),
+            speed: 1.0,
+            threshold: 1.0,
+            palette: Palette::Electric,
+            rng,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn random_ball(&mut self) -> Ball {
+        let angle = self.rng.unit_f32() * core::f32::consts::TAU;
+        let speed = MIN_BALL_SPEED + self.rng.unit_f32() * (MAX_BALL_SPEED - MIN_BALL_SPEED);
+        let radius = 2.5 + self.rng.unit_f32() * 2.5;
+        Ball {
+            position: (
+                self.rng.unit_f32() * W as f32,
+                self.rng.unit_f32() * H as f32,
+            ),
+            velocity: (speed * libm::cosf(angle), speed * libm::sinf(angle)),
+            radius2: radius * radius,
+        }
+    }
+
+    /// The sum of the fields of all the balls at a position
+    fn field(&self, x: f32, y: f32) -> f32 {
+        self.balls[..self.ball_count]
+            .iter()
+            .map(|ball| {
+                let (dx, dy) = (x - ball.position.0, y - ball.position.1);
+                ball.radius2 * approx_recip(dx * dx + dy * dy + SOFTENING)
+            })
+            .sum()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum MetaballsUpdate {
+    Reset,
+    SetBalls(u8),
+    /// A multiplier on how fast the balls move
+    SetSpeed(f32),
+    /// The field strength at the edge of a blob. Higher values make smaller blobs that
+    /// need to get closer before they merge.
+    SetThreshold(f32),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for MetaballsUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Metaballs<Rng, W, H> {
+    type StateUpdate = MetaballsUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1) * self.speed;
+        let (w, h) = (W as f32, H as f32);
+        for ball in self.balls[..self.ball_count].iter_mut() {
+            let (x, y) = &mut ball.position;
+            let (vx, vy) = &mut ball.velocity;
+            *x += *vx * dt;
+            *y += *vy * dt;
+            // bounce off the edges
+            if (*x < 0.0 && *vx < 0.0) || (*x > w && *vx > 0.0) {
+                *vx = -*vx;
+            }
+            if (*y < 0.0 && *vy < 0.0) || (*y > h && *vy > 0.0) {
+                *vy = -*vy;
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let inverse_threshold = 1.0 / self.threshold;
+        let palette = self.palette;
+        let coords = (0..H as i32).flat_map(|y| (0..W as i32).map(move |x| (x, y)));
+        let _ = target.draw_iter(coords.map(|(x, y)| {
+            // sample at the centre of the pixel
+            let v = self.field(x as f32 + 0.5, y as f32 + 0.5) * inverse_threshold;
+            let t = if v >= 1.0 {
+                // the top of the gradient for the blobs, brightest at the edge
+                0.95 - 0.25 * (v - 1.0).min(1.0)
+            } else {
+                // a glow that fades off quickly away from the edge
+                0.6 * v * v * v
+            };
+            Pixel(Point::new(x, y), palette.sample(t))
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            MetaballsUpdate::Reset => self.reset(),
+            MetaballsUpdate::SetBalls(count) => {
+                let count = (count as usize).clamp(1, MAX_BALLS);
+                for i in self.ball_count..count {
+                    self.balls[i] = self.random_ball();
+                }
+                self.ball_count = count;
+            }
+            MetaballsUpdate::SetSpeed(speed) => self.speed = speed.clamp(0.0, 10.0),
+            MetaballsUpdate::SetThreshold(threshold) => {
+                self.threshold = threshold.clamp(0.05, 20.0)
+            }
+            MetaballsUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Metaballs::new(6, rng)
+    }
+
+    fn reset(&mut self) {
+        for i in 0..self.ball_count {
+            self.balls[i] = self.random_ball();
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Smooth procedural noise for textures and motion: value, Perlin and simplex noise
+//! in 2d and 3d, and fractal sums of several octaves of them.
+//!
+//! All of the noise is roughly between -1 and 1, and has features about one unit
+//! apart. Scale the coordinates to change the size of the features, and use the
+//! third dimension as time to animate a 2d texture.
+
+use crate::RngU32;
+
+/// Skews 2d space onto the simplex grid, (sqrt(3) - 1) / 2
+const SKEW_2D: f32 = 0.366_025_4;
+/// Unskews the simplex grid back, (3 - sqrt(3)) / 6
+const UNSKEW_2D: f32 = 0.211_324_87;
+const SKEW_3D: f32 = 1.0 / 3.0;
+const UNSKEW_3D: f32 = 1.0 / 6.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum NoiseKind {
+    /// Random values at grid points, smoothly blended. Cheap, but blocky.
+    Value,
+    /// Random gradients at grid points, which hides the grid better
+    Perlin,
+    /// Gradients on a grid of triangles, or tetrahedra in 3d. The least directional,
+    /// and the cheapest in 3d.
+    Simplex,
+}
+
+/// A noise generator. Generators with the same seed give the same noise.
+#[derive(Copy, Clone)]
+pub struct Noise {
+    seed: u32,
+}
+
+/// Smooths out the blend between grid points, 6t^5 - 15t^4 + 10t^3
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// The dot product of a position with one of 8 gradients picked by the hash
+fn gradient_2d(hash: u32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// The dot product of a position with one of the 12 gradients towards the edges of
+/// a cube, picked by the hash. 16 cases with 4 repeated, as in Perlin's improved noise.
+fn gradient_3d(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..4 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Noise {
+    pub const fn new(seed: u32) -> Self {
+        Noise { seed }
+    }
+
+    pub fn from_rng<Rng: RngU32>(rng: &mut Rng) -> Self {
+        Noise::new(rng.next_u32())
+    }
+
+    /// A well mixed hash of a grid point
+    fn hash(&self, x: i32, y: i32, z: i32) -> u32 {
+        let mut h = self.seed
+            ^ (x as u32).wrapping_mul(0x8DA6_B343)
+            ^ (y as u32).wrapping_mul(0xD816_3841)
+            ^ (z as u32).wrapping_mul(0xCB1A_B31F);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2C1B_3C6D);
+        h ^= h >> 12;
+        h = h.wrapping_mul(0x297A_2D39);
+        h ^ (h >> 15)
+    }
+
+    /// A random value between -1 and 1 for a grid point
+    fn lattice_value(&self, x: i32, y: i32, z: i32) -> f32 {
+        (self.hash(x, y, z) >> 8) as f32 * (2.0 / (1 << 24) as f32) - 1.0
+    }
+
+    pub fn value_2d(&self, x: f32, y: f32) -> f32 {
+        let (fx, fy) = (libm::floorf(x), libm::floorf(y));
+        let (ix, iy) = (fx as i32, fy as i32);
+        let (u, v) = (fade(x - fx), fade(y - fy));
+        let corner = |dx, dy| self.lattice_value(ix + dx, iy + dy, 0);
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        )
+    }
+
+    pub fn value_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (fx, fy, fz) = (libm::floorf(x), libm::floorf(y), libm::floorf(z));
+        let (ix, iy, iz) = (fx as i32, fy as i32, fz as i32);
+        let (u, v, w) = (fade(x - fx), fade(y - fy), fade(z - fz));
+        let corner = |dx, dy, dz| self.lattice_value(ix + dx, iy + dy, iz + dz);
+        let face = |dz| {
+            lerp(
+                lerp(corner(0, 0, dz), corner(1, 0, dz), u),
+                lerp(corner(0, 1, dz), corner(1, 1, dz), u),
+                v,
+            )
+        };
+        lerp(face(0), face(1), w)
+    }
+
+    pub fn perlin_2d(&self, x: f32, y: f32) -> f32 {
+        let (fx, fy) = (libm::floorf(x), libm::floorf(y));
+        let (ix, iy) = (fx as i32, fy as i32);
+        let (x, y) = (x - fx, y - fy);
+        let (u, v) = (fade(x), fade(y));
+        let corner = |dx: i32, dy: i32| {
+            gradient_2d(self.hash(ix + dx, iy + dy, 0), x - dx as f32, y - dy as f32)
+        };
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        )
+    }
+
+    pub fn perlin_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (fx, fy, fz) = (libm::floorf(x), libm::floorf(y), libm::floorf(z));
+        let (ix, iy, iz) = (fx as i32, fy as i32, fz as i32);
+        let (x, y, z) = (x - fx, y - fy, z - fz);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            gradient_3d(
+                self.hash(ix + dx, iy + dy, iz + dz),
+                x - dx as f32,
+                y - dy as f32,
+                z - dz as f32,
+            )
+        };
+        let face = |dz| {
+            lerp(
+                lerp(corner(0, 0, dz), corner(1, 0, dz), u),
+                lerp(corner(0, 1, dz), corner(1, 1, dz), u),
+                v,
+            )
+        };
+        lerp(face(0), face(1), w)
+    }
+
+    pub fn simplex_2d(&self, x: f32, y: f32) -> f32 {
+        // find the triangle the point is in on the skewed grid
+        let s = (x + y) * SKEW_2D;
+        let (i, j) = (libm::floorf(x + s), libm::floorf(y + s));
+        let t = (i + j) * UNSKEW_2D;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let (i, j) = (i as i32, j as i32);
+
+        let corner = |di: i32, dj: i32, x: f32, y: f32| {
+            let t = 0.5 - x * x - y * y;
+            if t <= 0.0 {
+                return 0.0;
+            }
+            let t2 = t * t;
+            t2 * t2 * gradient_2d(self.hash(i + di, j + dj, 0), x, y)
+        };
+        let sum = corner(0, 0, x0, y0)
+            + corner(
+                i1,
+                j1,
+                x0 - i1 as f32 + UNSKEW_2D,
+                y0 - j1 as f32 + UNSKEW_2D,
+            )
+            + corner(1, 1, x0 - 1.0 + 2.0 * UNSKEW_2D, y0 - 1.0 + 2.0 * UNSKEW_2D);
+        70.0 * sum
+    }
+
+    pub fn simplex_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        // find the tetrahedron the point is in on the skewed grid
+        let s = (x + y + z) * SKEW_3D;
+        let (i, j, k) = (
+            libm::floorf(x + s),
+            libm::floorf(y + s),
+            libm::floorf(z + s),
+        );
+        let t = (i + j + k) * UNSKEW_3D;
+        let (x0, y0, z0) = (x - (i - t), y - (j - t), z - (k - t));
+        // the second and third corners step along the axes in order of distance
+        let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+        let (i, j, k) = (i as i32, j as i32, k as i32);
+
+        let corner = |(di, dj, dk): (i32, i32, i32), offset: f32| {
+            let (x, y, z) = (
+                x0 - di as f32 + offset,
+                y0 - dj as f32 + offset,
+                z0 - dk as f32 + offset,
+            );
+            let t = 0.6 - x * x - y * y - z * z;
+            if t <= 0.0 {
+                return 0.0;
+            }
+            let t2 = t * t;
+            t2 * t2 * gradient_3d(self.hash(i + di, j + dj, k + dk), x, y, z)
+        };
+        let sum = corner((0, 0, 0), 0.0)
+            + corner((i1, j1, k1), UNSKEW_3D)
+            + corner((i2, j2, k2), 2.0 * UNSKEW_3D)
+            + corner((1, 1, 1), 3.0 * UNSKEW_3D);
+        32.0 * sum
+    }
+
+    pub fn sample_2d(&self, kind: NoiseKind, x: f32, y: f32) -> f32 {
+        match kind {
+            NoiseKind::Value => self.value_2d(x, y),
+            NoiseKind::Perlin => self.perlin_2d(x, y),
+            NoiseKind::Simplex => self.simplex_2d(x, y),
+        }
+    }
+
+    pub fn sample_3d(&self, kind: NoiseKind, x: f32, y: f32, z: f32) -> f32 {
+        match kind {
+            NoiseKind::Value => self.value_3d(x, y, z),
+            NoiseKind::Perlin => self.perlin_3d(x, y, z),
+            NoiseKind::Simplex => self.simplex_3d(x, y, z),
+        }
+    }
+
+    /// The same noise with a different seed, so octaves don't line up with each other
+    fn octave(&self, octave: u32) -> Noise {
+        Noise::new(self.seed.wrapping_add(octave.wrapping_mul(0x9E37_79B9)))
+    }
+
+    /// Octaves of noise added together, each with double the detail and half the
+    /// strength of the last, and scaled back to between -1 and 1
+    pub fn fractal_2d(&self, kind: NoiseKind, x: f32, y: f32, octaves: u8) -> f32 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+        for octave in 0..octaves.max(1) as u32 {
+            sum += amplitude
+                * self
+                    .octave(octave)
+                    .sample_2d(kind, x * frequency, y * frequency);
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        sum / total
+    }
+
+    /// Octaves of noise added together, each with double the detail and half the
+    /// strength of the last, and scaled back to between -1 and 1
+    pub fn fractal_3d(&self, kind: NoiseKind, x: f32, y: f32, z: f32, octaves: u8) -> f32 {
+        let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+        for octave in 0..octaves.max(1) as u32 {
+            sum += amplitude
+                * self
+                    .octave(octave)
+                    .sample_3d(kind, x * frequency, y * frequency, z * frequency);
+            total += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        sum / total
+    }
+}
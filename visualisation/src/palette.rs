@@ -0,0 +1,83 @@
+use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor};
+
+const FIRE: [Rgb888; 5] = [
+    Rgb888::new(0, 0, 0),
+    Rgb888::new(120, 0, 0),
+    Rgb888::new(230, 60, 0),
+    Rgb888::new(255, 180, 20),
+    Rgb888::new(255, 255, 200),
+];
+
+const OCEAN: [Rgb888; 5] = [
+    Rgb888::new(0, 0, 20),
+    Rgb888::new(0, 30, 110),
+    Rgb888::new(0, 120, 180),
+    Rgb888::new(80, 220, 230),
+    Rgb888::new(230, 255, 255),
+];
+
+const RAINBOW: [Rgb888; 8] = [
+    Rgb888::new(255, 0, 0),
+    Rgb888::new(255, 160, 0),
+    Rgb888::new(220, 255, 0),
+    Rgb888::new(0, 255, 60),
+    Rgb888::new(0, 160, 255),
+    Rgb888::new(120, 0, 255),
+    Rgb888::new(255, 0, 160),
+    Rgb888::new(255, 0, 0),
+];
+
+const ELECTRIC: [Rgb888; 5] = [
+    Rgb888::new(0, 0, 0),
+    Rgb888::new(60, 0, 140),
+    Rgb888::new(220, 0, 200),
+    Rgb888::new(255, 140, 220),
+    Rgb888::new(255, 255, 255),
+];
+
+const GREYSCALE: [Rgb888; 2] = [Rgb888::new(0, 0, 0), Rgb888::new(255, 255, 255)];
+
+/// A set of colour gradients that visualisations can map values through
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Palette {
+    Fire,
+    Ocean,
+    Rainbow,
+    Electric,
+    Greyscale,
+}
+
+impl Palette {
+    /// The colours of the gradient, evenly spaced from 0 to 1
+    const fn stops(self) -> &'static [Rgb888] {
+        match self {
+            Palette::Fire => &FIRE,
+            Palette::Ocean => &OCEAN,
+            Palette::Rainbow => &RAINBOW,
+            Palette::Electric => &ELECTRIC,
+            Palette::Greyscale => &GREYSCALE,
+        }
+    }
+
+    /// Sample the gradient at `t`, which is clamped to [0, 1]
+    pub fn sample(self, t: f32) -> Rgb888 {
+        let stops = self.stops();
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (position as usize).min(stops.len() - 2);
+        let frac = position - i as f32;
+        lerp(stops[i], stops[i + 1], frac)
+    }
+
+    /// Sample the gradient at `t`, going back and forth along it so that it can be
+    /// used for values that grow without bound
+    pub fn sample_cyclic(self, t: f32) -> Rgb888 {
+        let t = t - libm::floorf(t / 2.0) * 2.0;
+        self.sample(if t > 1.0 { 2.0 - t } else { t })
+    }
+}
+
+/// Linearly interpolate between two colours
+pub fn lerp(a: Rgb888, b: Rgb888, t: f32) -> Rgb888 {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Rgb888::new(mix(a.r(), b.r()), mix(a.g(), b.g()), mix(a.b(), b.b()))
+}
@@ -0,0 +1,148 @@
+//! A small particle system for effects like fireworks, sparks and smoke: a fixed pool
+//! of particles that fall under gravity, slow with drag and change colour as they age.
+//!
+//! Particles are drawn by adding their colours onto a buffer, so where they overlap
+//! they get brighter, like light does.
+
+use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor};
+use heapless::Vec;
+
+use crate::palette::lerp;
+
+/// A single particle. Positions are in pixels and velocities in pixels per second,
+/// with y down the panel.
+#[derive(Copy, Clone)]
+pub struct Particle {
+    pub x: f32,
+    pub y: f32,
+    pub vx: f32,
+    pub vy: f32,
+    /// Seconds since it was spawned
+    pub age: f32,
+    /// Seconds it lasts
+    pub life: f32,
+    /// The colour when it's spawned
+    pub start_colour: Rgb888,
+    /// The colour just before it dies. Make this black to fade out.
+    pub end_colour: Rgb888,
+    /// How much drag affects this particle, from 0 to not slow down at all, with 1
+    /// the system's drag
+    pub drag: f32,
+    /// Free for the effect to use, for example to mark particles that do something
+    /// when they die
+    pub tag: u8,
+}
+
+impl Particle {
+    /// A particle at a point, moving with a velocity, that lasts `life` seconds and
+    /// fades from `colour` to black
+    pub fn new(x: f32, y: f32, vx: f32, vy: f32, life: f32, colour: Rgb888) -> Self {
+        Particle {
+            x,
+            y,
+            vx,
+            vy,
+            age: 0.0,
+            life,
+            start_colour: colour,
+            end_colour: Rgb888::new(0, 0, 0),
+            drag: 1.0,
+            tag: 0,
+        }
+    }
+
+    /// The colour at its current age
+    pub fn colour(&self) -> Rgb888 {
+        lerp(
+            self.start_colour,
+            self.end_colour,
+            (self.age / self.life).clamp(0.0, 1.0),
+        )
+    }
+}
+
+/// A pool of up to `N` particles
+pub struct ParticleSystem<const N: usize> {
+    particles: Vec<Particle, N>,
+    /// pixels per second per second, down the panel
+    gravity: f32,
+    /// the fraction of their speed particles lose each second
+    drag: f32,
+}
+
+impl<const N: usize> ParticleSystem<N> {
+    pub fn new(gravity: f32, drag: f32) -> Self {
+        ParticleSystem {
+            particles: Vec::new(),
+            gravity,
+            drag: drag.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn set_gravity(&mut self, gravity: f32) {
+        self.gravity = gravity;
+    }
+
+    pub fn set_drag(&mut self, drag: f32) {
+        self.drag = drag.clamp(0.0, 1.0);
+    }
+
+    /// Add a particle, returning false if the pool is full
+    pub fn spawn(&mut self, particle: Particle) -> bool {
+        self.particles.push(particle).is_ok()
+    }
+
+    /// How many more particles there's room for
+    pub fn free(&self) -> usize {
+        N - self.particles.len()
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Move the particles on by `dt` seconds. Particles that reach the end of their
+    /// life are removed, and passed to `expired` first.
+    pub fn update(&mut self, dt: f32, mut expired: impl FnMut(&Particle)) {
+        let keep = libm::powf(1.0 - self.drag.min(0.999), dt);
+        let mut i = 0;
+        while i < self.particles.len() {
+            let p = &mut self.particles[i];
+            p.age += dt;
+            if p.age >= p.life {
+                expired(p);
+                self.particles.swap_remove(i);
+                continue;
+            }
+            // a particle drag of 0 keeps all of the speed, and 1 slows like the system
+            let slow = (1.0 - (1.0 - keep) * p.drag).max(0.0);
+            p.vx *= slow;
+            p.vy = p.vy * slow + self.gravity * dt;
+            p.x += p.vx * dt;
+            p.y += p.vy * dt;
+            i += 1;
+        }
+    }
+
+    /// Add the particles' colours onto a buffer `width` pixels wide, in rows
+    pub fn draw_additive(&self, buffer: &mut [Rgb888], width: usize) {
+        let height = buffer.len() / width;
+        for p in self.particles.iter() {
+            let (x, y) = (libm::floorf(p.x), libm::floorf(p.y));
+            if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+                continue;
+            }
+            let pixel = &mut buffer[y as usize * width + x as usize];
+            let colour = p.colour();
+            *pixel = Rgb888::new(
+                pixel.r().saturating_add(colour.r()),
+                pixel.g().saturating_add(colour.g()),
+                pixel.b().saturating_add(colour.b()),
+            );
+        }
+    }
+}
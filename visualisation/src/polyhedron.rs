@@ -0,0 +1,154 @@
+use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    render3d::{CUBE, ICOSAHEDRON, Mat4, Mesh, Renderer, Vec3},
+};
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum MeshKind {
+    Cube,
+    Icosahedron,
+}
+
+impl MeshKind {
+    fn mesh(self) -> &'static Mesh {
+        match self {
+            MeshKind::Cube => &CUBE,
+            MeshKind::Icosahedron => &ICOSAHEDRON,
+        }
+    }
+}
+
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum RenderMode {
+    Wireframe,
+    FlatShaded,
+}
+
+/// A spinning solid
+pub struct Polyhedron<const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    renderer: Renderer<W, H>,
+    projection: Mat4,
+    mesh: MeshKind,
+    mode: RenderMode,
+    /// the current rotation around each axis, in radians
+    angles: Vec3,
+    /// the rotation speed around each axis, in radians per second
+    speeds: Vec3,
+}
+
+impl<const W: usize, const H: usize> Polyhedron<W, H>
+where
+    [(); W * H]:,
+{
+    const DEFAULT_SPEEDS: Vec3 = Vec3::new(0.7, 1.1, 0.3);
+
+    pub fn new(mesh: MeshKind, mode: RenderMode) -> Self {
+        Polyhedron {
+            renderer: Renderer::new(),
+            projection: Mat4::perspective(1.0, Renderer::<W, H>::aspect(), 0.1, 20.0),
+            mesh,
+            mode,
+            angles: Vec3::new(0.0, 0.0, 0.0),
+            speeds: Self::DEFAULT_SPEEDS,
+        }
+    }
+
+    fn model_view(&self) -> Mat4 {
+        Mat4::translation(Vec3::new(0.0, 0.0, -3.0))
+            * Mat4::rotation_z(self.angles.z)
+            * Mat4::rotation_y(self.angles.y)
+            * Mat4::rotation_x(self.angles.x)
+    }
+
+    /// keep an angle in [0, 2pi) so that it doesn't lose precision over time
+    fn wrap_angle(angle: f32) -> f32 {
+        angle - libm::floorf(angle / core::f32::consts::TAU) * core::f32::consts::TAU
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum PolyhedronUpdate {
+    Reset,
+    SetMesh(MeshKind),
+    SetRenderMode(RenderMode),
+    /// The rotation speed around each axis, in radians per second
+    SetRotationSpeed {
+        x: f32,
+        y: f32,
+        z: f32,
+    },
+}
+
+impl StateUpdate for PolyhedronUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Polyhedron<W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = PolyhedronUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = delta_time_us as f32 / 1_000_000.0;
+        self.angles = Vec3::new(
+            Self::wrap_angle(self.angles.x + self.speeds.x * dt),
+            Self::wrap_angle(self.angles.y + self.speeds.y * dt),
+            Self::wrap_angle(self.angles.z + self.speeds.z * dt),
+        );
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let model_view = self.model_view();
+        let mesh = self.mesh.mesh();
+        match self.mode {
+            RenderMode::Wireframe => Renderer::<W, H>::draw_wireframe(
+                target,
+                mesh,
+                &(self.projection * model_view),
+                Rgb888::CYAN,
+            ),
+            RenderMode::FlatShaded => {
+                self.renderer.clear_depth();
+                self.renderer.draw_flat_shaded(
+                    target,
+                    mesh,
+                    &model_view,
+                    &self.projection,
+                    Vec3::new(-0.5, 0.8, 1.0),
+                    Rgb888::new(255, 120, 40),
+                );
+            }
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            PolyhedronUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            PolyhedronUpdate::SetMesh(mesh) => self.mesh = mesh,
+            PolyhedronUpdate::SetRenderMode(mode) => self.mode = mode,
+            PolyhedronUpdate::SetRotationSpeed { x, y, z } => self.speeds = Vec3::new(x, y, z),
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Polyhedron::new(MeshKind::Icosahedron, RenderMode::FlatShaded)
+    }
+
+    fn reset(&mut self) {
+        self.angles = Vec3::new(0.0, 0.0, 0.0);
+        self.speeds = Self::DEFAULT_SPEEDS;
+    }
+}
@@ -0,0 +1,311 @@
+use core::fmt::Write;
+
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    text::{TextBuffer, draw_text_centred},
+};
+
+const PADDLE_H: f32 = 8.0;
+/// The fastest a paddle can move, in pixels per second
+const PADDLE_SPEED: f32 = 30.0;
+/// How much faster the ball gets with each hit
+const SPEED_UP: f32 = 1.06;
+const MAX_BALL_SPEED: f32 = 90.0;
+/// The steepest the ball can leave a paddle, as a fraction of its speed
+const MAX_BOUNCE: f32 = 0.75;
+const POINTS_TO_WIN: u8 = 7;
+/// Seconds between a point and the next serve
+const SERVE_DELAY: f32 = 1.0;
+/// Seconds the winner is shown for
+const WIN_DELAY: f32 = 3.0;
+
+#[derive(Copy, Clone)]
+struct Paddle {
+    /// the centre of the paddle
+    y: f32,
+    score: u8,
+    /// how far off the paddle is aiming this rally, in pixels
+    error: f32,
+}
+
+/// A game of pong with the computer playing both sides.
+///
+/// Each paddle aims for where the ball is going, plus a random error that's picked
+/// each time the ball heads its way. The error grows with the imperfection setting,
+/// which is what makes the paddles miss.
+pub struct Pong<Rng, const W: usize, const H: usize> {
+    paddles: [Paddle; 2],
+    ball: (f32, f32),
+    /// pixels per second
+    velocity: (f32, f32),
+    /// seconds until the ball is served, or the winner is cleared
+    waiting: f32,
+    winner: Option<usize>,
+    /// 0 plays perfectly, 1 misses a lot
+    imperfection: f32,
+    serve_speed: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Pong<Rng, W, H> {
+    /// The x position of the face of each paddle that the ball hits
+    const PADDLE_X: [f32; 2] = [2.0, W as f32 - 3.0];
+    /// Leave room for the score at the top
+    const TOP: f32 = 7.0;
+
+    pub fn new(rng: Rng) -> Self {
+        let mut this = Pong {
+            paddles: [Paddle {
+                y: 0.0,
+                score: 0,
+                error: 0.0,
+            }; 2],
+            ball: (0.0, 0.0),
+            velocity: (0.0, 0.0),
+            waiting: 0.0,
+            winner: None,
+            imperfection: 0.35,
+            serve_speed: 35.0,
+            rng,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    fn middle() -> f32 {
+        (Self::TOP + H as f32) / 2.0
+    }
+
+    /// Put the ball in the middle, ready to go towards `side`
+    fn serve(&mut self, side: usize) {
+        self.ball = (W as f32 / 2.0, Self::middle());
+        let vy = (self.rng.unit_f32() - 0.5) * self.serve_speed;
+        let vx = if side == 0 {
+            -self.serve_speed
+        } else {
+            self.serve_speed
+        };
+        self.velocity = (vx, vy);
+        self.waiting = SERVE_DELAY;
+        self.pick_error(side);
+    }
+
+    fn pick_error(&mut self, side: usize) {
+        let reach = PADDLE_H * (0.1 + 1.5 * self.imperfection);
+        self.paddles[side].error = (self.rng.unit_f32() * 2.0 - 1.0) * reach;
+    }
+
+    /// Where the ball will cross the paddle's x position, following the bounces
+    /// off the top and bottom walls
+    fn predict(&self, side: usize) -> f32 {
+        let (x, y) = self.ball;
+        let (vx, vy) = self.velocity;
+        let time = (Self::PADDLE_X[side] - x) / vx;
+        let height = H as f32 - 1.0 - Self::TOP;
+        // unfold the bounces into a straight line, then fold it back up
+        let travelled = y - Self::TOP + vy * time;
+        let folded = libm::fmodf(libm::fabsf(travelled), 2.0 * height);
+        let folded = if folded > height {
+            2.0 * height - folded
+        } else {
+            folded
+        };
+        Self::TOP + folded
+    }
+
+    fn move_paddles(&mut self, dt: f32) {
+        for side in 0..2 {
+            let coming = (side == 0) == (self.velocity.0 < 0.0);
+            let target = if coming && self.waiting <= 0.0 {
+                self.predict(side) + self.paddles[side].error
+            } else {
+                Self::middle()
+            };
+            let paddle = &mut self.paddles[side];
+            let step = PADDLE_SPEED * dt;
+            paddle.y += (target - paddle.y).clamp(-step, step);
+            paddle.y = paddle
+                .y
+                .clamp(Self::TOP + PADDLE_H / 2.0, H as f32 - PADDLE_H / 2.0);
+        }
+    }
+
+    fn move_ball(&mut self, dt: f32) {
+        let (mut x, mut y) = self.ball;
+        let (mut vx, mut vy) = self.velocity;
+        let (old_x, _) = self.ball;
+        x += vx * dt;
+        y += vy * dt;
+
+        if y < Self::TOP {
+            y = 2.0 * Self::TOP - y;
+            vy = -vy;
+        }
+        let bottom = H as f32 - 1.0;
+        if y > bottom {
+            y = 2.0 * bottom - y;
+            vy = -vy;
+        }
+
+        for side in 0..2 {
+            let face = Self::PADDLE_X[side];
+            let crossed = if side == 0 {
+                old_x >= face && x < face
+            } else {
+                old_x <= face && x > face
+            };
+            let offset = (y - self.paddles[side].y) / (PADDLE_H / 2.0);
+            if crossed && libm::fabsf(offset) <= 1.1 {
+                x = 2.0 * face - x;
+                let speed = (libm::sqrtf(vx * vx + vy * vy) * SPEED_UP).min(MAX_BALL_SPEED);
+                // where the ball hits on the paddle decides the angle it leaves at
+                let sideways = offset.clamp(-1.0, 1.0) * MAX_BOUNCE;
+                vy = speed * sideways;
+                vx = -vx.signum() * speed * libm::sqrtf(1.0 - sideways * sideways);
+                self.pick_error(1 - side);
+            }
+        }
+
+        self.ball = (x, y);
+        self.velocity = (vx, vy);
+
+        // missed
+        let scorer = if x < 0.0 {
+            Some(1)
+        } else if x > W as f32 {
+            Some(0)
+        } else {
+            None
+        };
+        if let Some(scorer) = scorer {
+            self.paddles[scorer].score += 1;
+            if self.paddles[scorer].score >= POINTS_TO_WIN {
+                self.winner = Some(scorer);
+                self.waiting = WIN_DELAY;
+            } else {
+                self.serve(1 - scorer);
+            }
+        }
+    }
+
+    fn step(&mut self, dt: f32) {
+        if self.waiting > 0.0 {
+            self.waiting -= dt;
+            if self.waiting <= 0.0 && self.winner.take().is_some() {
+                self.paddles[0].score = 0;
+                self.paddles[1].score = 0;
+                let side = (self.rng.next_u32() % 2) as usize;
+                self.serve(side);
+            }
+        } else {
+            self.move_ball(dt);
+        }
+        self.move_paddles(dt);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum PongUpdate {
+    Reset,
+    /// How often the paddles miss, from 0 for never to 1 for a lot
+    SetImperfection(f32),
+    /// The speed the ball is served at, in pixels per second
+    SetServeSpeed(f32),
+}
+
+impl StateUpdate for PongUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Pong<Rng, W, H> {
+    type StateUpdate = PongUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let mut dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        // small steps so the ball can't skip past a paddle
+        while dt > 0.0 {
+            let step = dt.min(0.01);
+            self.step(step);
+            dt -= step;
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let grey = Rgb888::new(60, 60, 60);
+        let white = Rgb888::new(255, 255, 255);
+        // the net
+        let _ = target.draw_iter(
+            (Self::TOP as i32..H as i32)
+                .filter(|y| y % 4 < 2)
+                .map(|y| Pixel(Point::new(W as i32 / 2, y), grey)),
+        );
+
+        let colours = [Rgb888::new(255, 80, 80), Rgb888::new(80, 160, 255)];
+        for (side, (paddle, colour)) in self.paddles.iter().zip(colours).enumerate() {
+            let x = if side == 0 {
+                Self::PADDLE_X[0] as i32 - 1
+            } else {
+                Self::PADDLE_X[1] as i32 + 1
+            };
+            let top = libm::roundf(paddle.y - PADDLE_H / 2.0) as i32;
+            let _ = target
+                .draw_iter((top..top + PADDLE_H as i32).map(|y| Pixel(Point::new(x, y), colour)));
+            let mut score: TextBuffer<4> = TextBuffer::new();
+            let _ = write!(score, "{}", paddle.score);
+            let x = W as i32 / 2 + if side == 0 { -8 } else { 8 };
+            draw_text_centred(target, score.as_str(), Point::new(x, 0), colour);
+        }
+
+        match self.winner {
+            Some(winner) => {
+                let text = if winner == 0 { "RED WINS" } else { "BLUE WINS" };
+                draw_text_centred(
+                    target,
+                    text,
+                    Point::new(W as i32 / 2, H as i32 / 2),
+                    colours[winner],
+                );
+            }
+            None => {
+                let (x, y) = self.ball;
+                let _ = target.draw_iter([Pixel(
+                    Point::new(libm::floorf(x) as i32, libm::floorf(y) as i32),
+                    white,
+                )]);
+            }
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            PongUpdate::Reset => self.reset(),
+            PongUpdate::SetImperfection(imperfection) => {
+                self.imperfection = imperfection.clamp(0.0, 1.0)
+            }
+            PongUpdate::SetServeSpeed(speed) => self.serve_speed = speed.clamp(5.0, MAX_BALL_SPEED),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Pong::new(rng)
+    }
+
+    fn reset(&mut self) {
+        for paddle in self.paddles.iter_mut() {
+            paddle.y = Self::middle();
+            paddle.score = 0;
+        }
+        self.winner = None;
+        let side = (self.rng.next_u32() % 2) as usize;
+        self.serve(side);
+    }
+}
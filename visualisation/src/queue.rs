@@ -0,0 +1,102 @@
+use core::mem::MaybeUninit;
+
+/// A fixed capacity double ended queue, stored as a ring buffer.
+/// `push` and `pull` use it as a stack, `pull_front` takes from the other end.
+pub struct Queue<T, const N: usize> {
+    data: [MaybeUninit<T>; N],
+    /// the index of the first element
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy + PartialEq, const N: usize> Queue<T, N> {
+    pub fn new() -> Self {
+        Self {
+            data: [MaybeUninit::uninit(); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn slot(&self, i: usize) -> usize {
+        (self.head + i) % N
+    }
+
+    /// Push onto the back of the queue, returning the value if the queue is full
+    pub fn push(&mut self, val: T) -> Option<T> {
+        if self.len < N {
+            self.data[self.slot(self.len)] = MaybeUninit::new(val);
+            self.len += 1;
+            None
+        } else {
+            Some(val)
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> {
+        (0..self.len).map(|i| unsafe { self.data[self.slot(i)].assume_init() })
+    }
+
+    pub fn push_unique(&mut self, val: T) -> Option<T> {
+        if self.iter().all(|x| x != val) {
+            self.push(val)
+        } else {
+            None
+        }
+    }
+
+    /// Take from the back of the queue
+    pub fn pull(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        Some(unsafe { self.data[self.slot(self.len)].assume_init() })
+    }
+
+    /// Take from the front of the queue
+    pub fn pull_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let val = unsafe { self.data[self.head].assume_init() };
+        self.head = self.slot(1);
+        self.len -= 1;
+        Some(val)
+    }
+
+    /// The value at the back of the queue
+    pub fn last(&self) -> Option<T> {
+        let i = self.len.checked_sub(1)?;
+        self.get(i)
+    }
+
+    /// The `i`th value from the front of the queue
+    pub fn get(&self, i: usize) -> Option<T> {
+        if i < self.len {
+            Some(unsafe { self.data[self.slot(i)].assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// Remove the `i`th value, replacing it with the value from the back of the queue
+    pub fn swap_remove(&mut self, i: usize) -> Option<T> {
+        let val = self.get(i)?;
+        let last = self.pull()?;
+        if i < self.len {
+            self.data[self.slot(i)] = MaybeUninit::new(last);
+        }
+        Some(val)
+    }
+}
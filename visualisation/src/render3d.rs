@@ -0,0 +1,472 @@
+//! A small 3d pipeline for drawing meshes onto the panel: vectors and matrices,
+//! perspective projection, and wireframe or flat-shaded, depth-tested triangles.
+
+use core::ops::{Add, Mul, Neg, Sub};
+
+use embedded_graphics::{
+    Drawable, Pixel,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, Point, Primitive, RgbColor},
+    primitives::{Line, PrimitiveStyle},
+};
+
+use crate::grid::Grid;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        libm::sqrtf(self.dot(self))
+    }
+
+    pub fn normalised(self) -> Vec3 {
+        let length = self.length();
+        if length > 0.0 {
+            self * (1.0 / length)
+        } else {
+            self
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: f32) -> Vec3 {
+        Vec3::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// A row-major 4x4 matrix, acting on column vectors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(v: Vec3) -> Self {
+        Mat4([
+            [1.0, 0.0, 0.0, v.x],
+            [0.0, 1.0, 0.0, v.y],
+            [0.0, 0.0, 1.0, v.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_x(angle: f32) -> Self {
+        let (s, c) = (libm::sinf(angle), libm::cosf(angle));
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, c, -s, 0.0],
+            [0.0, s, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_y(angle: f32) -> Self {
+        let (s, c) = (libm::sinf(angle), libm::cosf(angle));
+        Mat4([
+            [c, 0.0, s, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-s, 0.0, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_z(angle: f32) -> Self {
+        let (s, c) = (libm::sinf(angle), libm::cosf(angle));
+        Mat4([
+            [c, -s, 0.0, 0.0],
+            [s, c, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// A perspective projection looking down the -z axis, mapping the view frustum
+    /// onto x and y in [-1, 1] and depth in [0, 1]
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / libm::tanf(fov_y / 2.0);
+        let range = far - near;
+        Mat4([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [0.0, 0.0, -far / range, -far * near / range],
+            [0.0, 0.0, -1.0, 0.0],
+        ])
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Transform a point, returning the result in homogeneous coordinates
+    pub fn transform_point(&self, v: Vec3) -> [f32; 4] {
+        let m = &self.0;
+        let mut out = [0.0; 4];
+        for (o, row) in out.iter_mut().zip(m.iter()) {
+            *o = row[0] * v.x + row[1] * v.y + row[2] * v.z + row[3];
+        }
+        out
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut out = [[0.0; 4]; 4];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Mat4(out)
+    }
+}
+
+/// A triangle mesh, with anticlockwise winding for the outward faces
+pub struct Mesh {
+    pub vertices: &'static [Vec3],
+    pub faces: &'static [[u8; 3]],
+    /// The edges to draw in wireframe mode
+    pub edges: &'static [[u8; 2]],
+}
+
+pub const CUBE: Mesh = Mesh {
+    vertices: &[
+        Vec3::new(-1.0, -1.0, -1.0),
+        Vec3::new(1.0, -1.0, -1.0),
+        Vec3::new(1.0, 1.0, -1.0),
+        Vec3::new(-1.0, 1.0, -1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+        Vec3::new(1.0, -1.0, 1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(-1.0, 1.0, 1.0),
+    ],
+    faces: &[
+        [4, 5, 6],
+        [4, 6, 7],
+        [1, 0, 3],
+        [1, 3, 2],
+        [5, 1, 2],
+        [5, 2, 6],
+        [0, 4, 7],
+        [0, 7, 3],
+        [7, 6, 2],
+        [7, 2, 3],
+        [0, 1, 5],
+        [0, 5, 4],
+    ],
+    edges: &[
+        [0, 1],
+        [1, 2],
+        [2, 3],
+        [3, 0],
+        [4, 5],
+        [5, 6],
+        [6, 7],
+        [7, 4],
+        [0, 4],
+        [1, 5],
+        [2, 6],
+        [3, 7],
+    ],
+};
+
+/// The golden ratio, normalised so that the icosahedron fits in the unit sphere
+const ICO_A: f32 = 0.525_731_1;
+const ICO_B: f32 = 0.850_650_8;
+
+pub const ICOSAHEDRON: Mesh = Mesh {
+    vertices: &[
+        Vec3::new(-ICO_A, ICO_B, 0.0),
+        Vec3::new(ICO_A, ICO_B, 0.0),
+        Vec3::new(-ICO_A, -ICO_B, 0.0),
+        Vec3::new(ICO_A, -ICO_B, 0.0),
+        Vec3::new(0.0, -ICO_A, ICO_B),
+        Vec3::new(0.0, ICO_A, ICO_B),
+        Vec3::new(0.0, -ICO_A, -ICO_B),
+        Vec3::new(0.0, ICO_A, -ICO_B),
+        Vec3::new(ICO_B, 0.0, -ICO_A),
+        Vec3::new(ICO_B, 0.0, ICO_A),
+        Vec3::new(-ICO_B, 0.0, -ICO_A),
+        Vec3::new(-ICO_B, 0.0, ICO_A),
+    ],
+    faces: &[
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ],
+    edges: &[
+        [0, 1],
+        [0, 5],
+        [0, 7],
+        [0, 10],
+        [0, 11],
+        [1, 5],
+        [1, 7],
+        [1, 8],
+        [1, 9],
+        [2, 3],
+        [2, 4],
+        [2, 6],
+        [2, 10],
+        [2, 11],
+        [3, 4],
+        [3, 6],
+        [3, 8],
+        [3, 9],
+        [4, 5],
+        [4, 9],
+        [4, 11],
+        [5, 9],
+        [5, 11],
+        [6, 7],
+        [6, 8],
+        [6, 10],
+        [7, 8],
+        [7, 10],
+        [8, 9],
+        [10, 11],
+    ],
+};
+
+/// A vertex after projection, in pixel coordinates with a depth in [0, 1]
+#[derive(Copy, Clone, Debug)]
+pub struct ScreenPoint {
+    pub x: f32,
+    pub y: f32,
+    pub depth: f32,
+}
+
+/// Projects and rasterises onto a `W` by `H` panel, keeping a depth buffer the size of the panel
+pub struct Renderer<const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    depth: Grid<f32, W, H>,
+}
+
+impl<const W: usize, const H: usize> Default for Renderer<W, H>
+where
+    [(); W * H]:,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: usize, const H: usize> Renderer<W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new() -> Self {
+        Renderer {
+            depth: Grid::new(f32::INFINITY),
+        }
+    }
+
+    /// The aspect ratio of the panel, for building a projection matrix
+    pub const fn aspect() -> f32 {
+        W as f32 / H as f32
+    }
+
+    pub fn clear_depth(&mut self) {
+        self.depth.buffer_mut().fill(f32::INFINITY);
+    }
+
+    /// Project a point with the given model-view-projection matrix.
+    /// Returns None if the point is behind the camera.
+    pub fn project(mvp: &Mat4, v: Vec3) -> Option<ScreenPoint> {
+        let [x, y, z, w] = mvp.transform_point(v);
+        if w <= 1e-6 {
+            return None;
+        }
+        Some(ScreenPoint {
+            x: (x / w + 1.0) * 0.5 * W as f32,
+            y: (1.0 - y / w) * 0.5 * H as f32,
+            depth: z / w,
+        })
+    }
+
+    /// Draw a line between two projected points, without any depth testing
+    pub fn draw_line<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        target: &mut D,
+        a: ScreenPoint,
+        b: ScreenPoint,
+        colour: Rgb888,
+    ) {
+        let _ = Line::new(
+            Point::new(a.x as i32, a.y as i32),
+            Point::new(b.x as i32, b.y as i32),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(colour, 1))
+        .draw(target);
+    }
+
+    /// Fill a projected triangle, only drawing the pixels closer than what's already been drawn.
+    /// Triangles wound clockwise on screen face away from the camera and are skipped.
+    pub fn fill_triangle<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        &mut self,
+        target: &mut D,
+        [a, b, c]: [ScreenPoint; 3],
+        colour: Rgb888,
+    ) {
+        let edge = |p: &ScreenPoint, q: &ScreenPoint, x: f32, y: f32| {
+            (q.x - p.x) * (y - p.y) - (q.y - p.y) * (x - p.x)
+        };
+        // screen y points down, so anticlockwise triangles have a negative area here
+        let area = edge(&a, &b, c.x, c.y);
+        if area >= 0.0 {
+            return;
+        }
+
+        let min_x = (libm::floorf(a.x.min(b.x).min(c.x)) as i32).max(0);
+        let max_x = (libm::ceilf(a.x.max(b.x).max(c.x)) as i32).min(W as i32 - 1);
+        let min_y = (libm::floorf(a.y.min(b.y).min(c.y)) as i32).max(0);
+        let max_y = (libm::ceilf(a.y.max(b.y).max(c.y)) as i32).min(H as i32 - 1);
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(&b, &c, px, py) / area;
+                let w1 = edge(&c, &a, px, py) / area;
+                let w2 = edge(&a, &b, px, py) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+                let depth = w0 * a.depth + w1 * b.depth + w2 * c.depth;
+                if let Some(d) = self.depth.get_mut(x, y)
+                    && depth < *d
+                {
+                    *d = depth;
+                    let _ = target.draw_iter(core::iter::once(Pixel(Point::new(x, y), colour)));
+                }
+            }
+        }
+    }
+
+    /// Draw the edges of a mesh
+    pub fn draw_wireframe<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        target: &mut D,
+        mesh: &Mesh,
+        mvp: &Mat4,
+        colour: Rgb888,
+    ) {
+        for [i, j] in mesh.edges.iter() {
+            if let (Some(a), Some(b)) = (
+                Self::project(mvp, mesh.vertices[*i as usize]),
+                Self::project(mvp, mesh.vertices[*j as usize]),
+            ) {
+                Self::draw_line(target, a, b, colour);
+            }
+        }
+    }
+
+    /// Draw the faces of a mesh, each lit with a single brightness from the
+    /// angle between its normal and the light direction (in model-view space)
+    pub fn draw_flat_shaded<D: DrawTarget<Color = Rgb888, Error = core::convert::Infallible>>(
+        &mut self,
+        target: &mut D,
+        mesh: &Mesh,
+        model_view: &Mat4,
+        projection: &Mat4,
+        light: Vec3,
+        colour: Rgb888,
+    ) {
+        let mvp = *projection * *model_view;
+        let light = light.normalised();
+        for face in mesh.faces.iter() {
+            let [a, b, c] = face.map(|i| mesh.vertices[i as usize]);
+            let normal = model_view
+                .transform_vector((b - a).cross(c - a))
+                .normalised();
+            let brightness = 0.2 + 0.8 * normal.dot(light).max(0.0);
+            let shaded = Rgb888::new(
+                (colour.r() as f32 * brightness) as u8,
+                (colour.g() as f32 * brightness) as u8,
+                (colour.b() as f32 * brightness) as u8,
+            );
+            if let (Some(a), Some(b), Some(c)) = (
+                Self::project(&mvp, a),
+                Self::project(&mvp, b),
+                Self::project(&mvp, c),
+            ) {
+                self.fill_triangle(target, [a, b, c], shaded);
+            }
+        }
+    }
+}
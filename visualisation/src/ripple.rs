@@ -0,0 +1,199 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    audio::AudioFrame,
+    grid::Grid,
+    palette::{Palette, lerp},
+};
+
+/// How far a wave moves each step, in pixels. Has to stay below 1/sqrt(2) to be stable.
+const COURANT: f32 = 0.5;
+/// The most steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 16;
+/// The spacing of the tiles on the floor of the tank
+const TILE: i32 = 8;
+/// How far the slope of the surface bends the view of the floor, in pixels
+const REFRACTION: f32 = 6.0;
+
+/// A ripple tank: the damped 2d wave equation on a height field, with drops of rain
+/// falling into it. It's drawn by bending the view of a tiled floor through the
+/// slope of the surface, and lighting the surface from one side.
+pub struct Ripple<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    height: Grid<f32, W, H>,
+    /// the height field from the step before, which the next step is written into
+    previous: Grid<f32, W, H>,
+    rng: Rng,
+    /// the fraction of the wave energy lost per second
+    damping: f32,
+    /// random drops per second
+    drop_rate: f32,
+    /// pixels per second
+    wave_speed: f32,
+    palette: Palette,
+    time_banked: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Ripple<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(damping: f32, drop_rate: f32, wave_speed: f32, rng: Rng) -> Self {
+        Ripple {
+            height: Grid::new(0.0),
+            previous: Grid::new(0.0),
+            rng,
+            damping,
+            drop_rate,
+            wave_speed,
+            palette: Palette::Ocean,
+            time_banked: 0.0,
+        }
+    }
+
+    /// Push the surface down in a small round dip centred on (x, y)
+    fn drop(&mut self, x: i32, y: i32, strength: f32) {
+        for dy in -2..=2 {
+            for dx in -2..=2 {
+                let r2 = (dx * dx + dy * dy) as f32;
+                if r2 <= 4.0
+                    && let Some(h) = self.height.get_mut(x + dx, y + dy)
+                {
+                    *h -= strength * (1.0 - r2 / 5.0);
+                }
+            }
+        }
+    }
+
+    /// The height at a position, with the edges reflecting waves back
+    fn height_at(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, W as i32 - 1);
+        let y = y.clamp(0, H as i32 - 1);
+        self.height.get(x, y).copied().unwrap_or(0.0)
+    }
+
+    fn step(&mut self, dt: f32) {
+        let c2 = COURANT * COURANT;
+        let keep = (1.0 - self.damping * dt).clamp(0.0, 1.0);
+        for y in 0..H as i32 {
+            for x in 0..W as i32 {
+                let h = self.height_at(x, y);
+                let laplacian = self.height_at(x - 1, y)
+                    + self.height_at(x + 1, y)
+                    + self.height_at(x, y - 1)
+                    + self.height_at(x, y + 1)
+                    - 4.0 * h;
+                if let Some(previous) = self.previous.get_mut(x, y) {
+                    *previous = (2.0 * h - *previous + c2 * laplacian) * keep;
+                }
+            }
+        }
+        core::mem::swap(&mut self.height, &mut self.previous);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum RippleUpdate {
+    Reset,
+    /// Make a ripple at a point
+    Poke {
+        x: u8,
+        y: u8,
+    },
+    /// The fraction of the wave energy lost per second
+    SetDamping(f32),
+    /// The number of random raindrops per second
+    SetDropRate(f32),
+    /// How fast the waves travel, in pixels per second
+    SetWaveSpeed(f32),
+    SetPalette(Palette),
+}
+
+impl StateUpdate for RippleUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Ripple<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = RippleUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        if self.rng.unit_f32() < self.drop_rate * dt {
+            let x = (self.rng.next_u32() % W as u32) as i32;
+            let y = (self.rng.next_u32() % H as u32) as i32;
+            let strength = 1.0 + self.rng.unit_f32() * 2.0;
+            self.drop(x, y, strength);
+        }
+
+        // each step moves the waves COURANT pixels
+        let step_time = COURANT / self.wave_speed;
+        self.time_banked = (self.time_banked + dt).min(step_time * MAX_STEPS as f32);
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step(step_time);
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let white = Rgb888::new(255, 255, 255);
+        let _ = target.draw_iter(Grid::<f32, W, H>::iter_coords().map(|(x, y)| {
+            let slope_x = self.height_at(x + 1, y) - self.height_at(x - 1, y);
+            let slope_y = self.height_at(x, y + 1) - self.height_at(x, y - 1);
+            // look through the surface at a bent position on the floor
+            let floor_x = libm::floorf(x as f32 + slope_x * REFRACTION) as i32;
+            let floor_y = libm::floorf(y as f32 + slope_y * REFRACTION) as i32;
+            let grout = floor_x.rem_euclid(TILE) == 0 || floor_y.rem_euclid(TILE) == 0;
+            // light coming from the top left
+            let light = -(slope_x + slope_y) * 0.5;
+            let t = 0.45 + light * 0.6 - if grout { 0.15 } else { 0.0 };
+            let colour = self.palette.sample(t);
+            let colour = if light > 0.3 {
+                lerp(colour, white, ((light - 0.3) * 2.0).min(1.0))
+            } else {
+                colour
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            RippleUpdate::Reset => self.reset(),
+            RippleUpdate::Poke { x, y } => self.drop(x as i32, y as i32, 3.0),
+            RippleUpdate::SetDamping(damping) => self.damping = damping.clamp(0.0, 10.0),
+            RippleUpdate::SetDropRate(rate) => self.drop_rate = rate.max(0.0),
+            RippleUpdate::SetWaveSpeed(speed) => self.wave_speed = speed.clamp(1.0, 200.0),
+            RippleUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn audio(&mut self, frame: &AudioFrame) {
+        // a drop in the middle on each beat, bigger for stronger beats
+        if frame.beat {
+            self.drop(W as i32 / 2, H as i32 / 2, 1.5 + 2.0 * frame.beat_strength);
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Ripple::new(0.6, 1.5, 25.0, rng)
+    }
+
+    fn reset(&mut self) {
+        self.height.buffer_mut().fill(0.0);
+        self.previous.buffer_mut().fill(0.0);
+        self.time_banked = 0.0;
+    }
+}
@@ -1,62 +1,12 @@
-use crate::{RngU32, grid::Grid};
+use crate::{RngU32, grid::Grid, queue::Queue};
 
 use super::{StateUpdate, Visualisation};
 
-mod queue {
-    use core::mem::MaybeUninit;
-
-    pub struct Queue<T, const N: usize> {
-        data: [MaybeUninit<T>; N],
-        ptr: usize,
-    }
-
-    impl<T: Copy + PartialEq, const N: usize> Queue<T, N> {
-        pub fn new() -> Self {
-            Self {
-                data: [MaybeUninit::uninit(); N],
-                ptr: 0,
-            }
-        }
-
-        #[allow(dead_code)]
-        pub fn len(&self) -> usize {
-            self.ptr
-        }
-
-        pub fn push(&mut self, val: T) -> Option<T> {
-            if self.ptr < N {
-                self.data[self.ptr] = MaybeUninit::new(val);
-                self.ptr += 1;
-                None
-            } else {
-                Some(val)
-            }
-        }
-
-        pub fn iter(&self) -> impl Iterator<Item = T> {
-            (0..self.ptr).map(|i| unsafe { self.data[i].assume_init() })
-        }
-
-        pub fn push_unique(&mut self, val: T) -> Option<T> {
-            if self.iter().all(|x| x != val) {
-                self.push(val)
-            } else {
-                None
-            }
-        }
-
-        pub fn pull(&mut self) -> Option<T> {
-            self.ptr = self.ptr.checked_sub(1)?;
-            Some(unsafe { self.data[self.ptr].assume_init() })
-        }
-    }
-}
 use embedded_graphics::{
     Pixel,
     pixelcolor::Rgb888,
     prelude::{Point, RgbColor, WebColors},
 };
-use queue::Queue;
 
 pub struct SandPile<Rng, const W: usize, const H: usize>
 where
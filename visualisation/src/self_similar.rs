@@ -0,0 +1,729 @@
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    Drawable, Pixel,
+    pixelcolor::Rgb888,
+    prelude::{DrawTarget, OriginDimensions, Point, Primitive, RgbColor, Size},
+    primitives::{Line, PrimitiveStyle},
+};
+use heapless::{String, Vec};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    grid::Grid,
+    palette::{Palette, lerp},
+};
+
+/// The most maps in an IFS
+pub const MAX_IFS_MAPS: usize = 8;
+/// The longest L-system axiom
+pub const MAX_AXIOM_LEN: usize = 32;
+/// The longest replacement in an L-system rule
+pub const MAX_RULE_LEN: usize = 48;
+/// The most rules in an L-system
+pub const MAX_RULES: usize = 4;
+/// The most times an L-system's rules are applied
+pub const MAX_ITERATIONS: u8 = 8;
+/// IFS coefficients are fixed point, with this many fractional bits
+const IFS_FRACTION_BITS: u32 = 12;
+/// Points to run the chaos game for to find the size of an IFS
+const IFS_MEASURE_POINTS: u32 = 4000;
+/// The chaos game stops once this many points have been plotted
+const IFS_MAX_POINTS: u32 = 60_000;
+/// An L-system is cut short after this many symbols, so a grammar that grows too
+/// fast can't go on forever
+const MAX_SYMBOLS: u32 = 100_000;
+/// The most L-system symbols to work through in one update
+const SYMBOLS_PER_UPDATE: u32 = 4000;
+/// How deep an L-system's branches can be saved with `[`
+const MAX_BRANCH_DEPTH: usize = 32;
+/// How long a finished picture is shown before moving on to the next preset, in seconds
+const SHOW_TIME: f32 = 4.0;
+
+const BARK_COLOUR: Rgb888 = Rgb888::new(110, 60, 20);
+const LEAF_COLOUR: Rgb888 = Rgb888::new(90, 255, 60);
+
+/// An affine map `x' = a x + b y + e, y' = c x + d y + f`, with the coefficients as
+/// fixed point numbers with 12 fractional bits, so 4096 is 1.0
+#[derive(Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IfsMap {
+    /// a, b, c, d, e and f
+    pub coefficients: [i16; 6],
+    /// How often this map is chosen, relative to the others
+    pub weight: u8,
+}
+
+impl IfsMap {
+    pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32, weight: u8) -> Self {
+        const fn fixed(x: f32) -> i16 {
+            (x * (1 << IFS_FRACTION_BITS) as f32) as i16
+        }
+        IfsMap {
+            coefficients: [fixed(a), fixed(b), fixed(c), fixed(d), fixed(e), fixed(f)],
+            weight,
+        }
+    }
+
+    fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self
+            .coefficients
+            .map(|k| k as f32 / (1 << IFS_FRACTION_BITS) as f32);
+        (a * x + b * y + e, c * x + d * y + f)
+    }
+}
+
+/// An iterated function system, drawn with the chaos game
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct IfsSystem {
+    pub maps: Vec<IfsMap, MAX_IFS_MAPS>,
+}
+
+/// A rule replacing `symbol` with `replacement` each iteration. The symbol is a
+/// single byte, as the axiom and replacements are worked through a byte at a time.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LSystemRule {
+    pub symbol: u8,
+    pub replacement: String<MAX_RULE_LEN>,
+}
+
+/// An L-system, drawn by a turtle. `F` and `G` move forward drawing a line, `f` moves
+/// forward without drawing, `+` and `-` turn left and right by `angle`, `|` turns
+/// around, and `[` and `]` save and go back to the turtle's position. Anything else
+/// is only there for the rules.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LSystem {
+    pub axiom: String<MAX_AXIOM_LEN>,
+    pub rules: Vec<LSystemRule, MAX_RULES>,
+    /// How far `+` and `-` turn, in degrees
+    pub angle: f32,
+    /// How many times the rules are applied, up to [MAX_ITERATIONS]
+    pub iterations: u8,
+}
+
+impl LSystem {
+    fn rule_for(&self, symbol: u8) -> Option<u8> {
+        self.rules
+            .iter()
+            .position(|rule| rule.symbol == symbol)
+            .map(|i| i as u8)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SelfSimilarPreset {
+    /// Barnsley's fern
+    Fern,
+    /// The Sierpinski triangle
+    Sierpinski,
+    /// Heighway's dragon
+    Dragon,
+    /// A branching plant with leaves, from The Algorithmic Beauty of Plants
+    Plant,
+    /// A bushy plant
+    Bush,
+    /// A weed with straight branches
+    Weed,
+}
+
+impl SelfSimilarPreset {
+    fn next(self) -> Self {
+        match self {
+            SelfSimilarPreset::Fern => SelfSimilarPreset::Sierpinski,
+            SelfSimilarPreset::Sierpinski => SelfSimilarPreset::Dragon,
+            SelfSimilarPreset::Dragon => SelfSimilarPreset::Plant,
+            SelfSimilarPreset::Plant => SelfSimilarPreset::Bush,
+            SelfSimilarPreset::Bush => SelfSimilarPreset::Weed,
+            SelfSimilarPreset::Weed => SelfSimilarPreset::Fern,
+        }
+    }
+
+    fn shape(self) -> Shape {
+        let ifs = |maps: &[IfsMap]| {
+            Shape::Ifs(IfsSystem {
+                maps: Vec::from_slice(maps).unwrap_or_default(),
+            })
+        };
+        let l_system = |axiom: &str, rules: &[(u8, &str)], angle: f32, iterations: u8| {
+            Shape::LSystem(LSystem {
+                axiom: String::try_from(axiom).unwrap_or_default(),
+                rules: rules
+                    .iter()
+                    .map(|(symbol, replacement)| LSystemRule {
+                        symbol: *symbol,
+                        replacement: String::try_from(*replacement).unwrap_or_default(),
+                    })
+                    .collect(),
+                angle,
+                iterations,
+            })
+        };
+        match self {
+            SelfSimilarPreset::Fern => ifs(&[
+                IfsMap::new(0.0, 0.0, 0.0, 0.16, 0.0, 0.0, 1),
+                IfsMap::new(0.85, 0.04, -0.04, 0.85, 0.0, 1.6, 85),
+                IfsMap::new(0.2, -0.26, 0.23, 0.22, 0.0, 1.6, 7),
+                IfsMap::new(-0.15, 0.28, 0.26, 0.24, 0.0, 0.44, 7),
+            ]),
+            SelfSimilarPreset::Sierpinski => ifs(&[
+                IfsMap::new(0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1),
+                IfsMap::new(0.5, 0.0, 0.0, 0.5, 1.0, 0.0, 1),
+                IfsMap::new(0.5, 0.0, 0.0, 0.5, 0.5, 0.866, 1),
+            ]),
+            SelfSimilarPreset::Dragon => ifs(&[
+                IfsMap::new(0.5, -0.5, 0.5, 0.5, 0.0, 0.0, 1),
+                IfsMap::new(-0.5, -0.5, 0.5, -0.5, 1.0, 0.0, 1),
+            ]),
+            SelfSimilarPreset::Plant => {
+                l_system("X", &[(b'X', "F+[[X]-X]-F[-FX]+X"), (b'F', "FF")], 25.0, 5)
+            }
+            SelfSimilarPreset::Bush => l_system("F", &[(b'F', "FF+[+F-F-F]-[-F+F+F]")], 22.5, 3),
+            SelfSimilarPreset::Weed => l_system("F", &[(b'F', "F[+F]F[-F]F")], 25.7, 4),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Shape {
+    Ifs(IfsSystem),
+    LSystem(LSystem),
+}
+
+/// Where the expansion of an L-system is up to in the axiom or in a rule
+#[derive(Copy, Clone)]
+struct Frame {
+    /// the rule being expanded, or None for the axiom
+    rule: Option<u8>,
+    position: u8,
+    /// how many times the rules have been applied to get here
+    depth: u8,
+}
+
+/// Works through the symbols of an L-system one at a time, without ever writing out
+/// the whole expanded string
+struct Expansion {
+    stack: Vec<Frame, { MAX_ITERATIONS as usize + 1 }>,
+    symbols: u32,
+}
+
+impl Expansion {
+    fn new() -> Self {
+        let mut stack = Vec::new();
+        let _ = stack.push(Frame {
+            rule: None,
+            position: 0,
+            depth: 0,
+        });
+        Expansion { stack, symbols: 0 }
+    }
+
+    fn next(&mut self, system: &LSystem) -> Option<u8> {
+        if self.symbols >= MAX_SYMBOLS {
+            return None;
+        }
+        loop {
+            let frame = self.stack.last_mut()?;
+            let text = match frame.rule {
+                None => system.axiom.as_bytes(),
+                Some(rule) => system.rules[rule as usize].replacement.as_bytes(),
+            };
+            let Some(&symbol) = text.get(frame.position as usize) else {
+                self.stack.pop();
+                continue;
+            };
+            frame.position += 1;
+            let depth = frame.depth;
+            if depth < system.iterations
+                && let Some(rule) = system.rule_for(symbol)
+            {
+                let _ = self.stack.push(Frame {
+                    rule: Some(rule),
+                    position: 0,
+                    depth: depth + 1,
+                });
+                continue;
+            }
+            self.symbols += 1;
+            return Some(symbol);
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Turtle {
+    x: f32,
+    y: f32,
+    /// radians anticlockwise from the x axis
+    heading: f32,
+}
+
+impl Turtle {
+    fn start() -> Self {
+        Turtle {
+            x: 0.0,
+            y: 0.0,
+            heading: core::f32::consts::FRAC_PI_2,
+        }
+    }
+}
+
+/// Draws onto a grid of colours, so lines can be drawn into it a few at a time
+struct Canvas<'a, const W: usize, const H: usize>(&'a mut Grid<Rgb888, W, H>)
+where
+    [(); W * H]:;
+
+impl<const W: usize, const H: usize> OriginDimensions for Canvas<'_, W, H>
+where
+    [(); W * H]:,
+{
+    fn size(&self) -> Size {
+        Size::new(W as u32, H as u32)
+    }
+}
+
+impl<const W: usize, const H: usize> DrawTarget for Canvas<'_, W, H>
+where
+    [(); W * H]:,
+{
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Rgb888>>>(
+        &mut self,
+        pixels: I,
+    ) -> Result<(), Infallible> {
+        for Pixel(point, colour) in pixels {
+            self.0.set(point.x, point.y, colour);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    /// running through an L-system without drawing to find its size
+    Measuring,
+    Growing,
+    /// finished, and shown for the seconds left before moving on
+    Done(f32),
+}
+
+/// Self similar pictures grown from simple rules: iterated function systems drawn
+/// with the chaos game, and L-system plants drawn with turtle graphics. Both can be
+/// sent from the phone, or it can cycle through the presets.
+pub struct SelfSimilar<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    shape: Shape,
+    /// the preset being shown, or None for one sent from the phone
+    preset: Option<SelfSimilarPreset>,
+    cycle: bool,
+    phase: Phase,
+    /// how many times the chaos game has landed on each pixel
+    hits: Grid<u16, W, H>,
+    /// the L-system drawn so far
+    canvas: Grid<Rgb888, W, H>,
+    point: (f32, f32),
+    points: u32,
+    expansion: Expansion,
+    turtle: Turtle,
+    branches: Vec<Turtle, MAX_BRANCH_DEPTH>,
+    /// `[`s that didn't fit on the stack, so the matching `]`s are skipped too
+    lost_branches: u32,
+    /// the deepest branch found while measuring, for colouring
+    max_depth: usize,
+    /// the corners of the picture, in its own coordinates
+    min: (f32, f32),
+    max: (f32, f32),
+    points_per_second: f32,
+    segments_per_second: f32,
+    /// points or segments owed from previous updates
+    banked: f32,
+    palette: Palette,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> SelfSimilar<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(preset: SelfSimilarPreset, rng: Rng) -> Self {
+        let mut this = SelfSimilar {
+            shape: preset.shape(),
+            preset: Some(preset),
+            cycle: true,
+            phase: Phase::Growing,
+            hits: Grid::new(0),
+            canvas: Grid::new(Rgb888::BLACK),
+            point: (0.0, 0.0),
+            points: 0,
+            expansion: Expansion::new(),
+            turtle: Turtle::start(),
+            branches: Vec::new(),
+            lost_branches: 0,
+            max_depth: 0,
+            min: (0.0, 0.0),
+            max: (0.0, 0.0),
+            points_per_second: 10_000.0,
+            segments_per_second: 300.0,
+            banked: 0.0,
+            palette: Palette::Ocean,
+            rng,
+        };
+        this.start();
+        this
+    }
+
+    fn load(&mut self, shape: Shape, preset: Option<SelfSimilarPreset>) {
+        self.shape = shape;
+        self.preset = preset;
+        self.start();
+    }
+
+    /// Clear the picture and start drawing it from the beginning
+    fn start(&mut self) {
+        self.hits.buffer_mut().fill(0);
+        self.canvas.buffer_mut().fill(Rgb888::BLACK);
+        self.banked = 0.0;
+        self.point = (0.0, 0.0);
+        self.points = 0;
+        self.min = (f32::MAX, f32::MAX);
+        self.max = (f32::MIN, f32::MIN);
+        self.restart_turtle();
+        self.max_depth = 0;
+        self.phase = match self.shape {
+            Shape::Ifs(_) => {
+                self.measure_ifs();
+                Phase::Growing
+            }
+            Shape::LSystem(_) => Phase::Measuring,
+        };
+    }
+
+    fn restart_turtle(&mut self) {
+        self.expansion = Expansion::new();
+        self.turtle = Turtle::start();
+        self.branches.clear();
+        self.lost_branches = 0;
+    }
+
+    fn include(&mut self, (x, y): (f32, f32)) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    /// Where a point in the picture's own coordinates goes on the panel, fitting the
+    /// whole picture on with a pixel to spare around the edge
+    fn to_screen(&self, (x, y): (f32, f32)) -> Point {
+        let width = (self.max.0 - self.min.0).max(1e-3);
+        let height = (self.max.1 - self.min.1).max(1e-3);
+        let scale = ((W as f32 - 3.0) / width).min((H as f32 - 3.0) / height);
+        let left = (W as f32 - width * scale) / 2.0;
+        let bottom = (H as f32 + height * scale) / 2.0;
+        Point::new(
+            libm::roundf(left + (x - self.min.0) * scale) as i32,
+            libm::roundf(bottom - (y - self.min.1) * scale) as i32 - 1,
+        )
+    }
+
+    fn ifs_step(&mut self) {
+        let Shape::Ifs(ifs) = &self.shape else {
+            return;
+        };
+        let total: u32 = ifs.maps.iter().map(|map| map.weight as u32).sum();
+        let map = if total == 0 {
+            ifs.maps
+                .get(self.rng.next_u32() as usize % ifs.maps.len().max(1))
+        } else {
+            let mut choice = self.rng.next_u32() % total;
+            ifs.maps.iter().find(|map| {
+                let chosen = choice < map.weight as u32;
+                choice = choice.saturating_sub(map.weight as u32);
+                chosen
+            })
+        };
+        if let Some(map) = map {
+            self.point = map.apply(self.point);
+        }
+        if !(self.point.0.is_finite() && self.point.1.is_finite()) {
+            self.point = (0.0, 0.0);
+        }
+    }
+
+    /// Run the chaos game for a while to find how big the picture is
+    fn measure_ifs(&mut self) {
+        // let the point settle onto the attractor first
+        for _ in 0..20 {
+            self.ifs_step();
+        }
+        for _ in 0..IFS_MEASURE_POINTS {
+            self.ifs_step();
+            self.include(self.point);
+        }
+    }
+
+    fn plot_ifs(&mut self, points: u32) {
+        for _ in 0..points {
+            self.ifs_step();
+            let point = self.to_screen(self.point);
+            if let Some(h) = self.hits.get_mut(point.x, point.y) {
+                *h = h.saturating_add(1);
+            }
+        }
+        self.points += points;
+        if self.points >= IFS_MAX_POINTS {
+            self.phase = Phase::Done(SHOW_TIME);
+        }
+    }
+
+    /// Move the turtle on by one symbol, returning the line it drew, if any
+    fn turtle_step(&mut self, symbol: u8, angle: f32) -> Option<((f32, f32), (f32, f32))> {
+        let from = (self.turtle.x, self.turtle.y);
+        match symbol {
+            b'F' | b'G' | b'f' => {
+                self.turtle.x += libm::cosf(self.turtle.heading);
+                self.turtle.y += libm::sinf(self.turtle.heading);
+                if symbol != b'f' {
+                    return Some((from, (self.turtle.x, self.turtle.y)));
+                }
+            }
+            b'+' => self.turtle.heading += angle,
+            b'-' => self.turtle.heading -= angle,
+            b'|' => self.turtle.heading += core::f32::consts::PI,
+            b'[' => {
+                if self.branches.push(self.turtle).is_err() {
+                    self.lost_branches += 1;
+                }
+            }
+            b']' => {
+                if self.lost_branches > 0 {
+                    self.lost_branches -= 1;
+                } else if let Some(turtle) = self.branches.pop() {
+                    self.turtle = turtle;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Run the turtle on for some segments, or until it's gone through
+    /// [SYMBOLS_PER_UPDATE] symbols. While measuring nothing is drawn, and the segments
+    /// are only counted towards the size of the picture.
+    fn run_turtle(&mut self, mut segments: u32) {
+        let Shape::LSystem(system) = &self.shape else {
+            return;
+        };
+        let system = system.clone();
+        let angle = system.angle.to_radians();
+        let measuring = matches!(self.phase, Phase::Measuring);
+        if measuring {
+            self.include((0.0, 0.0));
+        }
+        for _ in 0..SYMBOLS_PER_UPDATE {
+            if segments == 0 {
+                return;
+            }
+            let Some(symbol) = self.expansion.next(&system) else {
+                if measuring {
+                    self.restart_turtle();
+                    self.phase = Phase::Growing;
+                } else {
+                    self.phase = Phase::Done(SHOW_TIME);
+                }
+                return;
+            };
+            let Some((from, to)) = self.turtle_step(symbol, angle) else {
+                continue;
+            };
+            segments -= 1;
+            if measuring {
+                self.include(to);
+                self.max_depth = self.max_depth.max(self.branches.len());
+            } else {
+                let depth = self.branches.len() as f32 / self.max_depth.max(1) as f32;
+                let colour = lerp(BARK_COLOUR, LEAF_COLOUR, depth);
+                let _ = Line::new(self.to_screen(from), self.to_screen(to))
+                    .into_styled(PrimitiveStyle::with_stroke(colour, 1))
+                    .draw(&mut Canvas(&mut self.canvas));
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SelfSimilarUpdate {
+    Reset,
+    LoadPreset(SelfSimilarPreset),
+    /// Draw an IFS sent from the phone. Stops cycling through the presets.
+    LoadIfs(IfsSystem),
+    /// Grow an L-system sent from the phone. Stops cycling through the presets.
+    LoadLSystem(LSystem),
+    /// Whether to move on to the next preset after each picture is finished
+    SetCycle(bool),
+    /// How fast the chaos game plots points
+    SetPointsPerSecond(f32),
+    /// How fast L-system plants grow, in lines per second
+    SetSegmentsPerSecond(f32),
+    /// The colours of the IFS pictures
+    SetPalette(Palette),
+}
+
+impl StateUpdate for SelfSimilarUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for SelfSimilar<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = SelfSimilarUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        match self.phase {
+            Phase::Measuring => self.run_turtle(u32::MAX),
+            Phase::Growing => {
+                let rate = match self.shape {
+                    Shape::Ifs(_) => self.points_per_second,
+                    Shape::LSystem(_) => self.segments_per_second,
+                };
+                self.banked += rate * dt;
+                let count = self.banked as u32;
+                self.banked -= count as f32;
+                match self.shape {
+                    Shape::Ifs(_) => self.plot_ifs(count),
+                    Shape::LSystem(_) => self.run_turtle(count),
+                }
+            }
+            Phase::Done(remaining) => {
+                if remaining > dt {
+                    self.phase = Phase::Done(remaining - dt);
+                } else if self.cycle
+                    && let Some(preset) = self.preset
+                {
+                    self.load(preset.next().shape(), Some(preset.next()));
+                }
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        match self.shape {
+            Shape::Ifs(_) => {
+                let max = self.hits.buffer().iter().copied().max().unwrap_or(0);
+                if max == 0 {
+                    return;
+                }
+                // log scaling, so the sparse parts still show up
+                let scale = 1.0 / libm::logf(1.0 + max as f32);
+                let palette = self.palette;
+                let _ = target.draw_iter(self.hits.iter_with_index().filter(|(_, h)| **h > 0).map(
+                    |((x, y), h)| {
+                        let t = 0.3 + 0.7 * libm::logf(1.0 + *h as f32) * scale;
+                        Pixel(Point::new(x, y), palette.sample(t))
+                    },
+                ));
+            }
+            Shape::LSystem(_) => {
+                let _ = target.draw_iter(
+                    self.canvas
+                        .iter_with_index()
+                        .filter(|(_, colour)| **colour != Rgb888::BLACK)
+                        .map(|((x, y), colour)| Pixel(Point::new(x, y), *colour)),
+                );
+            }
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            SelfSimilarUpdate::Reset => self.reset(),
+            SelfSimilarUpdate::LoadPreset(preset) => self.load(preset.shape(), Some(preset)),
+            SelfSimilarUpdate::LoadIfs(ifs) => {
+                self.cycle = false;
+                self.load(Shape::Ifs(ifs), None);
+            }
+            SelfSimilarUpdate::LoadLSystem(mut system) => {
+                system.iterations = system.iterations.min(MAX_ITERATIONS);
+                self.cycle = false;
+                self.load(Shape::LSystem(system), None);
+            }
+            SelfSimilarUpdate::SetCycle(cycle) => self.cycle = cycle,
+            SelfSimilarUpdate::SetPointsPerSecond(points) => {
+                self.points_per_second = points.clamp(100.0, 200_000.0)
+            }
+            SelfSimilarUpdate::SetSegmentsPerSecond(segments) => {
+                self.segments_per_second = segments.clamp(1.0, 50_000.0)
+            }
+            SelfSimilarUpdate::SetPalette(palette) => self.palette = palette,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        SelfSimilar::new(SelfSimilarPreset::Fern, rng)
+    }
+
+    fn reset(&mut self) {
+        self.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(axiom: &str, rules: &[(u8, &str)], iterations: u8) -> LSystem {
+        LSystem {
+            axiom: String::try_from(axiom).unwrap(),
+            rules: rules
+                .iter()
+                .map(|(symbol, replacement)| LSystemRule {
+                    symbol: *symbol,
+                    replacement: String::try_from(*replacement).unwrap(),
+                })
+                .collect(),
+            angle: 90.0,
+            iterations,
+        }
+    }
+
+    fn expand(system: &LSystem) -> String<256> {
+        let mut expansion = Expansion::new();
+        let mut text = Vec::<u8, 256>::new();
+        while let Some(symbol) = expansion.next(system) {
+            text.push(symbol).unwrap();
+        }
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn rules_are_applied_each_iteration() {
+        let koch = system("F", &[(b'F', "F+F")], 2);
+        assert_eq!(expand(&koch), "F+F+F+F");
+        let algae = system("A", &[(b'A', "AB"), (b'B', "A")], 4);
+        assert_eq!(expand(&algae), "ABAABABA");
+    }
+
+    #[test]
+    fn rules_match_whole_bytes() {
+        let system = system("Fé", &[(b'X', "F"), (b'F', "FF")], 1);
+        assert_eq!(system.rule_for(b'F'), Some(1));
+        assert_eq!(system.rule_for(b'f'), None);
+        // none of the bytes of a multibyte character match a rule
+        assert!("é".bytes().all(|byte| system.rule_for(byte).is_none()));
+    }
+
+    #[test]
+    fn non_ascii_text_passes_through() {
+        let system = system("Fé", &[(b'F', "F+")], 2);
+        assert_eq!(expand(&system), "F++é");
+    }
+}
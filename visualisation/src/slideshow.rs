@@ -0,0 +1,461 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+use heapless::Vec;
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    image::{HEADER_LEN, Image},
+    palette::lerp,
+    text::draw_text_centred,
+};
+
+/// Bytes of image data carried by each upload chunk, small enough to fit in one
+/// bluetooth packet
+pub const IMAGE_CHUNK_LEN: usize = 128;
+/// Bytes to keep the encoded images in. Enough for four 64x32 RGB888 images, or
+/// plenty of indexed ones.
+const STORE_LEN: usize = 4 * (HEADER_LEN + 64 * 32 * 3);
+const MAX_IMAGES: usize = 16;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Transition {
+    /// Switch straight to the next image
+    Cut,
+    /// Blend from one image to the next
+    Fade,
+    /// Draw the next image over the last one from left to right
+    Wipe,
+    /// Push the last image off to the left
+    Slide,
+    /// Change the pixels over one at a time in a scattered order
+    Dissolve,
+}
+
+/// Where an image is in the store
+#[derive(Copy, Clone)]
+struct Stored {
+    start: usize,
+    len: usize,
+}
+
+/// A slideshow of images uploaded from the phone in the [crate::image] format.
+///
+/// The images are uploaded in chunks and kept back to back in a fixed store, with an
+/// upload in progress written into the space after them. Images smaller than the
+/// panel are centred. They're only kept while the slideshow is running.
+pub struct Slideshow<const W: usize, const H: usize> {
+    store: [u8; STORE_LEN],
+    images: Vec<Stored, MAX_IMAGES>,
+    /// the length of the image being uploaded, which goes at the end of the store
+    upload: Option<usize>,
+    current: usize,
+    /// seconds the current image has been shown for, including the transition out
+    shown_for: f32,
+    /// seconds each image is shown for before the transition
+    dwell: f32,
+    transition: Transition,
+    transition_time: f32,
+}
+
+impl<const W: usize, const H: usize> Slideshow<W, H> {
+    pub fn new(dwell: f32, transition: Transition) -> Self {
+        Slideshow {
+            store: [0; STORE_LEN],
+            images: Vec::new(),
+            upload: None,
+            current: 0,
+            shown_for: 0.0,
+            dwell,
+            transition,
+            transition_time: 1.0,
+        }
+    }
+
+    /// The first byte after the stored images
+    fn end_of_images(&self) -> usize {
+        self.images
+            .last()
+            .map_or(0, |image| image.start + image.len)
+    }
+
+    fn begin_upload(&mut self, len: usize) {
+        let fits =
+            len >= HEADER_LEN && self.end_of_images() + len <= STORE_LEN && !self.images.is_full();
+        self.upload = fits.then_some(len);
+    }
+
+    fn upload_chunk(&mut self, offset: usize, data: &[u8]) {
+        if let Some(len) = self.upload
+            && offset + data.len() <= len
+        {
+            let start = self.end_of_images() + offset;
+            self.store[start..start + data.len()].copy_from_slice(data);
+        }
+    }
+
+    fn finish_upload(&mut self) {
+        if let Some(len) = self.upload.take() {
+            let start = self.end_of_images();
+            if Image::decode(&self.store[start..start + len]).is_ok() {
+                let _ = self.images.push(Stored { start, len });
+            }
+        }
+    }
+
+    fn delete(&mut self, index: usize) {
+        if index >= self.images.len() {
+            return;
+        }
+        // the upload in progress goes at the end, so it can't be kept
+        self.upload = None;
+        let end = self.end_of_images();
+        let removed = self.images.remove(index);
+        self.store
+            .copy_within(removed.start + removed.len..end, removed.start);
+        for image in self.images[index..].iter_mut() {
+            image.start -= removed.len;
+        }
+        if self.current > index {
+            self.current -= 1;
+        }
+        if self.current >= self.images.len() {
+            self.current = 0;
+        }
+        self.shown_for = 0.0;
+    }
+
+    fn image(&self, index: usize) -> Option<Image<'_>> {
+        let stored = self.images.get(index)?;
+        Image::parse(&self.store[stored.start..stored.start + stored.len]).ok()
+    }
+
+    /// The colour of an image at a position on the panel, with the image centred
+    fn colour_at(image: &Option<Image>, x: i32, y: i32) -> Rgb888 {
+        let black = Rgb888::new(0, 0, 0);
+        let Some(image) = image else {
+            return black;
+        };
+        let header = image.header();
+        let left = (W as i32 - header.width as i32) / 2;
+        let top = (H as i32 - header.height as i32) / 2;
+        image.pixel(x - left, y - top).unwrap_or(black)
+    }
+
+    /// How far through the transition to the next image, from 0 to 1, or None
+    /// if it's not transitioning
+    fn transition_progress(&self) -> Option<f32> {
+        if self.images.len() < 2 || self.transition == Transition::Cut {
+            return None;
+        }
+        let progress = (self.shown_for - self.dwell) / self.transition_time;
+        (progress > 0.0).then_some(progress.min(1.0))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SlideshowUpdate {
+    Reset,
+    /// Start uploading an image, which is `len` bytes in the [crate::image] format.
+    /// Ignored if there isn't room for it, and drops any unfinished upload.
+    BeginUpload {
+        len: u16,
+    },
+    /// Part of the image being uploaded, starting `offset` bytes in
+    UploadChunk {
+        offset: u16,
+        data: Vec<u8, IMAGE_CHUNK_LEN>,
+    },
+    /// Add the uploaded image to the end of the slideshow, if it checks out
+    FinishUpload,
+    /// Remove an image, by its position in the slideshow
+    DeleteImage(u8),
+    ClearImages,
+    /// Seconds each image is shown for, not counting the transition
+    SetDwell(f32),
+    SetTransition(Transition),
+    /// Seconds each transition takes
+    SetTransitionTime(f32),
+}
+
+impl StateUpdate for SlideshowUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Slideshow<W, H> {
+    type StateUpdate = SlideshowUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        if self.images.len() < 2 {
+            return true;
+        }
+        self.shown_for += (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        let total = match self.transition {
+            Transition::Cut => self.dwell,
+            _ => self.dwell + self.transition_time,
+        };
+        if self.shown_for >= total {
+            self.shown_for = 0.0;
+            self.current = (self.current + 1) % self.images.len();
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        if self.images.is_empty() {
+            draw_text_centred(
+                target,
+                "NO IMAGES",
+                Point::new(W as i32 / 2, H as i32 / 2 - 3),
+                Rgb888::new(80, 80, 80),
+            );
+            return;
+        }
+
+        let from = self.image(self.current);
+        let coords = (0..H as i32).flat_map(|y| (0..W as i32).map(move |x| (x, y)));
+        let Some(progress) = self.transition_progress() else {
+            let _ = target.draw_iter(
+                coords.map(|(x, y)| Pixel(Point::new(x, y), Self::colour_at(&from, x, y))),
+            );
+            return;
+        };
+
+        let to = self.image((self.current + 1) % self.images.len());
+        let w = W as i32;
+        let _ = target.draw_iter(coords.map(|(x, y)| {
+            let colour = match self.transition {
+                Transition::Cut => Self::colour_at(&from, x, y),
+                Transition::Fade => lerp(
+                    Self::colour_at(&from, x, y),
+                    Self::colour_at(&to, x, y),
+                    progress,
+                ),
+                Transition::Wipe => {
+                    if (x as f32) < progress * w as f32 {
+                        Self::colour_at(&to, x, y)
+                    } else {
+                        Self::colour_at(&from, x, y)
+                    }
+                }
+                Transition::Slide => {
+                    let shift = libm::roundf(progress * w as f32) as i32;
+                    if x + shift < w {
+                        Self::colour_at(&from, x + shift, y)
+                    } else {
+                        Self::colour_at(&to, x + shift - w, y)
+                    }
+                }
+                Transition::Dissolve => {
+                    // a fixed scramble of the pixel's position decides when it changes
+                    let hash =
+                        (x as u32).wrapping_mul(0x9E37_79B1) ^ (y as u32).wrapping_mul(0x85EB_CA77);
+                    let hash = hash ^ (hash >> 15);
+                    if (hash & 0xff) as f32 / 256.0 < progress {
+                        Self::colour_at(&to, x, y)
+                    } else {
+                        Self::colour_at(&from, x, y)
+                    }
+                }
+            };
+            Pixel(Point::new(x, y), colour)
+        }));
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            SlideshowUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            SlideshowUpdate::BeginUpload { len } => self.begin_upload(len as usize),
+            SlideshowUpdate::UploadChunk { offset, data } => {
+                self.upload_chunk(offset as usize, &data)
+            }
+            SlideshowUpdate::FinishUpload => self.finish_upload(),
+            SlideshowUpdate::DeleteImage(index) => self.delete(index as usize),
+            SlideshowUpdate::ClearImages => {
+                self.images.clear();
+                self.upload = None;
+                self.current = 0;
+                self.shown_for = 0.0;
+            }
+            SlideshowUpdate::SetDwell(dwell) => self.dwell = dwell.clamp(0.5, 3600.0),
+            SlideshowUpdate::SetTransition(transition) => self.transition = transition,
+            SlideshowUpdate::SetTransitionTime(time) => {
+                self.transition_time = time.clamp(0.05, 10.0)
+            }
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Slideshow::new(5.0, Transition::Fade)
+    }
+
+    fn reset(&mut self) {
+        self.current = 0;
+        self.shown_for = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{PixelFormat, encode};
+    use crate::xorshift::XorShift;
+    use embedded_graphics::prelude::RgbColor;
+
+    type TestSlideshow = Slideshow<64, 32>;
+
+    /// A 64x32 RGB888 image all of one colour
+    const FULL_LEN: usize = HEADER_LEN + 64 * 32 * 3;
+
+    fn send(slideshow: &mut TestSlideshow, update: SlideshowUpdate) {
+        <TestSlideshow as Visualisation<XorShift>>::run_state_update(slideshow, update);
+    }
+
+    fn full_image(shade: u8) -> [u8; FULL_LEN] {
+        let mut bytes = [0; FULL_LEN];
+        let pixels = [shade; 64 * 32 * 3];
+        encode(PixelFormat::Rgb888, 64, 32, &[], &pixels, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn small_image(shade: u8) -> [u8; HEADER_LEN + 3] {
+        let mut bytes = [0; HEADER_LEN + 3];
+        encode(PixelFormat::Rgb888, 1, 1, &[], &[shade; 3], &mut bytes).unwrap();
+        bytes
+    }
+
+    fn upload(slideshow: &mut TestSlideshow, bytes: &[u8]) {
+        send(
+            slideshow,
+            SlideshowUpdate::BeginUpload {
+                len: bytes.len() as u16,
+            },
+        );
+        for (i, chunk) in bytes.chunks(IMAGE_CHUNK_LEN).enumerate() {
+            send(
+                slideshow,
+                SlideshowUpdate::UploadChunk {
+                    offset: (i * IMAGE_CHUNK_LEN) as u16,
+                    data: Vec::from_slice(chunk).unwrap(),
+                },
+            );
+        }
+        send(slideshow, SlideshowUpdate::FinishUpload);
+    }
+
+    fn shade(slideshow: &TestSlideshow, index: usize) -> Option<u8> {
+        let image = slideshow.image(index)?;
+        let header = image.header();
+        let centre = (header.width as i32 / 2, header.height as i32 / 2);
+        image.pixel(centre.0, centre.1).map(|colour| colour.r())
+    }
+
+    #[test]
+    fn uploads_images_in_chunks() {
+        let mut slideshow = TestSlideshow::new(5.0, Transition::Cut);
+        upload(&mut slideshow, &full_image(7));
+        upload(&mut slideshow, &small_image(9));
+        assert_eq!(slideshow.images.len(), 2);
+        assert_eq!(shade(&slideshow, 0), Some(7));
+        assert_eq!(shade(&slideshow, 1), Some(9));
+
+        slideshow.delete(0);
+        assert_eq!(slideshow.images.len(), 1);
+        assert_eq!(shade(&slideshow, 0), Some(9));
+    }
+
+    #[test]
+    fn ignores_chunks_outside_the_upload() {
+        let mut slideshow = TestSlideshow::new(5.0, Transition::Cut);
+        upload(&mut slideshow, &small_image(1));
+        let image = small_image(2);
+        let end = slideshow.end_of_images();
+        send(
+            &mut slideshow,
+            SlideshowUpdate::BeginUpload {
+                len: image.len() as u16,
+            },
+        );
+        let junk: Vec<u8, IMAGE_CHUNK_LEN> = Vec::from_slice(&[0xaa; IMAGE_CHUNK_LEN]).unwrap();
+        for offset in [image.len() as u16, 1, u16::MAX - 10, u16::MAX] {
+            send(
+                &mut slideshow,
+                SlideshowUpdate::UploadChunk {
+                    offset,
+                    data: junk.clone(),
+                },
+            );
+        }
+        assert!(slideshow.store[end..].iter().all(|byte| *byte == 0));
+        send(
+            &mut slideshow,
+            SlideshowUpdate::UploadChunk {
+                offset: 0,
+                data: Vec::from_slice(&image).unwrap(),
+            },
+        );
+        send(&mut slideshow, SlideshowUpdate::FinishUpload);
+        assert_eq!(slideshow.images.len(), 2);
+        assert_eq!(shade(&slideshow, 0), Some(1));
+        assert_eq!(shade(&slideshow, 1), Some(2));
+    }
+
+    #[test]
+    fn ignores_uploads_that_are_too_big_or_too_small() {
+        let mut slideshow = TestSlideshow::new(5.0, Transition::Cut);
+        for len in [u16::MAX, STORE_LEN as u16 + 1, HEADER_LEN as u16 - 1, 0] {
+            send(&mut slideshow, SlideshowUpdate::BeginUpload { len });
+            assert!(slideshow.upload.is_none());
+            send(
+                &mut slideshow,
+                SlideshowUpdate::UploadChunk {
+                    offset: 0,
+                    data: Vec::from_slice(&[0xaa; IMAGE_CHUNK_LEN]).unwrap(),
+                },
+            );
+            send(&mut slideshow, SlideshowUpdate::FinishUpload);
+        }
+        assert!(slideshow.images.is_empty());
+        assert!(slideshow.store.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn ignores_uploads_once_the_store_is_full() {
+        let mut slideshow = TestSlideshow::new(5.0, Transition::Cut);
+        for shade in 0..4 {
+            upload(&mut slideshow, &full_image(shade));
+        }
+        assert_eq!(slideshow.images.len(), 4);
+        assert_eq!(slideshow.end_of_images(), STORE_LEN);
+        upload(&mut slideshow, &small_image(9));
+        assert_eq!(slideshow.images.len(), 4);
+        assert_eq!(shade(&slideshow, 3), Some(3));
+
+        // and once there are as many images as it can keep track of
+        send(&mut slideshow, SlideshowUpdate::ClearImages);
+        for shade in 0..MAX_IMAGES as u8 + 1 {
+            upload(&mut slideshow, &small_image(shade));
+        }
+        assert_eq!(slideshow.images.len(), MAX_IMAGES);
+        assert_eq!(
+            shade(&slideshow, MAX_IMAGES - 1),
+            Some(MAX_IMAGES as u8 - 1)
+        );
+    }
+
+    #[test]
+    fn ignores_uploads_that_dont_check_out() {
+        let mut slideshow = TestSlideshow::new(5.0, Transition::Cut);
+        let mut image = small_image(3);
+        image[HEADER_LEN] ^= 1;
+        upload(&mut slideshow, &image);
+        assert!(slideshow.images.is_empty());
+        // finishing without starting does nothing
+        send(&mut slideshow, SlideshowUpdate::FinishUpload);
+        assert!(slideshow.images.is_empty());
+    }
+}
@@ -0,0 +1,641 @@
+use core::fmt::Write;
+
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{
+    Input, RngU32, StateUpdate, Visualisation,
+    queue::Queue,
+    text::{TextBuffer, draw_text_centred},
+};
+
+/// The size of a board cell in pixels
+const CELL: i32 = 2;
+/// The most cells a board can have, enough for a 64x32 display
+const MAX_CELLS: usize = 512;
+/// How long the score is shown for before going back to the demo, in seconds
+const GAME_OVER_TIME: f32 = 6.0;
+/// How long input is ignored for after dying, so the score isn't skipped by accident
+const GAME_OVER_INPUT_DELAY: f32 = 1.0;
+/// How many cells the snake grows by for each bit of food
+const GROWTH: u8 = 2;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    fn from_input(input: Input) -> Option<Self> {
+        match input {
+            Input::Up => Some(Direction::Up),
+            Input::Down => Some(Direction::Down),
+            Input::Left => Some(Direction::Left),
+            Input::Right => Some(Direction::Right),
+            Input::A | Input::B => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    /// The computer plays until someone presses a button
+    Attract,
+    Playing,
+    /// Showing the score, with the seconds left before going back to attract mode
+    GameOver(f32),
+}
+
+/// Snake, played with the controller input. When nobody is playing the snake
+/// steers itself as an attract mode.
+///
+/// The game only changes state in `update` and `input`, so given the same rng
+/// and the same inputs it plays out the same way.
+pub struct Snake<Rng, const W: usize, const H: usize> {
+    /// the cells of the snake, with the tail at the front and the head at the back
+    body: Queue<(u8, u8), MAX_CELLS>,
+    /// which cells are covered by the snake, indexed by `y * BOARD_W + x`
+    occupied: [bool; MAX_CELLS],
+    direction: Direction,
+    /// turns that have been pressed but not made yet, so quick presses aren't lost
+    turns: Queue<Direction, 4>,
+    food: (u8, u8),
+    /// how many more cells the snake will grow by
+    growing: u8,
+    score: u32,
+    high_score: u32,
+    mode: Mode,
+    /// seconds between each move of the snake
+    step_time: f32,
+    time_banked: f32,
+    /// a clock used to flash text
+    time: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Snake<Rng, W, H> {
+    /// The board leaves a one pixel border around the edge of the display
+    const BOARD_W: i32 = (W as i32 - 2) / CELL;
+    const BOARD_H: i32 = (H as i32 - 2) / CELL;
+    const OFFSET_X: i32 = (W as i32 - Self::BOARD_W * CELL) / 2;
+    const OFFSET_Y: i32 = (H as i32 - Self::BOARD_H * CELL) / 2;
+    const START_STEP_TIME: f32 = 0.14;
+    const MIN_STEP_TIME: f32 = 0.06;
+    const ATTRACT_STEP_TIME: f32 = 0.07;
+
+    pub fn new(rng: Rng) -> Self {
+        let mut this = Snake {
+            body: Queue::new(),
+            occupied: [false; MAX_CELLS],
+            direction: Direction::Right,
+            turns: Queue::new(),
+            food: (0, 0),
+            growing: 0,
+            score: 0,
+            high_score: 0,
+            mode: Mode::Attract,
+            step_time: Self::ATTRACT_STEP_TIME,
+            time_banked: 0.0,
+            time: 0.0,
+            rng,
+        };
+        this.new_game(Mode::Attract);
+        this
+    }
+
+    fn index(x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < Self::BOARD_W && y < Self::BOARD_H {
+            Some((y * Self::BOARD_W + x) as usize).filter(|&i| i < MAX_CELLS)
+        } else {
+            None
+        }
+    }
+
+    fn new_game(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.body.clear();
+        self.turns.clear();
+        self.occupied = [false; MAX_CELLS];
+        self.direction = Direction::Right;
+        self.growing = 0;
+        self.score = 0;
+        self.time_banked = 0.0;
+        self.step_time = if mode == Mode::Playing {
+            Self::START_STEP_TIME
+        } else {
+            Self::ATTRACT_STEP_TIME
+        };
+        let y = Self::BOARD_H / 2;
+        for x in 2..5 {
+            self.grow_head(x, y);
+        }
+        self.place_food();
+    }
+
+    fn grow_head(&mut self, x: i32, y: i32) {
+        self.body.push((x as u8, y as u8));
+        if let Some(i) = Self::index(x, y) {
+            self.occupied[i] = true;
+        }
+    }
+
+    /// Put the food on a random empty cell, returns false if the board is full
+    fn place_food(&mut self) -> bool {
+        let cells = (Self::BOARD_W * Self::BOARD_H) as u32;
+        // random guesses are quick while the board is mostly empty
+        for _ in 0..32 {
+            let i = self.rng.next_u32() % cells;
+            if !self.occupied[i as usize] {
+                self.food = (
+                    (i as i32 % Self::BOARD_W) as u8,
+                    (i as i32 / Self::BOARD_W) as u8,
+                );
+                return true;
+            }
+        }
+        let start = self.rng.next_u32() % cells;
+        for offset in 0..cells {
+            let i = (start + offset) % cells;
+            if !self.occupied[i as usize] {
+                self.food = (
+                    (i as i32 % Self::BOARD_W) as u8,
+                    (i as i32 / Self::BOARD_W) as u8,
+                );
+                return true;
+            }
+        }
+        false
+    }
+
+    fn head(&self) -> (i32, i32) {
+        let (x, y) = self.body.last().unwrap_or((0, 0));
+        (x as i32, y as i32)
+    }
+
+    /// Whether the snake can move into a cell this step. The end of the tail
+    /// moves out of the way unless the snake is growing.
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        match Self::index(x, y) {
+            Some(i) => {
+                !self.occupied[i]
+                    || (self.growing == 0 && self.body.get(0) == Some((x as u8, y as u8)))
+            }
+            None => false,
+        }
+    }
+
+    /// Count the free cells reachable from a cell, stopping once `enough` are found
+    fn reachable_area(&self, (x, y): (i32, i32), enough: usize) -> usize {
+        let mut seen = self.occupied;
+        let mut stack: Queue<(u8, u8), MAX_CELLS> = Queue::new();
+        let Some(i) = Self::index(x, y) else {
+            return 0;
+        };
+        seen[i] = true;
+        stack.push((x as u8, y as u8));
+        let mut area = 0;
+        while let Some((x, y)) = stack.pull() {
+            area += 1;
+            if area >= enough {
+                break;
+            }
+            for direction in Direction::ALL {
+                let (dx, dy) = direction.offset();
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if let Some(i) = Self::index(nx, ny)
+                    && !seen[i]
+                {
+                    seen[i] = true;
+                    stack.push((nx as u8, ny as u8));
+                }
+            }
+        }
+        area
+    }
+
+    /// Pick a direction for attract mode: head for the food, but don't go
+    /// anywhere that doesn't leave enough room for the snake
+    fn choose_direction(&self) -> Direction {
+        let (hx, hy) = self.head();
+        let needed = self.body.len() + self.growing as usize;
+        let mut best = self.direction;
+        // (is roomy, area, distance to food) for the best move so far
+        let mut best_score: Option<(bool, usize, i32)> = None;
+        for direction in Direction::ALL {
+            if direction == self.direction.opposite() {
+                continue;
+            }
+            let (dx, dy) = direction.offset();
+            let next = (hx + dx, hy + dy);
+            if !self.is_free(next.0, next.1) {
+                continue;
+            }
+            let area = self.reachable_area(next, needed);
+            let distance =
+                (next.0 - self.food.0 as i32).abs() + (next.1 - self.food.1 as i32).abs();
+            let score = (area >= needed, area, distance);
+            let better = match best_score {
+                None => true,
+                Some((roomy, best_area, best_distance)) => {
+                    if score.0 != roomy {
+                        score.0
+                    } else if score.0 {
+                        distance < best_distance
+                    } else {
+                        area > best_area
+                    }
+                }
+            };
+            if better {
+                best = direction;
+                best_score = Some(score);
+            }
+        }
+        best
+    }
+
+    /// Move the snake one cell, returns false if it crashed
+    fn step(&mut self) -> bool {
+        if self.mode == Mode::Attract {
+            self.direction = self.choose_direction();
+        } else if let Some(turn) = self.turns.pull_front() {
+            self.direction = turn;
+        }
+
+        let (hx, hy) = self.head();
+        let (dx, dy) = self.direction.offset();
+        let (nx, ny) = (hx + dx, hy + dy);
+        if !self.is_free(nx, ny) {
+            return false;
+        }
+        if self.growing > 0 {
+            self.growing -= 1;
+        } else if let Some((tx, ty)) = self.body.pull_front()
+            && let Some(i) = Self::index(tx as i32, ty as i32)
+        {
+            self.occupied[i] = false;
+        }
+        self.grow_head(nx, ny);
+
+        if (nx as u8, ny as u8) == self.food {
+            self.score += 1;
+            self.growing += GROWTH;
+            if self.mode == Mode::Playing {
+                self.step_time = (self.step_time - 0.004).max(Self::MIN_STEP_TIME);
+            }
+            // no room left for food means the board is full, which is as good as it gets
+            return self.place_food();
+        }
+        true
+    }
+
+    fn game_over(&mut self) {
+        match self.mode {
+            Mode::Playing => {
+                self.high_score = self.high_score.max(self.score);
+                self.mode = Mode::GameOver(GAME_OVER_TIME);
+            }
+            // the demo just starts again
+            _ => self.new_game(Mode::Attract),
+        }
+    }
+
+    fn cell_pixels(x: i32, y: i32, colour: Rgb888) -> impl Iterator<Item = Pixel<Rgb888>> {
+        let (px, py) = (Self::OFFSET_X + x * CELL, Self::OFFSET_Y + y * CELL);
+        (0..CELL * CELL).map(move |i| Pixel(Point::new(px + i % CELL, py + i / CELL), colour))
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SnakeUpdate {
+    /// Go back to the demo
+    Reset,
+    ResetHighScore,
+}
+
+impl StateUpdate for SnakeUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Snake<Rng, W, H> {
+    type StateUpdate = SnakeUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.25);
+        self.time = (self.time + dt) % 60.0;
+        if let Mode::GameOver(remaining) = self.mode {
+            if remaining > dt {
+                self.mode = Mode::GameOver(remaining - dt);
+            } else {
+                self.new_game(Mode::Attract);
+            }
+            return true;
+        }
+        self.time_banked += dt;
+        while self.time_banked >= self.step_time {
+            self.time_banked -= self.step_time;
+            if !self.step() {
+                self.game_over();
+                break;
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        if let Mode::GameOver(_) = self.mode {
+            let mut text: TextBuffer<16> = TextBuffer::new();
+            draw_text_centred(
+                target,
+                "GAME OVER",
+                Point::new(W as i32 / 2, 3),
+                Rgb888::new(255, 40, 40),
+            );
+            let _ = write!(text, "SCORE {}", self.score);
+            draw_text_centred(
+                target,
+                text.as_str(),
+                Point::new(W as i32 / 2, 13),
+                Rgb888::new(255, 255, 255),
+            );
+            let mut text: TextBuffer<16> = TextBuffer::new();
+            let _ = write!(text, "BEST {}", self.high_score);
+            draw_text_centred(
+                target,
+                text.as_str(),
+                Point::new(W as i32 / 2, 22),
+                Rgb888::new(255, 200, 40),
+            );
+            return;
+        }
+
+        // the walls
+        let border = Rgb888::new(20, 20, 90);
+        let (right, bottom) = (
+            Self::OFFSET_X + Self::BOARD_W * CELL,
+            Self::OFFSET_Y + Self::BOARD_H * CELL,
+        );
+        let _ = target.draw_iter(
+            (0..W as i32)
+                .flat_map(|x| [Point::new(x, Self::OFFSET_Y - 1), Point::new(x, bottom)])
+                .chain(
+                    (0..H as i32)
+                        .flat_map(|y| [Point::new(Self::OFFSET_X - 1, y), Point::new(right, y)]),
+                )
+                .map(|p| Pixel(p, border)),
+        );
+
+        let length = self.body.len();
+        for (i, (x, y)) in self.body.iter().enumerate() {
+            // fade from the tail up to a bright head
+            let t = ((i + 1) * 160 / length.max(1)) as u8;
+            let colour = if i + 1 == length {
+                Rgb888::new(180, 255, 120)
+            } else {
+                Rgb888::new(0, 60 + t, 30)
+            };
+            let _ = target.draw_iter(Self::cell_pixels(x as i32, y as i32, colour));
+        }
+        let _ = target.draw_iter(Self::cell_pixels(
+            self.food.0 as i32,
+            self.food.1 as i32,
+            Rgb888::new(255, 30, 30),
+        ));
+
+        if self.mode == Mode::Attract && self.time % 2.0 < 1.4 {
+            draw_text_centred(
+                target,
+                "SNAKE",
+                Point::new(W as i32 / 2, 6),
+                Rgb888::new(255, 255, 255),
+            );
+            draw_text_centred(
+                target,
+                "PRESS A BUTTON",
+                Point::new(W as i32 / 2, 20),
+                Rgb888::new(255, 200, 40),
+            );
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            SnakeUpdate::Reset => self.reset(),
+            SnakeUpdate::ResetHighScore => self.high_score = 0,
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Snake::new(rng)
+    }
+
+    fn reset(&mut self) {
+        self.new_game(Mode::Attract);
+    }
+
+    fn input(&mut self, input: Input) {
+        match self.mode {
+            Mode::Attract => self.new_game(Mode::Playing),
+            Mode::GameOver(remaining) => {
+                if GAME_OVER_TIME - remaining > GAME_OVER_INPUT_DELAY {
+                    self.new_game(Mode::Playing);
+                }
+            }
+            Mode::Playing => {
+                let Some(direction) = Direction::from_input(input) else {
+                    return;
+                };
+                // check against the last turn waiting to be made, rather than the
+                // current direction, so quick double turns work
+                let last = self.turns.last().unwrap_or(self.direction);
+                if direction != last && direction != last.opposite() {
+                    self.turns.push(direction);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    type TestSnake = Snake<XorShift, 64, 32>;
+
+    /// A game that's just started, with the food out of the way
+    fn playing() -> TestSnake {
+        let mut snake = TestSnake::new(XorShift(1));
+        snake.input(Input::A);
+        snake.food = (0, 0);
+        snake
+    }
+
+    /// Run the game for `seconds`, a quarter of a second at a time
+    fn run(snake: &mut TestSnake, seconds: f32) {
+        for _ in 0..(seconds * 4.0) as u32 {
+            snake.update(250_000);
+        }
+    }
+
+    #[test]
+    fn steers_with_the_input() {
+        let mut snake = playing();
+        assert!(snake.mode == Mode::Playing);
+        assert_eq!(snake.head(), (4, 7));
+        assert!(snake.step());
+        assert_eq!(snake.head(), (5, 7));
+        snake.input(Input::Up);
+        snake.input(Input::Left);
+        assert!(snake.step());
+        assert_eq!(snake.head(), (5, 6));
+        assert!(snake.step());
+        assert_eq!(snake.head(), (4, 6));
+        // buttons don't steer
+        snake.input(Input::B);
+        assert!(snake.step());
+        assert_eq!(snake.head(), (3, 6));
+    }
+
+    #[test]
+    fn cant_reverse_onto_itself() {
+        let mut snake = playing();
+        snake.input(Input::Left);
+        assert!(snake.turns.is_empty());
+        assert!(snake.step());
+        assert_eq!(snake.head(), (5, 7));
+        // nor by reversing a turn that hasn't been made yet
+        snake.input(Input::Down);
+        snake.input(Input::Up);
+        assert_eq!(snake.turns.len(), 1);
+        assert!(snake.step());
+        assert_eq!(snake.head(), (5, 8));
+    }
+
+    #[test]
+    fn crashing_into_the_wall_ends_the_game() {
+        let mut snake = playing();
+        // from x = 4 it's 26 moves to the last column, and the next one hits the wall
+        for _ in 0..26 {
+            assert!(snake.step());
+        }
+        assert_eq!(snake.head(), (30, 7));
+        assert!(!snake.step());
+
+        let mut snake = playing();
+        run(&mut snake, 5.0);
+        assert!(matches!(snake.mode, Mode::GameOver(_)));
+        assert_eq!(snake.head(), (30, 7));
+    }
+
+    #[test]
+    fn crashing_into_itself_ends_the_game() {
+        let mut snake = playing();
+        snake.growing = 4;
+        assert!(snake.step());
+        assert!(snake.step());
+        snake.input(Input::Up);
+        assert!(snake.step());
+        snake.input(Input::Left);
+        assert!(snake.step());
+        snake.input(Input::Down);
+        assert_eq!(snake.head(), (5, 6));
+        assert!(!snake.step());
+    }
+
+    #[test]
+    fn eating_scores_and_grows() {
+        let mut snake = playing();
+        snake.food = (5, 7);
+        assert!(snake.step());
+        assert_eq!(snake.score, 1);
+        assert_ne!(snake.food, (5, 7));
+        snake.food = (0, 0);
+        for _ in 0..GROWTH {
+            snake.step();
+        }
+        assert_eq!(snake.body.len(), 3 + GROWTH as usize);
+        snake.step();
+        assert_eq!(snake.body.len(), 3 + GROWTH as usize);
+    }
+
+    #[test]
+    fn restarts_after_game_over() {
+        let mut snake = playing();
+        snake.score = 5;
+        snake.game_over();
+        assert_eq!(snake.high_score, 5);
+        // presses straight after dying don't skip the score
+        snake.input(Input::A);
+        assert!(matches!(snake.mode, Mode::GameOver(_)));
+        run(&mut snake, GAME_OVER_INPUT_DELAY + 0.25);
+        snake.input(Input::A);
+        assert!(snake.mode == Mode::Playing);
+        assert_eq!(snake.score, 0);
+        assert_eq!(snake.body.len(), 3);
+        assert_eq!(snake.high_score, 5);
+    }
+
+    #[test]
+    fn demo_takes_over_when_nobody_plays() {
+        let mut snake = playing();
+        snake.game_over();
+        run(&mut snake, GAME_OVER_TIME + 0.25);
+        assert!(snake.mode == Mode::Attract);
+
+        // the demo plays itself without crashing, at least for a while
+        run(&mut snake, 20.0);
+        assert!(snake.mode == Mode::Attract);
+        assert!(snake.score > 0);
+
+        snake.input(Input::Up);
+        assert!(snake.mode == Mode::Playing);
+        assert_eq!(snake.score, 0);
+        assert_eq!(snake.head(), (4, 7));
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let mut a = TestSnake::new(XorShift(9));
+        let mut b = TestSnake::new(XorShift(9));
+        for _ in 0..200 {
+            a.update(100_000);
+            b.update(100_000);
+        }
+        assert!(a.body.iter().eq(b.body.iter()));
+        assert_eq!((a.food, a.score), (b.food, b.score));
+    }
+}
@@ -0,0 +1,698 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation};
+
+/// The most values that can be sorted, one for each column of a 64 wide panel
+const MAX_LEN: usize = 64;
+/// The base of the digits radix sort sorts by
+const RADIX: usize = 4;
+/// How long the sorted values stay on screen, in seconds
+const SHOW_SORTED: f32 = 2.0;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SortAlgorithm {
+    Bubble,
+    Insertion,
+    /// Quicksort, partitioning around the last value
+    Quick,
+    Heap,
+    /// Bottom up merge sort, merging into a spare buffer and copying back
+    Merge,
+    /// Least significant digit first radix sort, in base 4
+    Radix,
+}
+
+/// What a step did, to highlight on the panel
+#[derive(Copy, Clone)]
+enum Op {
+    Compare(usize, usize),
+    Swap(usize, usize),
+    /// looked at a value without comparing it to anything
+    Read(usize),
+    /// wrote a value from the spare buffer
+    Write(usize),
+}
+
+#[derive(Copy, Clone)]
+struct Partition {
+    lo: usize,
+    hi: usize,
+    /// where the next value smaller than the pivot goes
+    store: usize,
+    next: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Sift {
+    root: usize,
+    end: usize,
+    /// the larger child, once it's been picked, to compare with the root
+    child: Option<usize>,
+}
+
+#[derive(Copy, Clone)]
+struct Merge {
+    lo: usize,
+    mid: usize,
+    hi: usize,
+    left: usize,
+    right: usize,
+    /// the next place in the spare buffer, or in the values once it's writing back
+    out: usize,
+    writing: bool,
+}
+
+/// Where each algorithm is up to. The quicksort stack and the spare buffer for merge
+/// and radix sort are kept in [Sorting].
+#[derive(Copy, Clone)]
+enum Sorter {
+    Bubble {
+        /// everything from here on is sorted
+        end: usize,
+        next: usize,
+        swapped: bool,
+    },
+    Insertion {
+        /// the value being moved down into place
+        i: usize,
+        j: usize,
+    },
+    Quick {
+        partition: Option<Partition>,
+        /// the number of ranges on the stack
+        depth: usize,
+    },
+    Heap {
+        building: bool,
+        /// the next root to sift while building, or the end of the heap after
+        next: usize,
+        sift: Option<Sift>,
+    },
+    Merge {
+        width: usize,
+        /// the start of the next pair of runs to merge
+        lo: usize,
+        merge: Option<Merge>,
+    },
+    Radix {
+        /// the place value of the current digit
+        place: usize,
+        next: usize,
+        writing: bool,
+    },
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    Sorting,
+    /// running over the sorted values to show they're in order, up to this index
+    Checking(usize),
+    /// showing the sorted values before starting again, with the seconds left
+    Finished(f32),
+}
+
+/// Animates sorting algorithms on a shuffled row of columns. Each step is a single
+/// compare, swap, read or write, and is highlighted on the panel.
+pub struct Sorting<Rng, const W: usize, const H: usize> {
+    values: [u8; MAX_LEN],
+    len: usize,
+    algorithm: SortAlgorithm,
+    sorter: Sorter,
+    /// a swap found by the last compare, which is done as the next step
+    pending_swap: Option<(usize, usize)>,
+    /// ranges left to partition for quicksort
+    stack: [(u8, u8); MAX_LEN],
+    /// the spare buffer for merge and radix sort
+    spare: [u8; MAX_LEN],
+    last_op: Option<Op>,
+    phase: Phase,
+    /// move on to the next algorithm after each sort
+    cycle: bool,
+    steps_per_second: f32,
+    time_banked: f32,
+    rng: Rng,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Sorting<Rng, W, H> {
+    pub fn new(algorithm: SortAlgorithm, len: usize, rng: Rng) -> Self {
+        let mut this = Sorting {
+            values: [0; MAX_LEN],
+            len: len.clamp(2, MAX_LEN.min(W)),
+            algorithm,
+            sorter: Sorter::Bubble {
+                end: 0,
+                next: 0,
+                swapped: false,
+            },
+            pending_swap: None,
+            stack: [(0, 0); MAX_LEN],
+            spare: [0; MAX_LEN],
+            last_op: None,
+            phase: Phase::Sorting,
+            cycle: true,
+            steps_per_second: 60.0,
+            time_banked: 0.0,
+            rng,
+        };
+        this.start();
+        this
+    }
+
+    /// Shuffle the values and start sorting them from the beginning
+    fn start(&mut self) {
+        for (i, value) in self.values[..self.len].iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        // Fisher-Yates
+        for i in (1..self.len).rev() {
+            let j = (self.rng.next_u32() % (i as u32 + 1)) as usize;
+            self.values.swap(i, j);
+        }
+        let n = self.len;
+        self.sorter = match self.algorithm {
+            SortAlgorithm::Bubble => Sorter::Bubble {
+                end: n,
+                next: 0,
+                swapped: false,
+            },
+            SortAlgorithm::Insertion => Sorter::Insertion { i: 1, j: 1 },
+            SortAlgorithm::Quick => {
+                self.stack[0] = (0, n as u8 - 1);
+                Sorter::Quick {
+                    partition: None,
+                    depth: 1,
+                }
+            }
+            SortAlgorithm::Heap => Sorter::Heap {
+                building: true,
+                next: n / 2,
+                sift: None,
+            },
+            SortAlgorithm::Merge => Sorter::Merge {
+                width: 1,
+                lo: 0,
+                merge: None,
+            },
+            SortAlgorithm::Radix => Sorter::Radix {
+                place: 1,
+                next: 0,
+                writing: false,
+            },
+        };
+        self.pending_swap = None;
+        self.last_op = None;
+        self.phase = Phase::Sorting;
+    }
+
+    fn next_algorithm(&mut self) {
+        self.algorithm = match self.algorithm {
+            SortAlgorithm::Bubble => SortAlgorithm::Insertion,
+            SortAlgorithm::Insertion => SortAlgorithm::Quick,
+            SortAlgorithm::Quick => SortAlgorithm::Heap,
+            SortAlgorithm::Heap => SortAlgorithm::Merge,
+            SortAlgorithm::Merge => SortAlgorithm::Radix,
+            SortAlgorithm::Radix => SortAlgorithm::Bubble,
+        };
+    }
+
+    /// Run the sort on until it does one compare, swap, read or write, returning
+    /// what it did, or None once the values are sorted
+    fn sort_step(&mut self) -> Option<Op> {
+        if let Some((a, b)) = self.pending_swap.take() {
+            self.values.swap(a, b);
+            return Some(Op::Swap(a, b));
+        }
+        let Sorting {
+            values,
+            len,
+            sorter,
+            pending_swap,
+            stack,
+            spare,
+            ..
+        } = self;
+        let n = *len;
+        match sorter {
+            Sorter::Bubble { end, next, swapped } => loop {
+                if *next + 1 < *end {
+                    let (a, b) = (*next, *next + 1);
+                    *next += 1;
+                    if values[a] > values[b] {
+                        *pending_swap = Some((a, b));
+                        *swapped = true;
+                    }
+                    return Some(Op::Compare(a, b));
+                }
+                // stop early if a pass didn't have to swap anything
+                if !*swapped || *end <= 2 {
+                    return None;
+                }
+                *end -= 1;
+                *next = 0;
+                *swapped = false;
+            },
+            Sorter::Insertion { i, j } => {
+                if *j == 0 {
+                    *i += 1;
+                    *j = *i;
+                }
+                if *i >= n {
+                    return None;
+                }
+                let (a, b) = (*j - 1, *j);
+                if values[a] > values[b] {
+                    *pending_swap = Some((a, b));
+                    *j -= 1;
+                } else {
+                    *i += 1;
+                    *j = *i;
+                }
+                Some(Op::Compare(a, b))
+            }
+            Sorter::Quick { partition, depth } => loop {
+                if let Some(p) = partition {
+                    if p.next < p.hi {
+                        let next = p.next;
+                        p.next += 1;
+                        if values[next] < values[p.hi] {
+                            if p.store != next {
+                                *pending_swap = Some((p.store, next));
+                            }
+                            p.store += 1;
+                        }
+                        return Some(Op::Compare(next, p.hi));
+                    }
+                    let (lo, hi, pivot) = (p.lo, p.hi, p.store);
+                    *partition = None;
+                    // push the bigger side first, so the smaller one is done first
+                    // and the stack stays short
+                    let mut sides = [(lo, pivot.saturating_sub(1)), (pivot + 1, hi)];
+                    if sides[0].1.saturating_sub(sides[0].0) < sides[1].1.saturating_sub(sides[1].0)
+                    {
+                        sides.swap(0, 1);
+                    }
+                    for (lo, hi) in sides {
+                        if lo < hi && hi < n {
+                            stack[*depth] = (lo as u8, hi as u8);
+                            *depth += 1;
+                        }
+                    }
+                    if pivot != hi {
+                        values.swap(pivot, hi);
+                        return Some(Op::Swap(pivot, hi));
+                    }
+                }
+                if *depth == 0 {
+                    return None;
+                }
+                *depth -= 1;
+                let (lo, hi) = stack[*depth];
+                let (lo, hi) = (lo as usize, hi as usize);
+                *partition = Some(Partition {
+                    lo,
+                    hi,
+                    store: lo,
+                    next: lo,
+                });
+            },
+            Sorter::Heap {
+                building,
+                next,
+                sift,
+            } => loop {
+                if let Some(s) = sift {
+                    match s.child {
+                        None => {
+                            let left = 2 * s.root + 1;
+                            if left >= s.end {
+                                *sift = None;
+                                continue;
+                            }
+                            if left + 1 < s.end {
+                                let bigger = if values[left + 1] > values[left] {
+                                    left + 1
+                                } else {
+                                    left
+                                };
+                                s.child = Some(bigger);
+                                return Some(Op::Compare(left, left + 1));
+                            }
+                            s.child = Some(left);
+                        }
+                        Some(child) => {
+                            let root = s.root;
+                            s.child = None;
+                            if values[child] > values[root] {
+                                *pending_swap = Some((root, child));
+                                s.root = child;
+                            } else {
+                                *sift = None;
+                            }
+                            return Some(Op::Compare(root, child));
+                        }
+                    }
+                    continue;
+                }
+                if *building {
+                    if *next == 0 {
+                        *building = false;
+                        *next = n;
+                        continue;
+                    }
+                    *next -= 1;
+                    *sift = Some(Sift {
+                        root: *next,
+                        end: n,
+                        child: None,
+                    });
+                } else {
+                    // move the biggest value to the end, then fix the heap
+                    if *next <= 1 {
+                        return None;
+                    }
+                    *next -= 1;
+                    *sift = Some(Sift {
+                        root: 0,
+                        end: *next,
+                        child: None,
+                    });
+                    values.swap(0, *next);
+                    return Some(Op::Swap(0, *next));
+                }
+            },
+            Sorter::Merge { width, lo, merge } => loop {
+                if let Some(m) = merge {
+                    if !m.writing {
+                        if m.left < m.mid && m.right < m.hi {
+                            let (a, b) = (m.left, m.right);
+                            if values[b] < values[a] {
+                                spare[m.out] = values[b];
+                                m.right += 1;
+                            } else {
+                                spare[m.out] = values[a];
+                                m.left += 1;
+                            }
+                            m.out += 1;
+                            return Some(Op::Compare(a, b));
+                        }
+                        // whatever's left of either run goes on the end without
+                        // any comparing
+                        for i in (m.left..m.mid).chain(m.right..m.hi) {
+                            spare[m.out] = values[i];
+                            m.out += 1;
+                        }
+                        m.writing = true;
+                        m.out = m.lo;
+                    }
+                    if m.out < m.hi {
+                        let out = m.out;
+                        values[out] = spare[out];
+                        m.out += 1;
+                        return Some(Op::Write(out));
+                    }
+                    *merge = None;
+                }
+                if *lo + *width >= n {
+                    *width *= 2;
+                    *lo = 0;
+                    if *width >= n {
+                        return None;
+                    }
+                    continue;
+                }
+                let mid = *lo + *width;
+                let hi = (mid + *width).min(n);
+                *merge = Some(Merge {
+                    lo: *lo,
+                    mid,
+                    hi,
+                    left: *lo,
+                    right: mid,
+                    out: *lo,
+                    writing: false,
+                });
+                *lo = hi;
+            },
+            Sorter::Radix {
+                place,
+                next,
+                writing,
+            } => loop {
+                if !*writing {
+                    if *next < n {
+                        let i = *next;
+                        *next += 1;
+                        return Some(Op::Read(i));
+                    }
+                    // a stable counting sort on this digit into the spare buffer
+                    let digit = |value: u8| value as usize / *place % RADIX;
+                    let mut starts = [0; RADIX];
+                    for value in values[..n].iter() {
+                        starts[digit(*value)] += 1;
+                    }
+                    let mut total = 0;
+                    for start in starts.iter_mut() {
+                        let count = *start;
+                        *start = total;
+                        total += count;
+                    }
+                    for value in values[..n].iter() {
+                        let d = digit(*value);
+                        spare[starts[d]] = *value;
+                        starts[d] += 1;
+                    }
+                    *writing = true;
+                    *next = 0;
+                }
+                if *next < n {
+                    let i = *next;
+                    values[i] = spare[i];
+                    *next += 1;
+                    return Some(Op::Write(i));
+                }
+                // the values go up to n - 1, so stop once no digits are left
+                *place *= RADIX;
+                *writing = false;
+                *next = 0;
+                if *place >= n {
+                    return None;
+                }
+            },
+        }
+    }
+
+    fn step(&mut self) {
+        match self.phase {
+            Phase::Sorting => {
+                self.last_op = self.sort_step();
+                if self.last_op.is_none() {
+                    self.phase = Phase::Checking(0);
+                }
+            }
+            Phase::Checking(i) => {
+                self.phase = if i + 1 >= self.len {
+                    Phase::Finished(SHOW_SORTED)
+                } else {
+                    Phase::Checking(i + 1)
+                };
+            }
+            Phase::Finished(_) => {}
+        }
+    }
+
+    fn column_colour(&self, index: usize) -> Rgb888 {
+        let compare = Rgb888::new(255, 220, 0);
+        let swap = Rgb888::new(255, 40, 20);
+        let sorted = Rgb888::new(40, 220, 60);
+        let plain = Rgb888::new(90, 90, 150);
+        match self.phase {
+            Phase::Checking(i) if index <= i => return sorted,
+            Phase::Finished(_) => return sorted,
+            _ => {}
+        }
+        match self.last_op {
+            Some(Op::Compare(a, b)) if index == a || index == b => compare,
+            Some(Op::Swap(a, b)) if index == a || index == b => swap,
+            Some(Op::Read(i)) if index == i => Rgb888::new(40, 200, 255),
+            Some(Op::Write(i)) if index == i => Rgb888::new(230, 60, 230),
+            _ => plain,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SortingUpdate {
+    Reset,
+    SetAlgorithm(SortAlgorithm),
+    /// The number of values to sort, up to the width of the panel. Starts again.
+    SetSize(u8),
+    /// Whether to move on to the next algorithm after each sort
+    SetCycle(bool),
+    SetStepsPerSecond(f32),
+}
+
+impl StateUpdate for SortingUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Sorting<Rng, W, H> {
+    type StateUpdate = SortingUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = delta_time_us as f32 / 1_000_000.0;
+        if let Phase::Finished(remaining) = self.phase {
+            if remaining > dt {
+                self.phase = Phase::Finished(remaining - dt);
+            } else {
+                if self.cycle {
+                    self.next_algorithm();
+                }
+                self.start();
+            }
+            return true;
+        }
+
+        // don't try to catch up on more than a fraction of a second
+        self.time_banked = (self.time_banked + dt).min(0.25);
+        let step_time = 1.0 / self.steps_per_second;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step();
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let column_width = (W / self.len).max(1);
+        let left = (W - column_width * self.len) / 2;
+        // leave a gap between wide columns so they can be told apart
+        let bar_width = if column_width >= 3 {
+            column_width - 1
+        } else {
+            column_width
+        };
+        for index in 0..self.len {
+            let colour = self.column_colour(index);
+            let height = (self.values[index] as usize + 1) * H / self.len;
+            let x0 = (left + index * column_width) as i32;
+            let pixels = (H - height..H).flat_map(|y| {
+                (x0..x0 + bar_width as i32).map(move |x| Pixel(Point::new(x, y as i32), colour))
+            });
+            let _ = target.draw_iter(pixels);
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            SortingUpdate::Reset => self.reset(),
+            SortingUpdate::SetAlgorithm(algorithm) => {
+                self.algorithm = algorithm;
+                self.start();
+            }
+            SortingUpdate::SetSize(len) => {
+                self.len = (len as usize).clamp(2, MAX_LEN.min(W));
+                self.start();
+            }
+            SortingUpdate::SetCycle(cycle) => self.cycle = cycle,
+            SortingUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 10_000.0)
+            }
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Sorting::new(SortAlgorithm::Bubble, 32, rng)
+    }
+
+    fn reset(&mut self) {
+        self.time_banked = 0.0;
+        self.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    type TestSorting = Sorting<XorShift, 64, 32>;
+
+    const ALGORITHMS: [SortAlgorithm; 6] = [
+        SortAlgorithm::Bubble,
+        SortAlgorithm::Insertion,
+        SortAlgorithm::Quick,
+        SortAlgorithm::Heap,
+        SortAlgorithm::Merge,
+        SortAlgorithm::Radix,
+    ];
+
+    /// Run the sort to the end, returning how many steps it took
+    fn sort(sorting: &mut TestSorting) -> usize {
+        let mut steps = 0;
+        while sorting.sort_step().is_some() {
+            steps += 1;
+            // bubble sort of 64 values takes a few thousand
+            assert!(steps < 100_000, "the sort didn't finish");
+        }
+        steps
+    }
+
+    fn assert_sorted(sorting: &TestSorting) {
+        let len = sorting.len;
+        assert!(
+            sorting.values[..len].iter().copied().eq(0..len as u8),
+            "{:?} didn't sort {len} values: {:?}",
+            sorting.algorithm as u8,
+            &sorting.values[..len]
+        );
+        assert!(sorting.pending_swap.is_none());
+    }
+
+    #[test]
+    fn every_algorithm_sorts_shuffled_values() {
+        for algorithm in ALGORITHMS {
+            for len in [2, 3, 4, 5, 7, 16, 31, 33, 64] {
+                for seed in [1, 7, 12_345] {
+                    let mut sorting = TestSorting::new(algorithm, len, XorShift(seed));
+                    sort(&mut sorting);
+                    assert_sorted(&sorting);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_algorithm_sorts_sorted_and_reversed_values() {
+        for algorithm in ALGORITHMS {
+            for len in [2, 9, 64] {
+                let mut sorting = TestSorting::new(algorithm, len, XorShift(1));
+                for (i, value) in sorting.values[..len].iter_mut().enumerate() {
+                    *value = i as u8;
+                }
+                sort(&mut sorting);
+                assert_sorted(&sorting);
+
+                sorting.start();
+                for (i, value) in sorting.values[..len].iter_mut().enumerate() {
+                    *value = (len - 1 - i) as u8;
+                }
+                sort(&mut sorting);
+                assert_sorted(&sorting);
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_values_stay_sorted() {
+        let mut sorting = TestSorting::new(SortAlgorithm::Quick, 40, XorShift(3));
+        sort(&mut sorting);
+        assert!(sorting.sort_step().is_none());
+        assert_sorted(&sorting);
+    }
+}
@@ -0,0 +1,311 @@
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::Rgb888,
+    prelude::{Point, RgbColor},
+};
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    audio::{AudioFrame, BAND_COUNT},
+    palette::{Palette, lerp},
+    text::draw_text_centred,
+};
+
+/// Seconds without any sound before saying that nothing's coming in
+const NO_AUDIO_TIME: f32 = 1.0;
+/// Seconds the peak markers stay at the top before falling
+const PEAK_HOLD: f32 = 0.5;
+/// How fast the peak markers fall, in levels per second
+const PEAK_FALL: f32 = 1.5;
+/// How fast the waterfall moves down the panel, in rows per second
+const WATERFALL_SPEED: f32 = 20.0;
+/// How fast the flash from a beat fades, per second
+const BEAT_FADE: f32 = 4.0;
+const MAX_HEIGHT: usize = 64;
+
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SpectrumMode {
+    /// A bar for each band, with markers at the recent peaks
+    Bars,
+    /// The spectrum over time, moving down the panel
+    Waterfall,
+    /// A volume meter
+    Vu,
+}
+
+/// Shows what the microphone hears: the spectrum as bars or a waterfall, or the volume
+/// on a meter. The panel flashes with each beat.
+pub struct Spectrum<const W: usize, const H: usize> {
+    mode: SpectrumMode,
+    palette: Palette,
+    /// how fast the bars fall, in levels per second
+    decay: f32,
+    bands: [f32; BAND_COUNT],
+    peaks: [f32; BAND_COUNT],
+    /// seconds each peak has left before it starts falling
+    peak_holds: [f32; BAND_COUNT],
+    level: f32,
+    level_peak: f32,
+    level_hold: f32,
+    /// the rows of the waterfall, with `waterfall_top` the newest
+    waterfall: [[u8; BAND_COUNT]; MAX_HEIGHT],
+    waterfall_top: usize,
+    /// the loudest of each band since the last waterfall row
+    pending_row: [f32; BAND_COUNT],
+    time_banked: f32,
+    beat_glow: f32,
+    since_audio: f32,
+    /// a beat sensitivity from the phone that hasn't been passed on to the analyser yet
+    beat_sensitivity: Option<f32>,
+}
+
+impl<const W: usize, const H: usize> Spectrum<W, H> {
+    pub fn new(mode: SpectrumMode) -> Self {
+        Spectrum {
+            mode,
+            palette: Palette::Fire,
+            decay: 2.0,
+            bands: [0.0; BAND_COUNT],
+            peaks: [0.0; BAND_COUNT],
+            peak_holds: [0.0; BAND_COUNT],
+            level: 0.0,
+            level_peak: 0.0,
+            level_hold: 0.0,
+            waterfall: [[0; BAND_COUNT]; MAX_HEIGHT],
+            waterfall_top: 0,
+            pending_row: [0.0; BAND_COUNT],
+            time_banked: 0.0,
+            beat_glow: 0.0,
+            since_audio: NO_AUDIO_TIME,
+            beat_sensitivity: None,
+        }
+    }
+
+    /// A new beat sensitivity for the [crate::AudioAnalyser], which runs apart from
+    /// the visualisations, if one has been sent since this was last called
+    pub fn take_beat_sensitivity(&mut self) -> Option<f32> {
+        self.beat_sensitivity.take()
+    }
+
+    /// Let a peak marker fall once it's been held long enough
+    fn fall_peak(peak: &mut f32, hold: &mut f32, dt: f32) {
+        if *hold > 0.0 {
+            *hold -= dt;
+        } else {
+            *peak = (*peak - PEAK_FALL * dt).max(0.0);
+        }
+    }
+
+    /// Raise a peak marker to a level if it's higher
+    fn raise_peak(peak: &mut f32, hold: &mut f32, level: f32) {
+        if level >= *peak {
+            *peak = level;
+            *hold = PEAK_HOLD;
+        }
+    }
+
+    /// The height in pixels of a level from 0 to 1
+    fn height(level: f32) -> i32 {
+        libm::roundf(level * H as f32) as i32
+    }
+
+    fn bar_pixels(&self) -> impl Iterator<Item = Pixel<Rgb888>> + '_ {
+        let bar_width = (W / BAND_COUNT).max(1) as i32;
+        let background = self.background();
+        (0..H as i32).flat_map(move |y| {
+            (0..W as i32).map(move |x| {
+                let band = ((x / bar_width) as usize).min(BAND_COUNT - 1);
+                let height = H as i32 - 1 - y;
+                let in_gap = bar_width > 2 && x % bar_width == bar_width - 1;
+                let colour = if in_gap {
+                    background
+                } else if height < Self::height(self.bands[band]) {
+                    self.palette.sample(0.3 + 0.7 * height as f32 / H as f32)
+                } else if height == Self::height(self.peaks[band]) && self.peaks[band] > 0.0 {
+                    Rgb888::WHITE
+                } else {
+                    background
+                };
+                Pixel(Point::new(x, y), colour)
+            })
+        })
+    }
+
+    fn waterfall_pixels(&self) -> impl Iterator<Item = Pixel<Rgb888>> + '_ {
+        let rows = H.min(MAX_HEIGHT);
+        (0..rows as i32).flat_map(move |y| {
+            let row = &self.waterfall[(self.waterfall_top + y as usize) % MAX_HEIGHT];
+            (0..W as i32).map(move |x| {
+                let band = (x as usize * BAND_COUNT / W).min(BAND_COUNT - 1);
+                let level = row[band] as f32 / 255.0;
+                Pixel(Point::new(x, y), self.palette.sample(level))
+            })
+        })
+    }
+
+    /// A horizontal meter across the middle, green then yellow then red
+    fn vu_pixels(&self) -> impl Iterator<Item = Pixel<Rgb888>> + '_ {
+        let (top, bottom) = (H as i32 / 4, H as i32 * 3 / 4);
+        let lit = libm::roundf(self.level * W as f32) as i32;
+        let peak = libm::roundf(self.level_peak * W as f32) as i32 - 1;
+        let background = self.background();
+        (0..H as i32).flat_map(move |y| {
+            (0..W as i32).map(move |x| {
+                let fraction = x as f32 / W as f32;
+                let segment = if fraction < 0.7 {
+                    Rgb888::new(0, 220, 40)
+                } else if fraction < 0.9 {
+                    Rgb888::new(240, 200, 0)
+                } else {
+                    Rgb888::new(255, 30, 0)
+                };
+                let colour = if y < top || y >= bottom {
+                    background
+                } else if x % 2 == 1 {
+                    // a gap between the segments
+                    background
+                } else if x < lit || x == peak {
+                    segment
+                } else {
+                    lerp(background, segment, 0.15)
+                };
+                Pixel(Point::new(x, y), colour)
+            })
+        })
+    }
+
+    /// The background colour, lit up by the last beat
+    fn background(&self) -> Rgb888 {
+        lerp(
+            Rgb888::BLACK,
+            self.palette.sample(0.6),
+            0.3 * self.beat_glow,
+        )
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum SpectrumUpdate {
+    Reset,
+    SetMode(SpectrumMode),
+    SetPalette(Palette),
+    /// How fast the bars fall back down, in heights of the panel per second
+    SetDecay(f32),
+    /// How many times louder than usual the bass has to get to count as a beat
+    SetBeatSensitivity(f32),
+}
+
+impl StateUpdate for SpectrumUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Spectrum<W, H> {
+    type StateUpdate = SpectrumUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = (delta_time_us as f32 / 1_000_000.0).min(0.1);
+        self.since_audio += dt;
+        self.beat_glow = (self.beat_glow - BEAT_FADE * dt).max(0.0);
+        for band in 0..BAND_COUNT {
+            self.bands[band] = (self.bands[band] - self.decay * dt).max(0.0);
+            Self::fall_peak(&mut self.peaks[band], &mut self.peak_holds[band], dt);
+        }
+        self.level = (self.level - self.decay * dt).max(0.0);
+        Self::fall_peak(&mut self.level_peak, &mut self.level_hold, dt);
+
+        self.time_banked = (self.time_banked + dt).min(1.0 / WATERFALL_SPEED);
+        if self.time_banked >= 1.0 / WATERFALL_SPEED {
+            self.time_banked = 0.0;
+            self.waterfall_top = (self.waterfall_top + MAX_HEIGHT - 1) % MAX_HEIGHT;
+            for (pixel, level) in self.waterfall[self.waterfall_top]
+                .iter_mut()
+                .zip(self.pending_row.iter_mut())
+            {
+                *pixel = (*level * 255.0) as u8;
+                *level = 0.0;
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        if self.since_audio >= NO_AUDIO_TIME {
+            draw_text_centred(
+                target,
+                "NO AUDIO",
+                Point::new(W as i32 / 2, H as i32 / 2 - 3),
+                Rgb888::new(80, 80, 80),
+            );
+            return;
+        }
+        let _ = match self.mode {
+            SpectrumMode::Bars => target.draw_iter(self.bar_pixels()),
+            SpectrumMode::Waterfall => target.draw_iter(self.waterfall_pixels()),
+            SpectrumMode::Vu => target.draw_iter(self.vu_pixels()),
+        };
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            SpectrumUpdate::Reset => <Self as Visualisation<Rng>>::reset(self),
+            SpectrumUpdate::SetMode(mode) => self.mode = mode,
+            SpectrumUpdate::SetPalette(palette) => self.palette = palette,
+            SpectrumUpdate::SetDecay(decay) => self.decay = decay.clamp(0.1, 20.0),
+            SpectrumUpdate::SetBeatSensitivity(sensitivity) => {
+                self.beat_sensitivity = Some(sensitivity)
+            }
+        }
+    }
+
+    fn audio(&mut self, frame: &AudioFrame) {
+        self.since_audio = 0.0;
+        for band in 0..BAND_COUNT {
+            let level = frame.bands[band];
+            self.bands[band] = self.bands[band].max(level);
+            self.pending_row[band] = self.pending_row[band].max(level);
+            Self::raise_peak(&mut self.peaks[band], &mut self.peak_holds[band], level);
+        }
+        self.level = self.level.max(frame.level);
+        Self::raise_peak(&mut self.level_peak, &mut self.level_hold, frame.level);
+        if frame.beat {
+            self.beat_glow = self.beat_glow.max(0.4 + 0.6 * frame.beat_strength);
+        }
+    }
+
+    fn new(_rng: Rng) -> Self {
+        Spectrum::new(SpectrumMode::Bars)
+    }
+
+    fn reset(&mut self) {
+        self.bands = [0.0; BAND_COUNT];
+        self.peaks = [0.0; BAND_COUNT];
+        self.level = 0.0;
+        self.level_peak = 0.0;
+        self.waterfall = [[0; BAND_COUNT]; MAX_HEIGHT];
+        self.beat_glow = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    #[test]
+    fn beat_sensitivity_is_passed_on_once() {
+        let mut spectrum = Spectrum::<64, 32>::new(SpectrumMode::Bars);
+        assert_eq!(spectrum.take_beat_sensitivity(), None);
+        <Spectrum<64, 32> as Visualisation<XorShift>>::run_state_update(
+            &mut spectrum,
+            SpectrumUpdate::SetBeatSensitivity(2.0),
+        );
+        assert_eq!(spectrum.take_beat_sensitivity(), Some(2.0));
+        assert_eq!(spectrum.take_beat_sensitivity(), None);
+    }
+}
@@ -0,0 +1,139 @@
+use embedded_graphics::pixelcolor::Rgb888;
+
+use crate::{
+    RngU32, StateUpdate, Visualisation,
+    render3d::{Mat4, Renderer, Vec3},
+};
+
+/// The maximum number of stars that can be on screen
+const MAX_STARS: usize = 160;
+
+/// Stars rushing towards the camera, drawn as streaks
+pub struct Starfield<Rng, const W: usize, const H: usize> {
+    stars: [Vec3; MAX_STARS],
+    n_stars: usize,
+    rng: Rng,
+    projection: Mat4,
+    /// how fast the camera moves forward, in world units per second
+    speed: f32,
+    /// how fast the camera rolls, in radians per second
+    roll_speed: f32,
+    roll: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Starfield<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 12.0;
+    /// how far the stars spread out from the centre of the view
+    const SPREAD: f32 = 4.0;
+    /// the length of the streaks, as the time taken to travel them
+    const STREAK_TIME: f32 = 0.04;
+
+    pub fn new(n_stars: usize, rng: Rng) -> Self {
+        let mut this = Starfield {
+            stars: [Vec3::new(0.0, 0.0, 0.0); MAX_STARS],
+            n_stars: n_stars.min(MAX_STARS),
+            rng,
+            projection: Mat4::perspective(1.2, Renderer::<W, H>::aspect(), Self::NEAR, Self::FAR),
+            speed: 6.0,
+            roll_speed: 0.2,
+            roll: 0.0,
+        };
+        for i in 0..MAX_STARS {
+            let z = -Self::NEAR - this.rng.unit_f32() * (Self::FAR - Self::NEAR);
+            this.stars[i] = this.new_star(z);
+        }
+        this
+    }
+
+    fn new_star(&mut self, z: f32) -> Vec3 {
+        Vec3::new(
+            (self.rng.unit_f32() * 2.0 - 1.0) * Self::SPREAD,
+            (self.rng.unit_f32() * 2.0 - 1.0) * Self::SPREAD,
+            z,
+        )
+    }
+
+    pub fn set_n_stars(&mut self, n_stars: usize) {
+        self.n_stars = n_stars.min(MAX_STARS);
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum StarfieldUpdate {
+    Reset,
+    /// How fast the stars move towards the camera, in world units per second
+    SetSpeed(f32),
+    /// How fast the view rolls, in radians per second
+    SetRollSpeed(f32),
+    SetStarCount(u16),
+}
+
+impl StateUpdate for StarfieldUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Starfield<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = StarfieldUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let dt = delta_time_us as f32 / 1_000_000.0;
+        self.roll = (self.roll + self.roll_speed * dt) % core::f32::consts::TAU;
+        for i in 0..self.n_stars {
+            self.stars[i].z += self.speed * dt;
+            if self.stars[i].z > -Self::NEAR {
+                self.stars[i] = self.new_star(-Self::FAR);
+            }
+        }
+        true
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let mvp = self.projection * Mat4::rotation_z(self.roll);
+        let streak = Vec3::new(0.0, 0.0, -self.speed * Self::STREAK_TIME);
+        for star in self.stars[..self.n_stars].iter() {
+            if let (Some(head), Some(tail)) = (
+                Renderer::<W, H>::project(&mvp, *star),
+                Renderer::<W, H>::project(&mvp, *star + streak),
+            ) {
+                // stars fade in from the distance
+                let brightness = (1.0 + star.z / Self::FAR).clamp(0.0, 1.0);
+                let v = (brightness * 255.0) as u8;
+                Renderer::<W, H>::draw_line(target, tail, head, Rgb888::new(v, v, v));
+            }
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            StarfieldUpdate::Reset => self.reset(),
+            StarfieldUpdate::SetSpeed(speed) => self.speed = speed.max(0.0),
+            StarfieldUpdate::SetRollSpeed(roll_speed) => self.roll_speed = roll_speed,
+            StarfieldUpdate::SetStarCount(n) => self.set_n_stars(n as usize),
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Starfield::new(100, rng)
+    }
+
+    fn reset(&mut self) {
+        for i in 0..MAX_STARS {
+            let z = -Self::NEAR - self.rng.unit_f32() * (Self::FAR - Self::NEAR);
+            self.stars[i] = self.new_star(z);
+        }
+        self.roll = 0.0;
+    }
+}
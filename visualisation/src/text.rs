@@ -0,0 +1,221 @@
+use embedded_graphics::{
+    Pixel,
+    mono_font::{MonoTextStyle, ascii::FONT_4X6},
+    pixelcolor::Rgb888,
+    prelude::{Drawable, Point},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+/// The height of the proportional font, in pixels
+pub const PROPORTIONAL_HEIGHT: i32 = 5;
+
+/// The proportional font, as columns of pixels with bit 0 at the top. It covers
+/// printable ASCII, apart from lower case letters which are drawn as capitals.
+const GLYPHS: [&[u8]; 69] = [
+    &[0x00, 0x00],                   // ' '
+    &[0x17],                         // '!'
+    &[0x03, 0x00, 0x03],             // '"'
+    &[0x0a, 0x1f, 0x0a, 0x1f, 0x0a], // '#'
+    &[0x12, 0x1f, 0x09],             // '$'
+    &[0x19, 0x04, 0x13],             // '%'
+    &[0x0a, 0x15, 0x0a, 0x10],       // '&'
+    &[0x03],                         // '\''
+    &[0x0e, 0x11],                   // '('
+    &[0x11, 0x0e],                   // ')'
+    &[0x05, 0x02, 0x05],             // '*'
+    &[0x04, 0x0e, 0x04],             // '+'
+    &[0x10, 0x08],                   // ','
+    &[0x04, 0x04, 0x04],             // '-'
+    &[0x10],                         // '.'
+    &[0x18, 0x04, 0x03],             // '/'
+    &[0x1f, 0x11, 0x1f],             // '0'
+    &[0x02, 0x1f],                   // '1'
+    &[0x19, 0x15, 0x12],             // '2'
+    &[0x11, 0x15, 0x0a],             // '3'
+    &[0x07, 0x04, 0x1f],             // '4'
+    &[0x17, 0x15, 0x09],             // '5'
+    &[0x1e, 0x15, 0x1d],             // '6'
+    &[0x01, 0x1d, 0x03],             // '7'
+    &[0x1f, 0x15, 0x1f],             // '8'
+    &[0x17, 0x15, 0x0f],             // '9'
+    &[0x0a],                         // ':'
+    &[0x10, 0x0a],                   // ';'
+    &[0x04, 0x0a, 0x11],             // '<'
+    &[0x0a, 0x0a, 0x0a],             // '='
+    &[0x11, 0x0a, 0x04],             // '>'
+    &[0x01, 0x15, 0x02],             // '?'
+    &[0x0e, 0x11, 0x15, 0x16],       // '@'
+    &[0x1e, 0x05, 0x1e],             // 'A'
+    &[0x1f, 0x15, 0x0a],             // 'B'
+    &[0x0e, 0x11, 0x11],             // 'C'
+    &[0x1f, 0x11, 0x0e],             // 'D'
+    &[0x1f, 0x15, 0x11],             // 'E'
+    &[0x1f, 0x05, 0x01],             // 'F'
+    &[0x0e, 0x11, 0x1d],             // 'G'
+    &[0x1f, 0x04, 0x1f],             // 'H'
+    &[0x11, 0x1f, 0x11],             // 'I'
+    &[0x08, 0x10, 0x0f],             // 'J'
+    &[0x1f, 0x04, 0x1b],             // 'K'
+    &[0x1f, 0x10, 0x10],             // 'L'
+    &[0x1f, 0x02, 0x04, 0x02, 0x1f], // 'M'
+    &[0x1f, 0x02, 0x04, 0x1f],       // 'N'
+    &[0x0e, 0x11, 0x0e],             // 'O'
+    &[0x1f, 0x05, 0x02],             // 'P'
+    &[0x0e, 0x19, 0x16],             // 'Q'
+    &[0x1f, 0x05, 0x1a],             // 'R'
+    &[0x12, 0x15, 0x09],             // 'S'
+    &[0x01, 0x1f, 0x01],             // 'T'
+    &[0x1f, 0x10, 0x1f],             // 'U'
+    &[0x0f, 0x10, 0x0f],             // 'V'
+    &[0x1f, 0x08, 0x04, 0x08, 0x1f], // 'W'
+    &[0x1b, 0x04, 0x1b],             // 'X'
+    &[0x03, 0x1c, 0x03],             // 'Y'
+    &[0x19, 0x15, 0x13],             // 'Z'
+    &[0x1f, 0x11],                   // '['
+    &[0x03, 0x04, 0x18],             // '\\'
+    &[0x11, 0x1f],                   // ']'
+    &[0x02, 0x01, 0x02],             // '^'
+    &[0x10, 0x10, 0x10],             // '_'
+    &[0x01, 0x02],                   // '`'
+    &[0x04, 0x1f, 0x11],             // '{'
+    &[0x1f],                         // '|'
+    &[0x11, 0x1f, 0x04],             // '}'
+    &[0x04, 0x02, 0x04, 0x02],       // '~'
+];
+
+/// Draw a line of text in the small 4x6 font, with its top left corner at `position`
+pub fn draw_text<
+    D: embedded_graphics::prelude::DrawTarget<
+            Color = embedded_graphics::pixelcolor::Rgb888,
+            Error = core::convert::Infallible,
+        >,
+>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    colour: Rgb888,
+) {
+    draw_aligned(target, text, position, colour, Alignment::Left);
+}
+
+/// Draw a line of text in the small 4x6 font, centred horizontally on `position`
+/// with its top at `position.y`
+pub fn draw_text_centred<
+    D: embedded_graphics::prelude::DrawTarget<
+            Color = embedded_graphics::pixelcolor::Rgb888,
+            Error = core::convert::Infallible,
+        >,
+>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    colour: Rgb888,
+) {
+    draw_aligned(target, text, position, colour, Alignment::Center);
+}
+
+fn draw_aligned<
+    D: embedded_graphics::prelude::DrawTarget<
+            Color = embedded_graphics::pixelcolor::Rgb888,
+            Error = core::convert::Infallible,
+        >,
+>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    colour: Rgb888,
+    alignment: Alignment,
+) {
+    let style = TextStyleBuilder::new()
+        .alignment(alignment)
+        .baseline(Baseline::Top)
+        .build();
+    let _ = Text::with_text_style(text, position, MonoTextStyle::new(&FONT_4X6, colour), style)
+        .draw(target);
+}
+
+/// A fixed size buffer to format short strings into with `write!`.
+/// Anything that doesn't fit is dropped.
+pub struct TextBuffer<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> TextBuffer<N> {
+    pub fn new() -> Self {
+        Self {
+            data: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole characters are ever written, so this is always valid
+        core::str::from_utf8(&self.data[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> core::fmt::Write for TextBuffer<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            let size = c.len_utf8();
+            if self.len + size > N {
+                break;
+            }
+            c.encode_utf8(&mut self.data[self.len..]);
+            self.len += size;
+        }
+        Ok(())
+    }
+}
+
+/// The columns of a character in the proportional font, with anything it doesn't
+/// cover drawn as a question mark
+fn glyph(c: char) -> &'static [u8] {
+    let c = c.to_ascii_uppercase();
+    let index = match c {
+        ' '..='`' => c as usize - ' ' as usize,
+        '{'..='~' => c as usize - '{' as usize + ('`' as usize - ' ' as usize + 1),
+        _ => '?' as usize - ' ' as usize,
+    };
+    GLYPHS[index]
+}
+
+/// The width of a line of text in the proportional font, in pixels
+pub fn proportional_width(text: &str) -> i32 {
+    let width: i32 = text.chars().map(|c| glyph(c).len() as i32 + 1).sum();
+    (width - 1).max(0)
+}
+
+/// Draw a line of text in the proportional font, with its top left corner at
+/// `position`. Characters outside the target are skipped.
+pub fn draw_proportional<
+    D: embedded_graphics::prelude::DrawTarget<
+            Color = embedded_graphics::pixelcolor::Rgb888,
+            Error = core::convert::Infallible,
+        >,
+>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    colour: Rgb888,
+) {
+    let bounds = target.bounding_box();
+    let right = bounds.top_left.x + bounds.size.width as i32;
+    let mut x = position.x;
+    for c in text.chars() {
+        if x >= right {
+            break;
+        }
+        let columns = glyph(c);
+        if x + columns.len() as i32 > bounds.top_left.x {
+            let pixels = columns.iter().enumerate().flat_map(|(i, column)| {
+                (0..PROPORTIONAL_HEIGHT)
+                    .filter(move |y| column & (1 << y) != 0)
+                    .map(move |y| Pixel(Point::new(x + i as i32, position.y + y), colour))
+            });
+            let _ = target.draw_iter(pixels);
+        }
+        x += columns.len() as i32 + 1;
+    }
+}
@@ -0,0 +1,520 @@
+use embedded_graphics::{Pixel, pixelcolor::Rgb888, prelude::Point};
+
+use crate::{RngU32, StateUpdate, Visualisation, grid::Grid};
+
+/// The most steps to run in one update, so a long frame doesn't stall everything
+const MAX_STEPS: u32 = 4;
+/// How many sharks are put back if they all die out
+const SEED_SHARKS: usize = 4;
+/// Rows at the bottom of the panel used by the population graph, when it's shown
+const GRAPH_HEIGHT: usize = 8;
+/// The most steps of population counts kept for the graph
+const HISTORY_LEN: usize = 128;
+
+const WATER_COLOUR: Rgb888 = Rgb888::new(0, 10, 40);
+const FISH_COLOUR: Rgb888 = Rgb888::new(40, 220, 120);
+const SHARK_COLOUR: Rgb888 = Rgb888::new(255, 70, 30);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Cell {
+    Water,
+    Fish {
+        /// steps since it last bred
+        age: u8,
+    },
+    Shark {
+        age: u8,
+        /// steps left before it starves, unless it eats
+        energy: u8,
+    },
+}
+
+/// Dewdney's Wa-Tor, a toroidal ocean of fish and the sharks that eat them.
+///
+/// Each step every fish swims to a random empty neighbour, and leaves a new fish
+/// behind once it's old enough to breed. Sharks swim onto a neighbouring fish and eat
+/// it if there is one, or to an empty neighbour if not. Each step costs a shark one
+/// energy and each fish eaten gains it `fish_energy`, and it starves when it runs out.
+/// Sharks breed like fish, and their young start with `shark_energy`.
+///
+/// All the randomness comes from the rng, so a seeded rng always plays out the same.
+/// If the sharks die out a few are put back, and if the fish die out it starts again.
+pub struct Wator<Rng, const W: usize, const H: usize>
+where
+    [(); W * H]:,
+{
+    cells: Grid<Cell, W, H>,
+    /// the cells that have already moved this step, so nothing moves twice
+    moved: Grid<bool, W, H>,
+    rng: Rng,
+    /// steps a fish has to survive before it breeds
+    fish_breed_time: u8,
+    /// steps a shark has to survive before it breeds
+    shark_breed_time: u8,
+    /// the energy a new shark starts with
+    shark_energy: u8,
+    /// the energy a shark gets from eating a fish
+    fish_energy: u8,
+    /// the fraction of the ocean filled with fish at the start
+    fish_density: f32,
+    /// the fraction of the ocean filled with sharks at the start
+    shark_density: f32,
+    show_graph: bool,
+    /// the number of fish and sharks after each of the last steps, with
+    /// `history_next` the next to be written
+    history: [(u16, u16); HISTORY_LEN],
+    history_len: usize,
+    history_next: usize,
+    fish: u16,
+    sharks: u16,
+    steps_per_second: f32,
+    time_banked: f32,
+}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Wator<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    pub fn new(
+        fish_breed_time: u8,
+        shark_breed_time: u8,
+        shark_energy: u8,
+        fish_energy: u8,
+        rng: Rng,
+    ) -> Self {
+        let mut this = Wator {
+            cells: Grid::new(Cell::Water),
+            moved: Grid::new(false),
+            rng,
+            fish_breed_time: fish_breed_time.max(1),
+            shark_breed_time: shark_breed_time.max(1),
+            shark_energy: shark_energy.max(1),
+            fish_energy,
+            fish_density: 0.3,
+            shark_density: 0.05,
+            show_graph: true,
+            history: [(0, 0); HISTORY_LEN],
+            history_len: 0,
+            history_next: 0,
+            fish: 0,
+            sharks: 0,
+            steps_per_second: 15.0,
+            time_banked: 0.0,
+        };
+        <Self as Visualisation<Rng>>::reset(&mut this);
+        this
+    }
+
+    /// The number of fish and sharks in the ocean
+    pub fn populations(&self) -> (u16, u16) {
+        (self.fish, self.sharks)
+    }
+
+    /// The rows the ocean covers, leaving room for the graph under it if it's shown
+    fn rows(&self) -> usize {
+        if self.show_graph && H > GRAPH_HEIGHT {
+            H - GRAPH_HEIGHT
+        } else {
+            H
+        }
+    }
+
+    /// The indices of the four neighbours of a cell, wrapping around the edges
+    fn neighbours(index: usize, rows: usize) -> [usize; 4] {
+        let (x, y) = (index % W, index / W);
+        [
+            y * W + (x + W - 1) % W,
+            y * W + (x + 1) % W,
+            (y + rows - 1) % rows * W + x,
+            (y + 1) % rows * W + x,
+        ]
+    }
+
+    /// A random neighbour of a cell that matches, if there are any
+    fn pick_neighbour(
+        &mut self,
+        index: usize,
+        rows: usize,
+        matches: fn(Cell) -> bool,
+    ) -> Option<usize> {
+        let mut found = [0; 4];
+        let mut count = 0;
+        for neighbour in Self::neighbours(index, rows) {
+            if matches(self.cells.buffer()[neighbour]) {
+                found[count] = neighbour;
+                count += 1;
+            }
+        }
+        (count > 0).then(|| found[self.rng.next_u32() as usize % count])
+    }
+
+    fn step_fish(&mut self, index: usize, rows: usize, age: u8) {
+        let age = age.saturating_add(1);
+        let Some(to) = self.pick_neighbour(index, rows, |c| c == Cell::Water) else {
+            self.cells.buffer_mut()[index] = Cell::Fish { age };
+            return;
+        };
+        let cells = self.cells.buffer_mut();
+        if age >= self.fish_breed_time {
+            cells[to] = Cell::Fish { age: 0 };
+            cells[index] = Cell::Fish { age: 0 };
+        } else {
+            cells[to] = Cell::Fish { age };
+            cells[index] = Cell::Water;
+        }
+        self.moved.buffer_mut()[to] = true;
+    }
+
+    fn step_shark(&mut self, index: usize, rows: usize, age: u8, energy: u8) {
+        let age = age.saturating_add(1);
+        let mut energy = energy.saturating_sub(1);
+        let prey = self.pick_neighbour(index, rows, |c| matches!(c, Cell::Fish { .. }));
+        if prey.is_some() {
+            energy = energy.saturating_add(self.fish_energy);
+        }
+        if energy == 0 {
+            self.cells.buffer_mut()[index] = Cell::Water;
+            return;
+        }
+        let Some(to) = prey.or_else(|| self.pick_neighbour(index, rows, |c| c == Cell::Water))
+        else {
+            self.cells.buffer_mut()[index] = Cell::Shark { age, energy };
+            return;
+        };
+        let shark_energy = self.shark_energy;
+        let cells = self.cells.buffer_mut();
+        if age >= self.shark_breed_time {
+            cells[to] = Cell::Shark { age: 0, energy };
+            cells[index] = Cell::Shark {
+                age: 0,
+                energy: shark_energy,
+            };
+        } else {
+            cells[to] = Cell::Shark { age, energy };
+            cells[index] = Cell::Water;
+        }
+        self.moved.buffer_mut()[to] = true;
+    }
+
+    /// Fill the ocean with fish and sharks at random
+    fn populate(&mut self) {
+        let cells = W * self.rows();
+        for index in 0..W * H {
+            let cell = if index >= cells {
+                Cell::Water
+            } else if self.rng.chance(self.shark_density) {
+                Cell::Shark {
+                    age: (self.rng.next_u32() % self.shark_breed_time as u32) as u8,
+                    energy: self.shark_energy,
+                }
+            } else if self.rng.chance(self.fish_density) {
+                Cell::Fish {
+                    age: (self.rng.next_u32() % self.fish_breed_time as u32) as u8,
+                }
+            } else {
+                Cell::Water
+            };
+            self.cells.buffer_mut()[index] = cell;
+        }
+        self.count();
+    }
+
+    /// Put a few sharks back in place of random fish
+    fn seed_sharks(&mut self) {
+        let cells = W * self.rows();
+        for _ in 0..SEED_SHARKS {
+            let index = self.rng.next_u32() as usize % cells;
+            if matches!(self.cells.buffer()[index], Cell::Fish { .. }) {
+                self.cells.buffer_mut()[index] = Cell::Shark {
+                    age: 0,
+                    energy: self.shark_energy,
+                };
+            }
+        }
+        self.count();
+    }
+
+    fn count(&mut self) {
+        self.fish = 0;
+        self.sharks = 0;
+        for cell in self.cells.buffer().iter() {
+            match cell {
+                Cell::Water => {}
+                Cell::Fish { .. } => self.fish += 1,
+                Cell::Shark { .. } => self.sharks += 1,
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        let rows = self.rows();
+        let cells = W * rows;
+        self.moved.buffer_mut().fill(false);
+        // start somewhere different each step, so the scan order doesn't favour any part
+        // of the ocean
+        let start = self.rng.next_u32() as usize % cells;
+        for offset in 0..cells {
+            let index = (start + offset) % cells;
+            if self.moved.buffer()[index] {
+                continue;
+            }
+            match self.cells.buffer()[index] {
+                Cell::Water => {}
+                Cell::Fish { age } => self.step_fish(index, rows, age),
+                Cell::Shark { age, energy } => self.step_shark(index, rows, age, energy),
+            }
+        }
+
+        self.count();
+        self.history[self.history_next] = (self.fish, self.sharks);
+        self.history_next = (self.history_next + 1) % HISTORY_LEN;
+        self.history_len = (self.history_len + 1).min(HISTORY_LEN);
+
+        if self.fish == 0 {
+            self.populate();
+        } else if self.sharks == 0 {
+            self.seed_sharks();
+        }
+    }
+
+    fn graph_pixels(&self) -> impl Iterator<Item = Pixel<Rgb888>> + '_ {
+        let shown = self.history_len.min(W);
+        let oldest = self.history_next + HISTORY_LEN - shown;
+        let samples = (0..shown).map(move |i| self.history[(oldest + i) % HISTORY_LEN]);
+        // scale to the biggest population shown, so both lines fill the strip
+        let scale = samples
+            .clone()
+            .map(|(fish, sharks)| fish.max(sharks))
+            .max()
+            .unwrap_or(0)
+            .max(1) as usize;
+        let bottom = H as i32 - 1;
+        let y = move |count: u16| bottom - (count as usize * (GRAPH_HEIGHT - 1) / scale) as i32;
+        let background = (H - GRAPH_HEIGHT..H).flat_map(|y| {
+            (0..W).map(move |x| Pixel(Point::new(x as i32, y as i32), Rgb888::new(0, 0, 0)))
+        });
+        let lines = samples.enumerate().flat_map(move |(x, (fish, sharks))| {
+            let x = (W - shown + x) as i32;
+            [
+                Pixel(Point::new(x, y(fish)), FISH_COLOUR),
+                Pixel(Point::new(x, y(sharks)), SHARK_COLOUR),
+            ]
+        });
+        background.chain(lines)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum WatorUpdate {
+    Reset,
+    /// Steps a fish has to survive before it breeds
+    SetFishBreedTime(u8),
+    /// Steps a shark has to survive before it breeds
+    SetSharkBreedTime(u8),
+    /// The energy a new shark starts with, which is how many steps it can go without
+    /// eating
+    SetSharkEnergy(u8),
+    /// The energy a shark gets from eating a fish
+    SetFishEnergy(u8),
+    /// The fraction of the ocean filled with fish when it starts. Starts again.
+    SetFishDensity(f32),
+    /// The fraction of the ocean filled with sharks when it starts. Starts again.
+    SetSharkDensity(f32),
+    /// Show the populations over time in a strip under the ocean. Starts again.
+    SetShowGraph(bool),
+    SetStepsPerSecond(f32),
+}
+
+impl StateUpdate for WatorUpdate {}
+
+impl<Rng: RngU32, const W: usize, const H: usize> Visualisation<Rng> for Wator<Rng, W, H>
+where
+    [(); W * H]:,
+{
+    type StateUpdate = WatorUpdate;
+
+    fn update(&mut self, delta_time_us: u32) -> bool {
+        let step_time = 1.0 / self.steps_per_second;
+        self.time_banked = (self.time_banked + delta_time_us as f32 / 1_000_000.0)
+            .min(step_time * MAX_STEPS as f32);
+        let mut stepped = false;
+        while self.time_banked >= step_time {
+            self.time_banked -= step_time;
+            self.step();
+            stepped = true;
+        }
+        stepped
+    }
+
+    fn draw<
+        D: embedded_graphics::prelude::DrawTarget<
+                Color = embedded_graphics::pixelcolor::Rgb888,
+                Error = core::convert::Infallible,
+            >,
+    >(
+        &mut self,
+        target: &mut D,
+    ) {
+        let rows = self.rows() as i32;
+        let _ = target.draw_iter(
+            self.cells
+                .iter_with_index()
+                .filter(|((_, y), _)| *y < rows)
+                .map(|((x, y), cell)| {
+                    let colour = match cell {
+                        Cell::Water => WATER_COLOUR,
+                        Cell::Fish { .. } => FISH_COLOUR,
+                        Cell::Shark { .. } => SHARK_COLOUR,
+                    };
+                    Pixel(Point::new(x, y), colour)
+                }),
+        );
+        if rows < H as i32 {
+            let _ = target.draw_iter(self.graph_pixels());
+        }
+    }
+
+    fn run_state_update(&mut self, state_update: Self::StateUpdate) {
+        match state_update {
+            WatorUpdate::Reset => self.reset(),
+            WatorUpdate::SetFishBreedTime(steps) => self.fish_breed_time = steps.max(1),
+            WatorUpdate::SetSharkBreedTime(steps) => self.shark_breed_time = steps.max(1),
+            WatorUpdate::SetSharkEnergy(energy) => self.shark_energy = energy.max(1),
+            WatorUpdate::SetFishEnergy(energy) => self.fish_energy = energy,
+            WatorUpdate::SetFishDensity(density) => {
+                self.fish_density = density.clamp(0.0, 1.0);
+                self.reset();
+            }
+            WatorUpdate::SetSharkDensity(density) => {
+                self.shark_density = density.clamp(0.0, 1.0);
+                self.reset();
+            }
+            WatorUpdate::SetShowGraph(show) => {
+                self.show_graph = show;
+                self.reset();
+            }
+            WatorUpdate::SetStepsPerSecond(steps) => {
+                self.steps_per_second = steps.clamp(1.0, 120.0)
+            }
+        }
+    }
+
+    fn new(rng: Rng) -> Self {
+        Wator::new(3, 10, 4, 3, rng)
+    }
+
+    fn reset(&mut self) {
+        self.populate();
+        self.history_len = 0;
+        self.history_next = 0;
+        self.time_banked = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift::XorShift;
+
+    type SmallWator = Wator<XorShift, 16, 16>;
+
+    fn populations_over(wator: &mut SmallWator, updates: usize) -> heapless::Vec<(u16, u16), 64> {
+        (0..updates)
+            .map(|_| {
+                wator.update(100_000);
+                wator.populations()
+            })
+            .collect()
+    }
+
+    /// A wator that takes exactly one step each second of updates
+    fn one_step_a_second(wator: &mut SmallWator) {
+        wator.run_state_update(WatorUpdate::SetStepsPerSecond(1.0));
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let mut a = SmallWator::new(3, 10, 4, 3, XorShift(42));
+        let mut b = SmallWator::new(3, 10, 4, 3, XorShift(42));
+        let mut c = SmallWator::new(3, 10, 4, 3, XorShift(43));
+        let (a, b, c) = (
+            populations_over(&mut a, 60),
+            populations_over(&mut b, 60),
+            populations_over(&mut c, 60),
+        );
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    /// The fish and sharks each step with seed 7, so any change to how the ocean
+    /// plays out shows up
+    const PINNED: [(u16, u16); 16] = [
+        (38, 5),
+        (45, 5),
+        (50, 6),
+        (61, 6),
+        (73, 7),
+        (84, 8),
+        (93, 8),
+        (97, 9),
+        (97, 9),
+        (101, 10),
+        (110, 10),
+        (108, 10),
+        (109, 12),
+        (109, 12),
+        (106, 14),
+        (104, 16),
+    ];
+
+    #[test]
+    fn populations_are_pinned() {
+        let mut wator = SmallWator::new(3, 10, 4, 3, XorShift(7));
+        one_step_a_second(&mut wator);
+        let mut populations = [wator.populations(); PINNED.len()];
+        for p in populations[1..].iter_mut() {
+            wator.update(1_000_000);
+            *p = wator.populations();
+        }
+        assert_eq!(populations, PINNED);
+    }
+
+    #[test]
+    fn sharks_are_put_back_when_they_die_out() {
+        let mut wator = SmallWator::new(3, 10, 4, 3, XorShift(5));
+        wator.run_state_update(WatorUpdate::SetSharkDensity(0.0));
+        wator.run_state_update(WatorUpdate::SetFishDensity(1.0));
+        let cells = (16 * wator.rows()) as u16;
+        assert_eq!(wator.populations(), (cells, 0));
+        wator.step();
+        let (fish, sharks) = wator.populations();
+        assert!(
+            (1..=SEED_SHARKS as u16).contains(&sharks),
+            "{sharks} sharks"
+        );
+        assert_eq!(fish + sharks, cells);
+    }
+
+    #[test]
+    fn ocean_is_refilled_when_the_fish_die_out() {
+        // sharks with one energy starve straight away, leaving an empty ocean
+        let mut wator = SmallWator::new(3, 10, 1, 3, XorShift(9));
+        wator.run_state_update(WatorUpdate::SetFishDensity(0.0));
+        wator.run_state_update(WatorUpdate::SetSharkDensity(0.5));
+        let (fish, sharks) = wator.populations();
+        assert_eq!(fish, 0);
+        assert!(sharks > 0);
+        wator.step();
+        // they'd all have starved, so these are a new lot
+        let (fish, sharks) = wator.populations();
+        assert_eq!(fish, 0);
+        assert!(sharks > 0);
+        assert!(
+            wator
+                .cells
+                .buffer()
+                .iter()
+                .all(|cell| !matches!(cell, Cell::Shark { energy, .. } if *energy != 1))
+        );
+    }
+}
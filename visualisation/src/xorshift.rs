@@ -0,0 +1,50 @@
+//! A cheap seeded rng, for the simulations that roll for every cell on every step,
+//! where taking that many numbers from the hardware rng would be slow. The tests use
+//! it too, so they play out the same every time.
+
+use crate::RngU32;
+
+/// Marsaglia's 32 bit xorshift. The seed mustn't be zero.
+pub struct XorShift(pub u32);
+
+impl XorShift {
+    /// Seed from another rng, taking a single number from it
+    pub fn seeded_from(rng: &mut impl RngU32) -> Self {
+        XorShift(rng.next_u32() | 1)
+    }
+}
+
+impl RngU32 for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+/// Counts how many numbers are taken from it, for testing how often the
+/// visualisations go to their rng
+#[cfg(test)]
+pub struct CountingRng {
+    rng: XorShift,
+    pub count: u32,
+}
+
+#[cfg(test)]
+impl CountingRng {
+    pub fn new(seed: u32) -> Self {
+        CountingRng {
+            rng: XorShift(seed),
+            count: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl RngU32 for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        self.count += 1;
+        self.rng.next_u32()
+    }
+}